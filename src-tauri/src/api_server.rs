@@ -0,0 +1,547 @@
+//! Opt-in HTTP API on 127.0.0.1 so local tools (editors, scripts) can translate
+//! through the app. Requests go through `engine::translate`, so the cache, usage
+//! totals and error history are the same as for the app windows.
+//!
+//! Every request needs `Authorization: Bearer <token>` (the token in settings):
+//! - `POST /translate` `{"text", "model"?, "stream"?, "allow_sensitive"?}`: JSON result,
+//!   or SSE events (`chunk`, `replace`, `done`, `error`) with `"stream": true`
+//! - `GET /models`: models that can be passed as `model`
+//! - `GET /usage`: usage totals
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::{Ipv4Addr, SocketAddr, TcpListener as StdTcpListener};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::channel::{mpsc, oneshot};
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited, StreamBody};
+use hyper::body::{Bytes, Frame, Incoming};
+use hyper::header;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use crate::engine::{
+    self, CollectingSink, Storage, TextUpdate, TranslateContext, TranslateOptions,
+    TranslationResult,
+};
+use crate::error::TranslateError;
+use crate::secrets;
+use crate::settings::{self, UsageStats, AVAILABLE_MODELS};
+
+/// Larger request bodies are rejected with 413
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Session IDs of API translations ("api-1", "api-2", ...)
+static NEXT_SESSION: AtomicU64 = AtomicU64::new(1);
+
+type Body = BoxBody<Bytes, Infallible>;
+
+/// What the server needs from the app (lib.rs implements it on the Tauri store)
+pub trait Backend: Send + Sync + 'static {
+    /// Expected bearer token (read per request, so a regenerated token applies at once)
+    fn token(&self) -> String;
+    /// Options from settings and the keychain
+    fn options(&self) -> Result<TranslateOptions, TranslateError>;
+    fn storage(&self) -> &dyn Storage;
+    fn usage(&self) -> UsageStats;
+}
+
+/// Running server; dropping it stops accepting connections
+pub struct ApiServer {
+    port: u16,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl ApiServer {
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+/// Bind 127.0.0.1:`port` (0 picks a free port). Returns the server handle and the
+/// future that serves requests, for the caller to spawn on its runtime.
+pub fn bind(
+    port: u16,
+    backend: Arc<dyn Backend>,
+) -> Result<(ApiServer, impl Future<Output = ()>), String> {
+    // WHY: Loopback only. The API spends the user's API key, so other machines
+    // must never reach it, token or not.
+    let listener = StdTcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))
+        .map_err(|e| format!("Failed to start local API on port {}: {}", port, e))?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+
+    let (shutdown, stopped) = oneshot::channel();
+    let server = ApiServer {
+        port,
+        _shutdown: shutdown,
+    };
+    Ok((server, serve(listener, backend, stopped)))
+}
+
+async fn serve(
+    listener: StdTcpListener,
+    backend: Arc<dyn Backend>,
+    mut stopped: oneshot::Receiver<()>,
+) {
+    let listener = match TcpListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("Failed to start local API: {}", e);
+            return;
+        }
+    };
+
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    // e.g. too many open files: back off instead of spinning
+                    log::warn!("Local API accept failed: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            },
+            // Sender dropped with the ApiServer
+            _ = &mut stopped => break,
+        };
+
+        let backend = backend.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |request| handle(backend.clone(), request));
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                log::debug!("Local API connection error: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle(
+    backend: Arc<dyn Backend>,
+    request: Request<Incoming>,
+) -> Result<Response<Body>, Infallible> {
+    if !authorized(&request, &backend.token()) {
+        return Ok(error_response(
+            StatusCode::UNAUTHORIZED,
+            "Unauthorized",
+            "Missing or invalid bearer token",
+        ));
+    }
+
+    let response = match (request.method(), request.uri().path()) {
+        (&Method::POST, "/translate") => translate(backend, request).await,
+        (&Method::GET, "/models") => json_response(StatusCode::OK, models()),
+        (&Method::GET, "/usage") => json_response(StatusCode::OK, json!(backend.usage())),
+        (_, "/translate" | "/models" | "/usage") => error_response(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "MethodNotAllowed",
+            "Method not allowed",
+        ),
+        _ => error_response(StatusCode::NOT_FOUND, "NotFound", "Unknown endpoint"),
+    };
+    Ok(response)
+}
+
+/// WHY: Browsers can't attach an Authorization header to a cross-origin request
+/// without a CORS preflight (which we never answer), so the token also keeps web
+/// pages from using the API through the user's browser.
+fn authorized(request: &Request<Incoming>, token: &str) -> bool {
+    let given = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    // An empty token (never generated) matches nothing
+    match given {
+        Some(given) => !token.is_empty() && constant_time_eq(given.as_bytes(), token.as_bytes()),
+        None => false,
+    }
+}
+
+/// No early exit, so response timing doesn't reveal how much of the token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Debug, Deserialize)]
+struct TranslateRequest {
+    text: String,
+    /// Model id or alias (haiku, sonnet) instead of the app setting
+    model: Option<String>,
+    #[serde(default)]
+    stream: bool,
+    /// Send text that looks like it contains credentials
+    #[serde(default)]
+    allow_sensitive: bool,
+}
+
+async fn translate(backend: Arc<dyn Backend>, request: Request<Incoming>) -> Response<Body> {
+    let body = match Limited::new(request.into_body(), MAX_BODY_BYTES)
+        .collect()
+        .await
+    {
+        Ok(body) => body.to_bytes(),
+        Err(e) if e.is::<LengthLimitError>() => {
+            return error_response(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "PayloadTooLarge",
+                "Request body is larger than 1 MB",
+            )
+        }
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, "BadRequest", &e.to_string()),
+    };
+    let request: TranslateRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            let message = format!("Invalid request body: {}", e);
+            return error_response(StatusCode::BAD_REQUEST, "BadRequest", &message);
+        }
+    };
+    let text = request.text.trim().to_string();
+    if text.is_empty() {
+        return error_response(StatusCode::BAD_REQUEST, "BadRequest", "text is empty");
+    }
+    let model = match request.model.as_deref().map(settings::resolve_model) {
+        Some(Ok(model)) => Some(model),
+        Some(Err(message)) => {
+            return error_response(StatusCode::BAD_REQUEST, "BadRequest", &message)
+        }
+        None => None,
+    };
+
+    if let Err(error) = secrets::check(&[&text], request.allow_sensitive) {
+        return translate_error_response(&error);
+    }
+    let mut options = match backend.options() {
+        Ok(options) => options,
+        Err(error) => return translate_error_response(&error),
+    };
+    if let Some(model) = model {
+        options.model = model;
+    }
+    let session_id = format!("api-{}", NEXT_SESSION.fetch_add(1, Ordering::Relaxed));
+
+    if request.stream {
+        return stream_translation(backend, text, session_id, options);
+    }
+    let sink = CollectingSink::new(|_| {});
+    let ctx = TranslateContext {
+        sink: &sink,
+        storage: backend.storage(),
+        options,
+    };
+    match engine::translate(&ctx, &text, &session_id).await {
+        Ok(translation) => {
+            let result =
+                TranslationResult::new(translation, &ctx.options.model, sink.take_report());
+            json_response(StatusCode::OK, json!(result))
+        }
+        Err(error) => translate_error_response(&error),
+    }
+}
+
+/// Translate in a task that writes SSE events into the response body as they arrive
+fn stream_translation(
+    backend: Arc<dyn Backend>,
+    text: String,
+    session_id: String,
+    options: TranslateOptions,
+) -> Response<Body> {
+    let (events, body) = mpsc::unbounded::<Result<Frame<Bytes>, Infallible>>();
+
+    // WHY: The translation finishes even if the client disconnects, so its result
+    // still reaches the cache and the usage totals.
+    tokio::spawn(async move {
+        let send = |event: &str, data: Value| {
+            let frame = format!("event: {}\ndata: {}\n\n", event, data);
+            let _ = events.unbounded_send(Ok(Frame::data(Bytes::from(frame))));
+        };
+        let sink = CollectingSink::new(|update| match update {
+            TextUpdate::Chunk(text) => send("chunk", json!({ "text": text })),
+            TextUpdate::Replace(text) => send("replace", json!({ "text": text })),
+        });
+        let ctx = TranslateContext {
+            sink: &sink,
+            storage: backend.storage(),
+            options,
+        };
+        match engine::translate(&ctx, &text, &session_id).await {
+            Ok(translation) => {
+                let result =
+                    TranslationResult::new(translation, &ctx.options.model, sink.take_report());
+                send("done", json!(result));
+            }
+            Err(error) => send("error", error_body(&error)),
+        }
+    });
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(BodyExt::boxed(StreamBody::new(body)))
+        .unwrap()
+}
+
+fn models() -> Value {
+    let models: Vec<Value> = AVAILABLE_MODELS
+        .iter()
+        .map(|(id, name)| json!({ "id": id, "name": name }))
+        .collect();
+    json!({ "models": models })
+}
+
+/// HTTP status for an engine error
+fn error_status(error: &TranslateError) -> StatusCode {
+    match error {
+        TranslateError::SensitiveContentBlocked { .. }
+        | TranslateError::PrivateContentBlocked { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        TranslateError::RateLimitExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+        TranslateError::ApiKeyMissing | TranslateError::Overloaded => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        TranslateError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+        // WHY: An invalid Anthropic key is an upstream failure, not a 401 for the
+        // caller (whose bearer token was fine)
+        TranslateError::AuthenticationFailed { .. }
+        | TranslateError::NetworkError { .. }
+        | TranslateError::ApiError { .. }
+        | TranslateError::ParseError { .. }
+        | TranslateError::IncompleteResponse
+        | TranslateError::PlaceholderMismatch { .. } => StatusCode::BAD_GATEWAY,
+        TranslateError::Unknown { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Same shape as `traylingo --json` errors
+fn error_body(error: &TranslateError) -> Value {
+    json!({ "error": error, "message": error.user_message() })
+}
+
+fn translate_error_response(error: &TranslateError) -> Response<Body> {
+    json_response(error_status(error), error_body(error))
+}
+
+/// Errors of the API itself, shaped like engine errors so clients check one field
+fn error_response(status: StatusCode, kind: &str, message: &str) -> Response<Body> {
+    json_response(
+        status,
+        json!({ "error": { "type": kind }, "message": message }),
+    )
+}
+
+fn json_response(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body.to_string())).boxed())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::memory::{self, MemoryStorage};
+    use crate::mock_server::{MockResponse, MockServer};
+
+    const TOKEN: &str = "test-token";
+    const SOURCE: &str = "Please restart the app to apply changes.";
+    const TRANSLATION: &str = "変更を適用するにはアプリを再起動してください。";
+    const SSE_TRANSLATION: &str = include_str!("../tests/fixtures/sse/translation.sse");
+
+    struct TestBackend {
+        endpoint: String,
+        storage: MemoryStorage,
+    }
+
+    impl Backend for TestBackend {
+        fn token(&self) -> String {
+            TOKEN.to_string()
+        }
+
+        fn options(&self) -> Result<TranslateOptions, TranslateError> {
+            Ok(memory::options(&self.endpoint))
+        }
+
+        fn storage(&self) -> &dyn Storage {
+            &self.storage
+        }
+
+        fn usage(&self) -> UsageStats {
+            let usage = self.storage.usage.lock().unwrap();
+            UsageStats {
+                translations: usage.len() as u64,
+                prompt_tokens: usage.iter().map(|u| u64::from(u.prompt_tokens)).sum(),
+                ..Default::default()
+            }
+        }
+    }
+
+    /// API server in front of a mock Messages API; keep the ApiServer alive
+    async fn start(responses: Vec<MockResponse>) -> (ApiServer, MockServer, String) {
+        let upstream = MockServer::start(responses).await;
+        let backend = TestBackend {
+            endpoint: upstream.url().to_string(),
+            storage: MemoryStorage::default(),
+        };
+        let (server, serve) = bind(0, Arc::new(backend)).unwrap();
+        tokio::spawn(serve);
+        let url = format!("http://127.0.0.1:{}", server.port());
+        (server, upstream, url)
+    }
+
+    async fn post(url: &str, body: Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/translate", url))
+            .bearer_auth(TOKEN)
+            .json(&body)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+    }
+
+    #[tokio::test]
+    async fn test_auth_and_routing() {
+        let (_server, _upstream, url) = start(vec![]).await;
+        let client = reqwest::Client::new();
+
+        let response = client.get(format!("{}/models", url)).send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        let response = client
+            .get(format!("{}/models", url))
+            .bearer_auth("wrong")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401);
+
+        let response = client
+            .get(format!("{}/models", url))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(
+            body["models"].as_array().unwrap().len(),
+            AVAILABLE_MODELS.len()
+        );
+
+        let response = client
+            .get(format!("{}/translate", url))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 405);
+        let response = client
+            .get(format!("{}/nope", url))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+    }
+
+    #[tokio::test]
+    async fn test_translate_json_and_usage() {
+        let (_server, _upstream, url) = start(vec![MockResponse::sse(SSE_TRANSLATION, 64)]).await;
+
+        let response = post(&url, json!({ "text": SOURCE })).await;
+        assert_eq!(response.status(), 200);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["translation"], TRANSLATION);
+        assert_eq!(body["cached"], false);
+        assert_eq!(body["usage"]["prompt_tokens"], 183);
+
+        let usage: Value = reqwest::Client::new()
+            .get(format!("{}/usage", url))
+            .bearer_auth(TOKEN)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(usage["translations"], 1);
+        assert_eq!(usage["prompt_tokens"], 183);
+    }
+
+    #[tokio::test]
+    async fn test_translate_stream() {
+        let (_server, _upstream, url) = start(vec![MockResponse::sse(SSE_TRANSLATION, 7)]).await;
+
+        let response = post(&url, json!({ "text": SOURCE, "stream": true })).await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE.as_str()],
+            "text/event-stream"
+        );
+        let body = response.text().await.unwrap();
+
+        let mut streamed = String::new();
+        let mut done = None;
+        for event in body.split("\n\n").filter(|event| !event.is_empty()) {
+            let (name, data) = event.split_once('\n').unwrap();
+            let data: Value = serde_json::from_str(data.strip_prefix("data: ").unwrap()).unwrap();
+            match name {
+                "event: chunk" => streamed.push_str(data["text"].as_str().unwrap()),
+                "event: done" => done = Some(data),
+                other => panic!("unexpected event: {}", other),
+            }
+        }
+        assert_eq!(streamed, TRANSLATION);
+        assert_eq!(done.unwrap()["translation"], TRANSLATION);
+    }
+
+    #[tokio::test]
+    async fn test_translate_errors() {
+        let (_server, upstream, url) = start(vec![]).await;
+
+        let response = post(&url, json!({ "text": "password=hunter22" })).await;
+        assert_eq!(response.status(), 422);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["type"], "SensitiveContentBlocked");
+
+        let response = post(&url, json!({ "text": SOURCE, "model": "gpt-4" })).await;
+        assert_eq!(response.status(), 400);
+        let response = post(&url, json!({ "txt": SOURCE })).await;
+        assert_eq!(response.status(), 400);
+        let response = post(&url, json!({ "text": "a".repeat(MAX_BODY_BYTES) })).await;
+        assert_eq!(response.status(), 413);
+
+        // None of these reached the Messages API
+        assert!(upstream.requests().is_empty());
+    }
+
+    #[test]
+    fn test_error_status() {
+        assert_eq!(
+            error_status(&TranslateError::Overloaded),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            error_status(&TranslateError::IncompleteResponse),
+            StatusCode::BAD_GATEWAY
+        );
+    }
+}
@@ -0,0 +1,885 @@
+//! Bulk translation through the Message Batches API: half the price, results
+//! within 24 hours. Jobs stay in the store until their results have been written
+//! to the translation cache; lib.rs polls them in the background.
+
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{error, info, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::anthropic::{self, Usage};
+use crate::cache;
+use crate::engine::{Storage, TranslateOptions, UsagePayload};
+use crate::error::TranslateError;
+use crate::markdown;
+use crate::placeholders;
+use crate::redaction::Redactor;
+use crate::secrets;
+use crate::settings::{self, OutputValidation, SettingsStore};
+use crate::validation;
+
+/// Batch requests are billed at half the standard per-token price
+pub const BATCH_DISCOUNT: f64 = 0.5;
+
+/// How often lib.rs checks unfinished jobs
+pub const POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+const JOBS_KEY: &str = "batch_jobs";
+/// Session id recorded in the usage totals for batch results
+const BATCH_SESSION_ID: &str = "batch";
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+/// Results of a large batch are one download
+const RESULTS_TIMEOUT_SECS: u64 = 300;
+/// Jobs keep their texts in settings.json until the results are imported
+const MAX_BATCH_TEXTS: usize = 10_000;
+
+/// `processing_status` of a batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    InProgress,
+    Canceling,
+    Ended,
+}
+
+/// Requests per state, as reported by the API
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestCounts {
+    #[serde(default)]
+    pub processing: u32,
+    #[serde(default)]
+    pub succeeded: u32,
+    #[serde(default)]
+    pub errored: u32,
+    #[serde(default)]
+    pub canceled: u32,
+    #[serde(default)]
+    pub expired: u32,
+}
+
+/// What happened to a job's results once it ended
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportSummary {
+    /// Written to the translation cache
+    pub cached: usize,
+    /// Failed output validation (not cached)
+    pub flagged: usize,
+    /// Errored, canceled or expired requests, and translations that lost placeholders
+    pub failed: usize,
+    /// At batch rates
+    pub estimated_cost: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchJob {
+    /// Batch id from the API (`msgbatch_...`)
+    pub id: String,
+    pub model: String,
+    /// Unix seconds
+    pub created_at: u64,
+    pub status: BatchStatus,
+    pub counts: RequestCounts,
+    pub total: usize,
+    /// Source texts; request `t{n}` is `texts[n]`. Dropped once the results are imported.
+    /// Only read from stores written in plain text by older versions.
+    #[serde(default, skip_serializing)]
+    pub texts: Vec<String>,
+    /// `texts` sealed with the cache key, as stored. Kept sealed in memory only while
+    /// the key can't be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sealed_texts: Option<String>,
+    /// Set once the results are imported
+    #[serde(default)]
+    pub result: Option<ImportSummary>,
+}
+
+impl BatchJob {
+    pub fn is_finished(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// The job as shown to the frontend (without its source texts)
+    fn summary(&self) -> Self {
+        Self {
+            texts: Vec::new(),
+            sealed_texts: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// A text that was not sent, by its position in the submitted list
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkippedText {
+    pub index: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Submission {
+    /// None when nothing was left to send
+    pub job: Option<BatchJob>,
+    /// Already in the translation cache (not sent)
+    pub already_cached: usize,
+    pub skipped: Vec<SkippedText>,
+}
+
+// Message Batches API responses
+#[derive(Deserialize)]
+struct BatchResponse {
+    id: String,
+    processing_status: BatchStatus,
+    #[serde(default)]
+    request_counts: RequestCounts,
+    #[serde(default)]
+    results_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResultLine {
+    custom_id: String,
+    result: ItemResult,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ItemResult {
+    Succeeded {
+        message: Value,
+    },
+    Errored {
+        #[serde(default)]
+        error: Value,
+    },
+    Canceled,
+    Expired,
+}
+
+/// Submit `texts` as one batch. Blank and duplicate texts are dropped, cached ones
+/// counted, and texts that can't be batched are reported in `skipped`.
+pub async fn submit(
+    store: &dyn SettingsStore,
+    storage: &dyn Storage,
+    options: &TranslateOptions,
+    texts: &[String],
+) -> Result<Submission, TranslateError> {
+    if options.api_key.is_empty() {
+        return Err(TranslateError::ApiKeyMissing);
+    }
+    if !settings::is_cache_enabled(store) {
+        return Err(TranslateError::Unknown {
+            message: "Batch results go to the translation cache. Enable it in Settings first."
+                .to_string(),
+        });
+    }
+
+    let mut seen = HashSet::new();
+    let mut queued = Vec::new();
+    let mut already_cached = 0;
+    let mut skipped = Vec::new();
+    for (index, text) in texts.iter().enumerate() {
+        let text = text.trim();
+        if text.is_empty() || !seen.insert(text) {
+            continue;
+        }
+        if storage
+            .cached_translation(text, &anthropic::fingerprint(&options.model, text))
+            .is_some()
+        {
+            already_cached += 1;
+        } else if let Some(reason) = skip_reason(text, options) {
+            skipped.push(SkippedText { index, reason });
+        } else {
+            queued.push(text.to_string());
+        }
+    }
+    // WHY: Results past the cache size would evict each other before anyone reads them
+    let limit = cache::capacity(&settings::get_settings(store)).min(MAX_BATCH_TEXTS);
+    if queued.len() > limit {
+        return Err(TranslateError::Unknown {
+            message: format!(
+                "Too many texts for one batch ({}, max {})",
+                queued.len(),
+                limit
+            ),
+        });
+    }
+    if queued.is_empty() {
+        return Ok(Submission {
+            job: None,
+            already_cached,
+            skipped,
+        });
+    }
+
+    let requests: Vec<Value> = queued
+        .iter()
+        .enumerate()
+        .map(|(index, text)| {
+            let protected = placeholders::protect(text);
+            json!({
+                "custom_id": custom_id(index),
+                "params": anthropic::batch_params(
+                    &options.model,
+                    anthropic::wrap_text_to_translate(&protected.text),
+                ),
+            })
+        })
+        .collect();
+    let client = client(REQUEST_TIMEOUT_SECS)?;
+    let request = client
+        .post(batches_url(&options.endpoint))
+        .json(&json!({ "requests": requests }));
+    let batch: BatchResponse = parse(send(request, &options.api_key).await?).await?;
+
+    let job = BatchJob {
+        id: batch.id,
+        model: options.model.clone(),
+        created_at: now(),
+        status: batch.processing_status,
+        counts: batch.request_counts,
+        total: queued.len(),
+        texts: queued,
+        sealed_texts: None,
+        result: None,
+    };
+    // The batch runs (and is billed) either way; without the job its results are lost
+    if let Err(e) = update_job(store, &job) {
+        error!("Failed to save batch job {}: {}", job.id, e);
+        return Err(TranslateError::Unknown { message: e });
+    }
+    info!("Submitted batch {} ({} texts)", job.id, job.total);
+    Ok(Submission {
+        job: Some(job.summary()),
+        already_cached,
+        skipped,
+    })
+}
+
+/// Why a text can't go into a batch (None: it can)
+fn skip_reason(text: &str, options: &TranslateOptions) -> Option<String> {
+    // WHY: Queued texts sit in settings.json (sealed) until the batch ends, and neither
+    // credentials nor redacted translations are ever cached, so batching them gains nothing
+    if secrets::contains_secrets(text) {
+        return Some("Looks like it contains credentials".to_string());
+    }
+    if let Some(patterns) = &options.redaction_patterns {
+        let mut redactor = Redactor::new(patterns);
+        redactor.redact(text);
+        if !redactor.is_empty() {
+            return Some("Contains personal data (privacy mode)".to_string());
+        }
+    }
+    // Markdown is translated block by block and not cached as a whole
+    if markdown::looks_like_markdown(text) {
+        return Some("Markdown documents are only translated interactively".to_string());
+    }
+    None
+}
+
+/// Jobs with results still to import
+pub fn has_pending(store: &dyn SettingsStore) -> bool {
+    load_jobs(store).iter().any(|job| !job.is_finished())
+}
+
+/// All jobs, newest first (without their source texts)
+pub fn list_jobs(store: &dyn SettingsStore) -> Vec<BatchJob> {
+    let mut jobs: Vec<BatchJob> = load_jobs(store).iter().map(BatchJob::summary).collect();
+    jobs.sort_by_key(|job| std::cmp::Reverse(job.created_at));
+    jobs
+}
+
+/// Give up on the results of unfinished jobs and drop their texts (with
+/// "New key & wipe", which leaves the sealed texts unreadable)
+pub fn discard_texts(store: &dyn SettingsStore) -> Result<(), String> {
+    let jobs: Vec<BatchJob> = load_jobs(store)
+        .into_iter()
+        .map(|mut job| {
+            if !job.is_finished() {
+                job.result = Some(ImportSummary {
+                    failed: job.total,
+                    ..ImportSummary::default()
+                });
+            }
+            job.texts = Vec::new();
+            job.sealed_texts = None;
+            job
+        })
+        .collect();
+    save_jobs(store, &jobs)
+}
+
+/// Forget jobs whose results have been imported
+pub fn clear_finished(store: &dyn SettingsStore) -> Result<(), String> {
+    let jobs: Vec<BatchJob> = load_jobs(store)
+        .into_iter()
+        .filter(|job| !job.is_finished())
+        .collect();
+    save_jobs(store, &jobs)
+}
+
+/// Check every unfinished job once and import the results of those that ended.
+/// Returns the jobs that changed (without their source texts).
+pub async fn poll(
+    store: &dyn SettingsStore,
+    storage: &dyn Storage,
+    options: &TranslateOptions,
+) -> Vec<BatchJob> {
+    let mut changed = Vec::new();
+    for job in load_jobs(store)
+        .into_iter()
+        .filter(|job| !job.is_finished())
+    {
+        let id = job.id.clone();
+        // Results can't be matched to their texts until the key can be read again
+        if job.sealed_texts.is_some() {
+            warn!("Batch {} waits for the cache key", id);
+            continue;
+        }
+        match refresh(store, storage, options, job).await {
+            Ok(Some(job)) => changed.push(job.summary()),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to check batch {}: {}", id, e),
+        }
+    }
+    changed
+}
+
+/// Update one job from the API; None when nothing changed
+async fn refresh(
+    store: &dyn SettingsStore,
+    storage: &dyn Storage,
+    options: &TranslateOptions,
+    mut job: BatchJob,
+) -> Result<Option<BatchJob>, TranslateError> {
+    let url = format!("{}/{}", batches_url(&options.endpoint), job.id);
+    let request = client(REQUEST_TIMEOUT_SECS)?.get(url);
+    let batch: BatchResponse = parse(send(request, &options.api_key).await?).await?;
+    // An ended job is unfinished only until its results are imported (or given up on)
+    if batch.processing_status == job.status
+        && batch.request_counts == job.counts
+        && job.status != BatchStatus::Ended
+    {
+        return Ok(None);
+    }
+    job.status = batch.processing_status;
+    job.counts = batch.request_counts;
+
+    if job.status == BatchStatus::Ended {
+        let summary = match batch.results_url {
+            Some(results_url) => {
+                let request = client(RESULTS_TIMEOUT_SECS)?.get(results_url);
+                let results = send(request, &options.api_key).await?.text().await?;
+                import_results(storage, options.output_validation, &job, &results)
+            }
+            // WHY: Without a results URL there is nothing left to wait for; polling it
+            // again would keep the job (and its queued texts) around forever.
+            None => {
+                warn!("Batch {} ended without results", job.id);
+                ImportSummary {
+                    failed: job.texts.len(),
+                    ..ImportSummary::default()
+                }
+            }
+        };
+        info!("Imported batch {}: {:?}", job.id, summary);
+        job.result = Some(summary);
+        // Queued plaintext isn't needed once the results are in the cache
+        job.texts = Vec::new();
+    }
+    update_job(store, &job).map_err(|message| TranslateError::Unknown { message })?;
+    Ok(Some(job))
+}
+
+/// Ask the API to stop a job. Requests already processed are still imported when it ends.
+pub async fn cancel(
+    store: &dyn SettingsStore,
+    options: &TranslateOptions,
+    id: &str,
+) -> Result<BatchJob, TranslateError> {
+    let mut job = load_jobs(store)
+        .into_iter()
+        .find(|job| job.id == id && !job.is_finished())
+        .ok_or_else(|| TranslateError::Unknown {
+            message: format!("No unfinished batch job {}", id),
+        })?;
+    let url = format!("{}/{}/cancel", batches_url(&options.endpoint), id);
+    let request = client(REQUEST_TIMEOUT_SECS)?.post(url);
+    let batch: BatchResponse = parse(send(request, &options.api_key).await?).await?;
+    job.status = batch.processing_status;
+    job.counts = batch.request_counts;
+    update_job(store, &job).map_err(|message| TranslateError::Unknown { message })?;
+    Ok(job.summary())
+}
+
+/// Write successful results to the cache and add their usage (at batch rates)
+/// to the totals
+fn import_results(
+    storage: &dyn Storage,
+    output_validation: OutputValidation,
+    job: &BatchJob,
+    results: &str,
+) -> ImportSummary {
+    let mut summary = ImportSummary::default();
+    let mut imported = 0;
+    for line in results.lines().filter(|line| !line.trim().is_empty()) {
+        let line: ResultLine = match serde_json::from_str(line) {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("Unreadable batch result line: {}", e);
+                continue;
+            }
+        };
+        let Some(source) = source_text(&job.texts, &line.custom_id) else {
+            warn!("Unknown batch request id: {}", line.custom_id);
+            continue;
+        };
+        imported += 1;
+
+        let message = match line.result {
+            ItemResult::Succeeded { message } => message,
+            ItemResult::Errored { error } => {
+                warn!(
+                    "Batch request {} failed: {}",
+                    line.custom_id, error["error"]["message"]
+                );
+                summary.failed += 1;
+                continue;
+            }
+            ItemResult::Canceled | ItemResult::Expired => {
+                summary.failed += 1;
+                continue;
+            }
+        };
+        let (text, usage) = match anthropic::batch_message(message) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                warn!("Batch request {}: {}", line.custom_id, e);
+                summary.failed += 1;
+                continue;
+            }
+        };
+        if let Some(usage) = &usage {
+            let payload = usage_payload(usage, &job.model);
+            summary.estimated_cost += payload.estimated_cost;
+            storage.record_usage(&payload);
+        }
+
+        // Same placeholder map as at submission: protect() is deterministic
+        let translation = match placeholders::protect(source).restore(&text) {
+            Ok(translation) => translation,
+            Err(e) => {
+                warn!("Batch request {}: {}", line.custom_id, e);
+                summary.failed += 1;
+                continue;
+            }
+        };
+        // Never cache flagged output (no retry here; translate the text interactively)
+        if output_validation != OutputValidation::Off
+            && !validation::check_output(source, &translation).is_empty()
+        {
+            summary.flagged += 1;
+            continue;
+        }
+        match storage.save_translation(
+            source,
+            &translation,
+            &anthropic::fingerprint(&job.model, source),
+        ) {
+            Ok(()) => summary.cached += 1,
+            Err(e) => {
+                warn!("Failed to cache batch result: {}", e);
+                summary.failed += 1;
+            }
+        }
+    }
+    // Requests missing from the results
+    summary.failed += job.texts.len().saturating_sub(imported);
+    summary
+}
+
+fn usage_payload(usage: &Usage, model: &str) -> UsagePayload {
+    UsagePayload {
+        session_id: BATCH_SESSION_ID.to_string(),
+        model: Some(model.to_string()),
+        prompt_tokens: usage.input_tokens,
+        completion_tokens: usage.output_tokens,
+        estimated_cost: anthropic::calculate_cost(usage.input_tokens, usage.output_tokens, model)
+            * BATCH_DISCOUNT,
+        cached: false,
+    }
+}
+
+fn custom_id(index: usize) -> String {
+    format!("t{}", index)
+}
+
+fn source_text<'a>(texts: &'a [String], custom_id: &str) -> Option<&'a str> {
+    let index: usize = custom_id.strip_prefix('t')?.parse().ok()?;
+    texts.get(index).map(String::as_str)
+}
+
+// ==================== Store ====================
+
+fn load_jobs(store: &dyn SettingsStore) -> Vec<BatchJob> {
+    let mut jobs: Vec<BatchJob> = store
+        .get(JOBS_KEY)
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default();
+    let legacy = jobs.iter().any(|job| !job.texts.is_empty());
+    for job in &mut jobs {
+        let Some(sealed) = &job.sealed_texts else {
+            continue;
+        };
+        match cache::unseal(store, &texts_aad(&job.id), sealed) {
+            Ok(texts) => {
+                if texts.is_none() {
+                    warn!("Texts of batch {} can't be decrypted", job.id);
+                }
+                job.texts = texts.unwrap_or_default();
+                job.sealed_texts = None;
+            }
+            Err(e) => warn!("Texts of batch {} unavailable: {}", job.id, e),
+        }
+    }
+    if legacy {
+        if let Err(e) = save_jobs(store, &jobs) {
+            warn!("Failed to encrypt queued batch texts: {}", e);
+        }
+    }
+    jobs
+}
+
+// WHY: Queued texts are whole documents, so settings.json only holds them sealed
+// with the cache key (bound to the job id)
+fn save_jobs(store: &dyn SettingsStore, jobs: &[BatchJob]) -> Result<(), String> {
+    let mut stored = Vec::with_capacity(jobs.len());
+    for job in jobs {
+        let mut job = job.clone();
+        if !job.texts.is_empty() {
+            job.sealed_texts = Some(cache::seal(store, &texts_aad(&job.id), &job.texts)?);
+        }
+        stored.push(job);
+    }
+    store.set(
+        JOBS_KEY,
+        serde_json::to_value(stored).map_err(|e| e.to_string())?,
+    );
+    store.save()
+}
+
+fn texts_aad(id: &str) -> String {
+    format!("{}/{}", JOBS_KEY, id)
+}
+
+/// Insert or replace a job by id.
+// WHY: Re-reads the list instead of writing back a copy taken before an API call,
+// so a job submitted while another was being polled isn't lost.
+fn update_job(store: &dyn SettingsStore, job: &BatchJob) -> Result<(), String> {
+    let mut jobs = load_jobs(store);
+    match jobs.iter_mut().find(|existing| existing.id == job.id) {
+        Some(existing) => *existing = job.clone(),
+        None => jobs.push(job.clone()),
+    }
+    save_jobs(store, &jobs)
+}
+
+// ==================== HTTP ====================
+
+/// Message Batches endpoint next to the Messages endpoint (a mock server in tests)
+fn batches_url(messages_url: &str) -> String {
+    format!("{}/batches", messages_url.trim_end_matches('/'))
+}
+
+fn client(timeout_secs: u64) -> Result<Client, TranslateError> {
+    Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .build()
+        .map_err(|e| TranslateError::NetworkError {
+            message: e.to_string(),
+        })
+}
+
+async fn send(
+    request: reqwest::RequestBuilder,
+    api_key: &str,
+) -> Result<reqwest::Response, TranslateError> {
+    let response = request
+        .header("x-api-key", api_key)
+        .header("anthropic-version", "2023-06-01")
+        .send()
+        .await?;
+    anthropic::check_response(response).await
+}
+
+async fn parse(response: reqwest::Response) -> Result<BatchResponse, TranslateError> {
+    response
+        .json()
+        .await
+        .map_err(|e| TranslateError::ParseError {
+            message: e.to_string(),
+        })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::memory::{self, MemoryStorage};
+    use crate::mock_server::{MockResponse, MockServer};
+    use crate::settings::TempStore;
+
+    const SOURCE: &str = "Please restart the app to apply changes.";
+    const TRANSLATION: &str = "変更を適用するにはアプリを再起動してください。";
+
+    fn store(name: &str) -> TempStore {
+        TempStore::new(&format!("batch-{}", name))
+    }
+
+    fn batch_json(status: &str, succeeded: u32, results_url: Option<&str>) -> String {
+        json!({
+            "id": "msgbatch_01",
+            "type": "message_batch",
+            "processing_status": status,
+            "request_counts": {
+                "processing": 1 - succeeded,
+                "succeeded": succeeded,
+                "errored": 0,
+                "canceled": 0,
+                "expired": 0
+            },
+            "results_url": results_url,
+        })
+        .to_string()
+    }
+
+    fn succeeded(custom_id: &str, text: &str) -> String {
+        json!({
+            "custom_id": custom_id,
+            "result": {
+                "type": "succeeded",
+                "message": {
+                    "content": [{ "type": "text", "text": text }],
+                    "usage": { "input_tokens": 1000, "output_tokens": 200 }
+                }
+            }
+        })
+        .to_string()
+    }
+
+    fn job(texts: &[&str]) -> BatchJob {
+        BatchJob {
+            id: "msgbatch_01".to_string(),
+            model: "claude-haiku-4-5-20251001".to_string(),
+            created_at: 0,
+            status: BatchStatus::Ended,
+            counts: RequestCounts::default(),
+            total: texts.len(),
+            texts: texts.iter().map(|text| text.to_string()).collect(),
+            sealed_texts: None,
+            result: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_submit_and_import() {
+        let store = store("submit");
+        let storage = MemoryStorage::default();
+        let cached = "Already translated";
+        storage
+            .save_translation(
+                cached,
+                "翻訳済み",
+                &anthropic::fingerprint("claude-haiku-4-5-20251001", cached),
+            )
+            .unwrap();
+
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            &batch_json("in_progress", 0, None),
+        )])
+        .await;
+        let options = memory::options(server.url());
+        let texts: Vec<String> = [
+            SOURCE,
+            cached,
+            "   ",
+            SOURCE,
+            "key: sk-ant-REDACTED",
+            "# Title\n\n- item\n- item",
+        ]
+        .iter()
+        .map(|text| text.to_string())
+        .collect();
+
+        let submission = submit(&store, &storage, &options, &texts).await.unwrap();
+        assert_eq!(submission.already_cached, 1);
+        let skipped: Vec<usize> = submission.skipped.iter().map(|s| s.index).collect();
+        assert_eq!(skipped, vec![4, 5]);
+        let job = submission.job.unwrap();
+        assert_eq!(job.id, "msgbatch_01");
+        assert_eq!(job.total, 1);
+        assert!(job.texts.is_empty());
+
+        let body = server.requests()[0].body.clone();
+        let requests = body["requests"].as_array().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["custom_id"], "t0");
+        assert!(requests[0]["params"].get("stream").is_none());
+        assert!(requests[0]["params"]["messages"][0]["content"]
+            .as_str()
+            .unwrap()
+            .contains(SOURCE));
+        assert!(has_pending(&store));
+
+        let results = format!("{}\n", succeeded("t0", TRANSLATION));
+        let server = MockServer::start(vec![MockResponse::json(200, &results)]).await;
+        let status = batch_json("ended", 1, Some(server.url()));
+        let status_server = MockServer::start(vec![MockResponse::json(200, &status)]).await;
+        let options = memory::options(status_server.url());
+
+        let changed = poll(&store, &storage, &options).await;
+        assert_eq!(changed.len(), 1);
+        let summary = changed[0].result.clone().unwrap();
+        assert_eq!(summary.cached, 1);
+        assert_eq!(summary.failed, 0);
+        let full_price = anthropic::calculate_cost(1000, 200, &job.model);
+        assert!((summary.estimated_cost - full_price * BATCH_DISCOUNT).abs() < 1e-12);
+
+        assert_eq!(
+            storage
+                .cached_translation(SOURCE, &anthropic::fingerprint(&job.model, SOURCE))
+                .as_deref(),
+            Some(TRANSLATION)
+        );
+        let usage = storage.usage.lock().unwrap().clone();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].session_id, "batch");
+        assert!(!has_pending(&store));
+        assert!(load_jobs(&store)[0].texts.is_empty());
+
+        clear_finished(&store).unwrap();
+        assert!(list_jobs(&store).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_poll_ended_without_results_fails_job() {
+        let store = store("no-results");
+        // Stored as ended by an earlier poll that didn't finish it
+        let mut ended = job(&[SOURCE, "Second text"]);
+        ended.counts.processing = 1;
+        update_job(&store, &ended).unwrap();
+
+        let server =
+            MockServer::start(vec![MockResponse::json(200, &batch_json("ended", 0, None))]).await;
+        let changed = poll(
+            &store,
+            &MemoryStorage::default(),
+            &memory::options(server.url()),
+        )
+        .await;
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].result.as_ref().unwrap().failed, 2);
+        assert!(!has_pending(&store));
+        assert!(load_jobs(&store)[0].texts.is_empty());
+        assert!(!store.get(JOBS_KEY).unwrap().to_string().contains(SOURCE));
+    }
+
+    #[test]
+    fn test_queued_texts_are_sealed() {
+        let store = store("sealed");
+        let mut pending = job(&[SOURCE]);
+        pending.status = BatchStatus::InProgress;
+        update_job(&store, &pending).unwrap();
+        assert!(!store.get(JOBS_KEY).unwrap().to_string().contains(SOURCE));
+        assert_eq!(load_jobs(&store)[0].texts, [SOURCE]);
+
+        // Plain text from older versions is sealed on first read
+        let mut legacy = serde_json::to_value(vec![pending]).unwrap();
+        legacy[0]["texts"] = json!([SOURCE]);
+        legacy[0].as_object_mut().unwrap().remove("sealed_texts");
+        store.set(JOBS_KEY, legacy);
+        assert_eq!(load_jobs(&store)[0].texts, [SOURCE]);
+        assert!(!store.get(JOBS_KEY).unwrap().to_string().contains(SOURCE));
+
+        // "New key & wipe" gives up on the job
+        discard_texts(&store).unwrap();
+        let jobs = load_jobs(&store);
+        assert!(jobs[0].texts.is_empty());
+        assert_eq!(jobs[0].result.as_ref().unwrap().failed, 1);
+        assert!(!has_pending(&store));
+    }
+
+    #[tokio::test]
+    async fn test_poll_without_changes_keeps_job() {
+        let store = store("unchanged");
+        let mut pending = job(&[SOURCE]);
+        pending.status = BatchStatus::InProgress;
+        pending.counts.processing = 1;
+        update_job(&store, &pending).unwrap();
+
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            &batch_json("in_progress", 0, None),
+        )])
+        .await;
+        let changed = poll(
+            &store,
+            &MemoryStorage::default(),
+            &memory::options(server.url()),
+        )
+        .await;
+        assert!(changed.is_empty());
+        assert_eq!(load_jobs(&store)[0].texts, vec![SOURCE.to_string()]);
+    }
+
+    #[test]
+    fn test_import_results_failures() {
+        let storage = MemoryStorage::default();
+        let job = job(&[SOURCE, "Open {count} files", "Hello world", "Missing"]);
+        let results = [
+            succeeded("t0", TRANSLATION),
+            // Placeholder dropped by the model
+            succeeded("t1", "ファイルを開く"),
+            json!({
+                "custom_id": "t2",
+                "result": {
+                    "type": "errored",
+                    "error": { "type": "error", "error": { "type": "overloaded_error", "message": "Overloaded" } }
+                }
+            })
+            .to_string(),
+            json!({ "custom_id": "t9", "result": { "type": "expired" } }).to_string(),
+        ]
+        .join("\n");
+
+        let summary = import_results(&storage, OutputValidation::Off, &job, &results);
+        assert_eq!(summary.cached, 1);
+        assert_eq!(summary.flagged, 0);
+        // t1 (placeholder), t2 (errored), t3 (missing); t9 is unknown
+        assert_eq!(summary.failed, 3);
+        assert_eq!(storage.usage.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_import_results_flags_untranslated_output() {
+        let storage = MemoryStorage::default();
+        let job = job(&[SOURCE]);
+        let results = succeeded("t0", SOURCE);
+
+        let summary = import_results(&storage, OutputValidation::Flag, &job, &results);
+        assert_eq!(summary.flagged, 1);
+        assert!(storage
+            .cached_translation(SOURCE, &anthropic::fingerprint(&job.model, SOURCE))
+            .is_none());
+    }
+}
@@ -0,0 +1,6 @@
+// Command-line translator sharing the app's keychain entry, settings and cache.
+// Install it on PATH as `traylingo` (e.g. `ln -s .../traylingo-cli ~/.local/bin/traylingo`).
+
+fn main() -> std::process::ExitCode {
+    traylingo_lib::run_cli()
+}
@@ -0,0 +1,7 @@
+// MCP (Model Context Protocol) server on stdio for AI agents, sharing the app's
+// keychain entry, settings and cache. Register it in the agent's MCP config as a
+// stdio server with the path to this binary.
+
+fn main() -> std::process::ExitCode {
+    traylingo_lib::run_mcp()
+}
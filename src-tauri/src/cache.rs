@@ -0,0 +1,1634 @@
+//! Translation and dictionary cache. Kept out of settings.json in an append-only
+//! log (`translation_cache.log`) with an in-memory hash index: a lookup reads one
+//! record, a save appends one line. Old versions of entries, evictions and hits
+//! pile up as dead records until the log is compacted.
+//!
+//! Entries are sealed with AES-256-GCM under a random data key kept in the
+//! Keychain, so the file holds only hashed keys, model names and ciphertext.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+#[cfg(not(test))]
+use crate::keychain;
+use crate::secrets;
+use crate::settings::{self, Settings, SettingsStore, EMAIL_REGEX, LONG_NUMBER_REGEX, URL_REGEX};
+use crate::validation::Language;
+
+pub const DEFAULT_CAPACITY: usize = 5_000;
+pub const MIN_CAPACITY: usize = 100;
+pub const MAX_CAPACITY: usize = 100_000;
+
+/// Layout of keys and entries in the log; a log written with an older one is
+/// discarded (format 2 stored entries in plain text, format 3 hashed a glossary field)
+const KEY_FORMAT: u32 = 4;
+/// AES-GCM nonce, stored in front of each ciphertext
+const NONCE_LEN: usize = 12;
+
+const LOG_FILE: &str = "translation_cache.log";
+const STATS_FILE: &str = "translation_cache_stats.json";
+pub const DEFAULT_TTL_DAYS: u32 = 30;
+pub const MAX_TTL_DAYS: u32 = 365;
+const DAY_SECS: i64 = 24 * 60 * 60;
+/// Most entries returned per page of the cache browser
+const MAX_PAGE_SIZE: usize = 200;
+const SOURCE_PREVIEW_LENGTH: usize = 30; // Short and masked for privacy
+/// Lookups between writes of hit counts and LRU order
+const FLUSH_EVERY: u32 = 20;
+/// Dead records tolerated before compaction, on top of one per live entry
+const COMPACT_SLACK: usize = 1_000;
+
+/// settings.json keys of the cache before it had its own file (emptied once)
+const LEGACY_KEYS: [&str; 2] = ["translation_cache", "dictionary_cache"];
+const LEGACY_STATS_KEY: &str = "cache_stats";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Namespace {
+    /// Sentence translations
+    Translation,
+    /// Dictionary entries (values are serialized JSON)
+    Dictionary,
+}
+
+/// Cache statistics
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CacheStats {
+    /// Total entries in cache
+    pub entry_count: usize,
+    /// Cache hits (translations served from cache)
+    pub hits: u64,
+    /// Cache misses (new translations)
+    pub misses: u64,
+    /// Share of lookups served from the cache (0–1)
+    pub hit_rate: f64,
+    /// Translation hits × average API cost of their model (USD)
+    pub estimated_savings: f64,
+}
+
+/// Hit/miss totals in STATS_FILE
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Counts {
+    hits: u64,
+    misses: u64,
+    /// Translation hits by model (for the savings estimate)
+    #[serde(default)]
+    model_hits: BTreeMap<String, u64>,
+}
+
+/// One entry as shown in the cache browser
+#[derive(Debug, Serialize)]
+pub struct CacheEntry {
+    /// Record key, for `delete_entry`
+    pub id: String,
+    pub namespace: Namespace,
+    pub model: String,
+    /// Start of the source text, truncated and masked
+    pub preview: String,
+    pub value: String,
+    pub timestamp: i64,
+}
+
+/// Entries of one page, most recently used first
+#[derive(Debug, Serialize)]
+pub struct CachePage {
+    pub entries: Vec<CacheEntry>,
+    /// Matching entries on all pages
+    pub total: usize,
+}
+
+/// Size and age limits from Settings
+struct Limits {
+    capacity: usize,
+    ttl_secs: i64,
+    /// Entries kept per model (0: never cached)
+    per_model: BTreeMap<String, usize>,
+}
+
+impl Limits {
+    fn new(settings: &Settings) -> Self {
+        Self {
+            capacity: capacity(settings),
+            ttl_secs: ttl_secs(settings),
+            per_model: settings.cache_model_limits.clone(),
+        }
+    }
+}
+
+/// First line of the log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Header {
+    /// A new generation means the file was rewritten
+    generation: u64,
+    /// Missing in logs keyed by text hash and model only
+    #[serde(default = "first_key_format")]
+    key_format: u32,
+    /// Id of the data key the entries are sealed with
+    #[serde(default)]
+    key_id: String,
+}
+
+/// One line of the log
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Record {
+    Header(Header),
+    Put {
+        key: String,
+        namespace: Namespace,
+        model: String,
+        timestamp: i64,
+        /// Base64 of the nonce and the AES-256-GCM ciphertext of [`Contents`]
+        sealed: String,
+    },
+    /// Cache hit (moves the entry to the back of the LRU order)
+    Touch {
+        key: String,
+    },
+    Delete {
+        key: String,
+    },
+}
+
+/// The encrypted part of an entry
+#[derive(Debug, Serialize, Deserialize)]
+struct Contents {
+    /// Start of the source text, truncated and masked
+    preview: String,
+    value: String,
+}
+
+/// AES-256-GCM key the entries are sealed with
+struct DataKey {
+    /// Short hash of the key, recorded in the log header
+    id: String,
+    cipher: Aes256Gcm,
+}
+
+impl DataKey {
+    /// From its base64 form in the key store
+    fn decode(encoded: &str) -> Option<Self> {
+        let encoded = encoded.trim();
+        let bytes = BASE64.decode(encoded).ok()?;
+        if bytes.len() != 32 {
+            return None;
+        }
+        Some(Self {
+            id: hash_text(encoded)[..16].to_string(),
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&bytes)),
+        })
+    }
+
+    /// A random key and its base64 form
+    fn generate() -> io::Result<(Self, String)> {
+        let mut bytes = [0u8; 32];
+        getrandom::getrandom(&mut bytes).map_err(|e| io::Error::other(e.to_string()))?;
+        let encoded = BASE64.encode(bytes);
+        let key = Self::decode(&encoded).ok_or_else(|| io::Error::other("Invalid cache key"))?;
+        Ok((key, encoded))
+    }
+
+    /// Encrypt, bound to the record key so a sealed value can't be moved to another entry
+    fn seal<T: Serialize>(&self, aad: &str, value: &T) -> io::Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).map_err(|e| io::Error::other(e.to_string()))?;
+        let plain = serde_json::to_vec(value)?;
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &plain,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .map_err(|_| io::Error::other("Failed to encrypt cache entry"))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(BASE64.encode(sealed))
+    }
+
+    /// None when the entry is corrupt or was sealed with another key
+    fn open<T: DeserializeOwned>(&self, aad: &str, sealed: &str) -> Option<T> {
+        let bytes = BASE64.decode(sealed).ok()?;
+        if bytes.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plain = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: aad.as_bytes(),
+                },
+            )
+            .ok()?;
+        serde_json::from_slice(&plain).ok()
+    }
+}
+
+/// Where the data key is kept (base64)
+trait KeyStore: Send + Sync {
+    /// Ok(None) only when no key has been saved yet
+    fn load(&self) -> Result<Option<String>, String>;
+    fn save(&self, key: &str) -> Result<(), String>;
+}
+
+/// The login Keychain, shared by the app, CLI and MCP server
+#[cfg(not(test))]
+struct KeychainKeys;
+
+#[cfg(not(test))]
+impl KeyStore for KeychainKeys {
+    fn load(&self) -> Result<Option<String>, String> {
+        keychain::get_cache_key()
+    }
+
+    fn save(&self, key: &str) -> Result<(), String> {
+        keychain::set_cache_key(key)
+    }
+}
+
+#[cfg(not(test))]
+fn key_store(_dir: &Path) -> Box<dyn KeyStore> {
+    Box::new(KeychainKeys)
+}
+
+// WHY: Tests must not read or replace the developer's real cache key
+#[cfg(test)]
+fn key_store(dir: &Path) -> Box<dyn KeyStore> {
+    Box::new(tests::MemoryKeys(dir.to_path_buf()))
+}
+
+/// What besides the text decides a translation. Hashed into the cache key, so a
+/// new prompt or different options never serve an old translation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct Fingerprint {
+    pub model: String,
+    /// SHA-256 of the prompt (its version)
+    pub prompt: String,
+    /// "ja-en", "en-ja", or "auto" when the text is too short to tell
+    pub languages: String,
+    /// Options that change the output, by name (e.g. the translation memory mode)
+    pub options: BTreeMap<String, String>,
+}
+
+impl Fingerprint {
+    pub fn new(model: &str, prompt: &str, text: &str) -> Self {
+        let languages = match Language::detect(text) {
+            Some(source) => format!("{}-{}", source.code(), source.opposite().code()),
+            None => "auto".to_string(),
+        };
+        Self {
+            model: model.to_string(),
+            prompt: hash_text(prompt),
+            languages,
+            options: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_option(mut self, name: &str, value: &str) -> Self {
+        self.options.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Key of `text` translated this way. Fields serialize in declaration order
+    /// and options sorted, so equal fingerprints always hash the same.
+    fn key(&self, namespace: Namespace, text: &str) -> String {
+        let prefix = match namespace {
+            Namespace::Translation => "t",
+            Namespace::Dictionary => "d",
+        };
+        let canonical = serde_json::to_string(&(self, text)).unwrap_or_default();
+        format!("{}:{}", prefix, hash_text(&canonical))
+    }
+}
+
+/// Where a live entry's Put record is in the log
+#[derive(Debug, Clone)]
+struct Slot {
+    offset: u64,
+    /// Without the newline
+    len: usize,
+    namespace: Namespace,
+    model: String,
+    timestamp: i64,
+    /// Position in the LRU order
+    tick: u64,
+}
+
+#[derive(Default)]
+struct Index {
+    generation: u64,
+    /// Bytes of the log already applied
+    applied: u64,
+    /// Lines applied, live or dead (for the compaction check)
+    records: usize,
+    slots: HashMap<String, Slot>,
+    /// Least recently used first
+    lru: BTreeMap<u64, String>,
+    tick: u64,
+    // Not yet written: hits since the last flush and their counts
+    touched: Vec<String>,
+    hits: u64,
+    misses: u64,
+    model_hits: BTreeMap<String, u64>,
+    lookups: u32,
+    /// Loaded on first use
+    key: Option<DataKey>,
+}
+
+impl Index {
+    /// Forget the log (it is replayed again from the start)
+    fn reset(&mut self) {
+        self.generation = 0;
+        self.applied = 0;
+        self.records = 0;
+        self.slots.clear();
+        self.lru.clear();
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn apply(&mut self, record: Record, offset: u64, len: usize) {
+        match record {
+            Record::Header(header) => self.generation = header.generation,
+            Record::Put {
+                key,
+                namespace,
+                model,
+                timestamp,
+                ..
+            } => {
+                self.remove(&key);
+                let tick = self.next_tick();
+                self.lru.insert(tick, key.clone());
+                self.slots.insert(
+                    key,
+                    Slot {
+                        offset,
+                        len,
+                        namespace,
+                        model,
+                        timestamp,
+                        tick,
+                    },
+                );
+            }
+            Record::Touch { key } => self.touch(&key),
+            Record::Delete { key } => self.remove(&key),
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        let tick = self.next_tick();
+        if let Some(slot) = self.slots.get_mut(key) {
+            self.lru.remove(&slot.tick);
+            slot.tick = tick;
+            self.lru.insert(tick, key.to_string());
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(slot) = self.slots.remove(key) {
+            self.lru.remove(&slot.tick);
+        }
+    }
+
+    /// Read the log past `applied`
+    fn replay(&mut self, file: &mut File) -> io::Result<()> {
+        file.seek(SeekFrom::Start(self.applied))?;
+        let mut reader = BufReader::new(file);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            // A line without its newline is still being written by another process
+            if read == 0 || line.last() != Some(&b'\n') {
+                return Ok(());
+            }
+            let offset = self.applied;
+            self.applied += read as u64;
+            self.records += 1;
+            match serde_json::from_slice(&line) {
+                Ok(record) => self.apply(record, offset, read - 1),
+                Err(e) => log::warn!("Skipping unreadable cache record: {}", e),
+            }
+        }
+    }
+}
+
+/// Log-backed cache in one directory
+pub struct Cache {
+    dir: PathBuf,
+    keys: Box<dyn KeyStore>,
+    index: Mutex<Index>,
+}
+
+impl Cache {
+    fn new(dir: PathBuf) -> Self {
+        Self {
+            keys: key_store(&dir),
+            dir,
+            index: Mutex::new(Index::default()),
+        }
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.dir.join(LOG_FILE)
+    }
+
+    fn stats_path(&self) -> PathBuf {
+        self.dir.join(STATS_FILE)
+    }
+
+    fn lock(&self) -> io::Result<MutexGuard<'_, Index>> {
+        self.index
+            .lock()
+            .map_err(|_| io::Error::other("Cache index lock poisoned"))
+    }
+
+    /// The stored data key, or a new one on first use
+    fn load_key(&self) -> io::Result<DataKey> {
+        // WHY: A new key makes every entry unreadable, so only create one when there is
+        // none. A locked Keychain leaves the cache unavailable until it can be read.
+        let stored = self
+            .keys
+            .load()
+            .map_err(|e| io::Error::other(format!("Cache key unavailable: {}", e)))?;
+        if let Some(encoded) = stored {
+            match DataKey::decode(&encoded) {
+                Some(key) => return Ok(key),
+                None => log::warn!("Stored cache key is unreadable; creating a new one"),
+            }
+        }
+        let (key, encoded) = DataKey::generate()?;
+        self.keys.save(&encoded).map_err(io::Error::other)?;
+        Ok(key)
+    }
+
+    fn data_key<'a>(&self, index: &'a mut Index) -> io::Result<&'a DataKey> {
+        if index.key.is_none() {
+            index.key = Some(self.load_key()?);
+        }
+        index
+            .key
+            .as_ref()
+            .ok_or_else(|| io::Error::other("Cache key unavailable"))
+    }
+
+    /// Catch up with the log, which other processes (CLI, MCP server) append to
+    /// and may rewrite
+    fn sync(&self, index: &mut Index) -> io::Result<()> {
+        let key_id = self.data_key(index)?.id.clone();
+        let path = self.log_path();
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                create_log(&path, &key_id)?;
+                File::open(&path)?
+            }
+            Err(e) => return Err(e),
+        };
+        let header = read_header(&mut file)?;
+        match &header {
+            Some(header) if header.key_format < KEY_FORMAT => {
+                // None of its keys can be looked up any more
+                log::info!(
+                    "Discarding translation cache with key format {}",
+                    header.key_format
+                );
+                return self.rewrite(index, None);
+            }
+            Some(header) if header.key_id != key_id => {
+                // Another process may have rotated the key
+                let key = self.load_key()?;
+                let rotated = key.id == header.key_id;
+                index.key = Some(key);
+                if !rotated {
+                    log::warn!("Discarding translation cache sealed with an unknown key");
+                    return self.rewrite(index, None);
+                }
+            }
+            _ => {}
+        }
+        let generation = header.map(|header| header.generation);
+        if generation != Some(index.generation) || file.metadata()?.len() < index.applied {
+            index.reset();
+        }
+        index.replay(&mut file)
+    }
+
+    /// Append records and apply them (through the log, like everyone else's)
+    fn append(&self, index: &mut Index, records: &[Record]) -> io::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        let mut bytes = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut bytes, record)?;
+            bytes.push(b'\n');
+        }
+        // One write, so a concurrent appender can't interleave inside a line
+        OpenOptions::new()
+            .append(true)
+            .open(self.log_path())?
+            .write_all(&bytes)?;
+        self.sync(index)
+    }
+
+    fn get(&self, key: &str, ttl_secs: i64) -> io::Result<Option<String>> {
+        let mut index = self.lock()?;
+        self.sync(&mut index)?;
+        let now = now();
+        let sealed = match index.slots.get(key) {
+            Some(slot) if now - slot.timestamp < ttl_secs => {
+                // A compaction elsewhere may have moved the record: then it's a miss
+                match read_record(&self.log_path(), slot)? {
+                    Record::Put {
+                        key: found, sealed, ..
+                    } if found == key => Some(sealed),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let value = match sealed {
+            Some(sealed) => match self.data_key(&mut index)?.open::<Contents>(key, &sealed) {
+                Some(contents) => Some(contents.value),
+                None => {
+                    log::warn!("Dropping a translation cache entry that can't be decrypted");
+                    self.append(
+                        &mut index,
+                        &[Record::Delete {
+                            key: key.to_string(),
+                        }],
+                    )?;
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if value.is_some() {
+            index.hits += 1;
+            if let Some(slot) = index.slots.get(key) {
+                if slot.namespace == Namespace::Translation {
+                    let model = slot.model.clone();
+                    *index.model_hits.entry(model).or_default() += 1;
+                }
+            }
+            index.touch(key);
+            index.touched.push(key.to_string());
+        } else {
+            index.misses += 1;
+        }
+        index.lookups += 1;
+        if index.lookups >= FLUSH_EVERY {
+            self.flush_index(&mut index)?;
+        }
+        Ok(value)
+    }
+
+    fn put(
+        &self,
+        key: &str,
+        namespace: Namespace,
+        model: &str,
+        contents: &Contents,
+        limits: &Limits,
+    ) -> io::Result<()> {
+        let model_limit = limits.per_model.get(model).copied();
+        if model_limit == Some(0) {
+            return Ok(());
+        }
+        let mut index = self.lock()?;
+        self.sync(&mut index)?;
+        let record = Record::Put {
+            key: key.to_string(),
+            namespace,
+            model: model.to_string(),
+            timestamp: now(),
+            sealed: self.data_key(&mut index)?.seal(key, contents)?,
+        };
+
+        // Least recently used entries make room: first the model's own, then any
+        let older = || index.lru.values().filter(|old| old.as_str() != key);
+        let mut evicted: Vec<&String> = Vec::new();
+        if let Some(limit) = model_limit {
+            let same_model: Vec<&String> = older()
+                .filter(|old| index.slots[*old].model == model)
+                .collect();
+            let excess = (same_model.len() + 1).saturating_sub(limit);
+            evicted.extend(same_model.into_iter().take(excess));
+        }
+        let others = older().count() - evicted.len();
+        let excess = (others + 1).saturating_sub(limits.capacity);
+        let rest: Vec<&String> = older()
+            .filter(|old| !evicted.contains(old))
+            .take(excess)
+            .collect();
+        evicted.extend(rest);
+        let mut records: Vec<Record> = evicted
+            .into_iter()
+            .map(|old| Record::Delete { key: old.clone() })
+            .collect();
+        records.push(record);
+        self.append(&mut index, &records)?;
+
+        if index.records > index.slots.len() * 2 + COMPACT_SLACK {
+            self.rewrite(&mut index, Some(limits.ttl_secs))?;
+        }
+        Ok(())
+    }
+
+    /// Replace the log with only its live entries younger than `keep_ttl_secs`
+    /// (None: no entries), under a new generation so other processes reload.
+    // WHY: Entries another process appends between our read and the rename are
+    // lost; for a cache that only costs a repeated API call.
+    fn rewrite(&self, index: &mut Index, keep_ttl_secs: Option<i64>) -> io::Result<()> {
+        let path = self.log_path();
+        let temp = self.dir.join(format!("{}.tmp", LOG_FILE));
+        let header = new_header(&self.data_key(index)?.id)?;
+        // Rotating the key may come before anything was cached
+        fs::create_dir_all(&self.dir)?;
+        {
+            let mut out = BufWriter::new(File::create(&temp)?);
+            serde_json::to_writer(&mut out, &header)?;
+            out.write_all(b"\n")?;
+            if let Some(ttl_secs) = keep_ttl_secs {
+                let now = now();
+                let mut source = File::open(&path)?;
+                // Oldest first, so replaying rebuilds the same LRU order
+                for key in index.lru.values() {
+                    let slot = &index.slots[key];
+                    if now - slot.timestamp < ttl_secs {
+                        out.write_all(&read_line(&mut source, slot)?)?;
+                        out.write_all(b"\n")?;
+                    }
+                }
+            }
+            out.flush()?;
+        }
+        fs::rename(&temp, &path)?;
+        index.reset();
+        self.sync(index)
+    }
+
+    /// Write pending hits (LRU order) and hit/miss counts
+    fn flush_index(&self, index: &mut Index) -> io::Result<()> {
+        // Last hit of each key, in order
+        let mut seen = HashSet::new();
+        let mut touched: Vec<Record> = std::mem::take(&mut index.touched)
+            .into_iter()
+            .rev()
+            .filter(|key| seen.insert(key.clone()))
+            .map(|key| Record::Touch { key })
+            .collect();
+        touched.reverse();
+        self.append(index, &touched)?;
+
+        if index.hits > 0 || index.misses > 0 {
+            // Added to the file rather than overwriting it, so other processes' counts stay
+            let mut counts = read_counts(&self.stats_path());
+            counts.hits += index.hits;
+            counts.misses += index.misses;
+            for (model, hits) in std::mem::take(&mut index.model_hits) {
+                *counts.model_hits.entry(model).or_default() += hits;
+            }
+            write_atomic(&self.stats_path(), &serde_json::to_vec(&counts)?)?;
+        }
+        index.hits = 0;
+        index.misses = 0;
+        index.model_hits.clear();
+        index.lookups = 0;
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        let mut index = self.lock()?;
+        self.sync(&mut index)?;
+        self.flush_index(&mut index)
+    }
+
+    /// Entry count and hit/miss totals, including those not yet flushed
+    fn stats(&self) -> io::Result<(usize, Counts)> {
+        let mut index = self.lock()?;
+        self.sync(&mut index)?;
+        let mut counts = read_counts(&self.stats_path());
+        counts.hits += index.hits;
+        counts.misses += index.misses;
+        for (model, hits) in &index.model_hits {
+            *counts.model_hits.entry(model.clone()).or_default() += hits;
+        }
+        Ok((index.slots.len(), counts))
+    }
+
+    /// Unexpired entries, most recently used first, whose preview or value
+    /// contains `query` (any case)
+    fn list(
+        &self,
+        query: &str,
+        offset: usize,
+        limit: usize,
+        ttl_secs: i64,
+    ) -> io::Result<CachePage> {
+        let mut index = self.lock()?;
+        self.sync(&mut index)?;
+        self.data_key(&mut index)?;
+        let index = &*index;
+        let data_key = index
+            .key
+            .as_ref()
+            .ok_or_else(|| io::Error::other("Cache key unavailable"))?;
+
+        let query = query.trim().to_lowercase();
+        let now = now();
+        let mut file = File::open(self.log_path())?;
+        let mut page = CachePage {
+            entries: Vec::new(),
+            total: 0,
+        };
+        for key in index.lru.values().rev() {
+            let slot = &index.slots[key];
+            if now - slot.timestamp >= ttl_secs {
+                continue;
+            }
+            let in_page = page.total >= offset && page.entries.len() < limit;
+            // Without a search, entries off the page are only counted
+            if query.is_empty() && !in_page {
+                page.total += 1;
+                continue;
+            }
+            // Undecryptable entries are skipped here and dropped by `get`
+            let Some(contents) = read_line(&mut file, slot)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+                .and_then(|record| match record {
+                    Record::Put {
+                        key: found, sealed, ..
+                    } if &found == key => Some(sealed),
+                    _ => None,
+                })
+                .and_then(|sealed| data_key.open::<Contents>(key, &sealed))
+            else {
+                continue;
+            };
+            if !query.is_empty()
+                && !contents.preview.to_lowercase().contains(&query)
+                && !contents.value.to_lowercase().contains(&query)
+            {
+                continue;
+            }
+            if in_page {
+                page.entries.push(CacheEntry {
+                    id: key.clone(),
+                    namespace: slot.namespace,
+                    model: slot.model.clone(),
+                    preview: contents.preview,
+                    value: contents.value,
+                    timestamp: slot.timestamp,
+                });
+            }
+            page.total += 1;
+        }
+        Ok(page)
+    }
+
+    /// Remove one entry; false if it wasn't cached
+    fn delete(&self, key: &str) -> io::Result<bool> {
+        let mut index = self.lock()?;
+        self.sync(&mut index)?;
+        if !index.slots.contains_key(key) {
+            return Ok(false);
+        }
+        self.append(
+            &mut index,
+            &[Record::Delete {
+                key: key.to_string(),
+            }],
+        )?;
+        Ok(true)
+    }
+
+    fn seal_value<T: Serialize>(&self, aad: &str, value: &T) -> io::Result<String> {
+        let mut index = self.lock()?;
+        self.data_key(&mut index)?.seal(aad, value)
+    }
+
+    fn open_value<T: DeserializeOwned>(&self, aad: &str, sealed: &str) -> io::Result<Option<T>> {
+        let mut index = self.lock()?;
+        Ok(self.data_key(&mut index)?.open(aad, sealed))
+    }
+
+    fn clear(&self) -> io::Result<()> {
+        let mut index = self.lock()?;
+        self.clear_locked(&mut index)
+    }
+
+    /// Switch to a new data key; everything sealed with the old one is wiped
+    fn rotate_key(&self) -> io::Result<()> {
+        let mut index = self.lock()?;
+        let (key, encoded) = DataKey::generate()?;
+        self.keys.save(&encoded).map_err(io::Error::other)?;
+        index.key = Some(key);
+        self.clear_locked(&mut index)
+    }
+
+    fn clear_locked(&self, index: &mut Index) -> io::Result<()> {
+        index.touched.clear();
+        index.hits = 0;
+        index.misses = 0;
+        index.model_hits.clear();
+        index.lookups = 0;
+        match fs::remove_file(self.stats_path()) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        self.rewrite(index, None)
+    }
+
+    /// Drop the cache from settings.json (keyed without a fingerprint, so its
+    /// entries can't be found any more); only the hit/miss counts carry over
+    fn discard_legacy(&self, store: &dyn SettingsStore) -> io::Result<()> {
+        let mut changed = false;
+        for store_key in LEGACY_KEYS {
+            if store
+                .get(store_key)
+                .and_then(|v| v.as_array().map(|entries| !entries.is_empty()))
+                .unwrap_or(false)
+            {
+                store.set(store_key, Value::Array(Vec::new()));
+                changed = true;
+            }
+        }
+        if let Some(legacy) = store
+            .get(LEGACY_STATS_KEY)
+            .and_then(|v| serde_json::from_value::<Counts>(v).ok())
+        {
+            let mut index = self.lock()?;
+            index.hits += legacy.hits;
+            index.misses += legacy.misses;
+            self.flush_index(&mut index)?;
+            store.set(LEGACY_STATS_KEY, Value::Null);
+            changed = true;
+        }
+        if changed {
+            store.save().map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn new_generation() -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    getrandom::getrandom(&mut bytes).map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn new_header(key_id: &str) -> io::Result<Record> {
+    Ok(Record::Header(Header {
+        generation: new_generation()?,
+        key_format: KEY_FORMAT,
+        key_id: key_id.to_string(),
+    }))
+}
+
+/// Start a log with just a header, unless another process just did
+fn create_log(path: &Path, key_id: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_vec(&new_header(key_id)?)?;
+    line.push(b'\n');
+    match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(mut file) => file.write_all(&line),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+fn first_key_format() -> u32 {
+    1
+}
+
+fn read_header(file: &mut File) -> io::Result<Option<Header>> {
+    let mut line = String::new();
+    BufReader::new(&mut *file).read_line(&mut line)?;
+    Ok(match serde_json::from_str(&line) {
+        Ok(Record::Header(header)) => Some(header),
+        _ => None,
+    })
+}
+
+fn read_line(file: &mut File, slot: &Slot) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; slot.len];
+    file.seek(SeekFrom::Start(slot.offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_record(path: &Path, slot: &Slot) -> io::Result<Record> {
+    let bytes = read_line(&mut File::open(path)?, slot)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+fn read_counts(path: &Path) -> Counts {
+    fs::read(path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let temp = path.with_extension("json.tmp");
+    fs::write(&temp, bytes)?;
+    fs::rename(&temp, path)
+}
+
+// ==================== Keys and previews ====================
+
+/// Generate SHA256 hash for cache key
+fn hash_text(text: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(text.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Create a safe preview of text for cache storage.
+/// Truncates to SOURCE_PREVIEW_LENGTH and masks sensitive patterns.
+fn create_safe_preview(text: &str) -> String {
+    let preview: String = text.chars().take(SOURCE_PREVIEW_LENGTH).collect();
+    mask_sensitive_patterns(&preview)
+}
+
+/// Mask sensitive patterns in text (emails, URLs, long numbers)
+fn mask_sensitive_patterns(text: &str) -> String {
+    let text = EMAIL_REGEX.replace_all(text, "[EMAIL]");
+    let text = URL_REGEX.replace_all(&text, "[URL]");
+    let text = LONG_NUMBER_REGEX.replace_all(&text, "[***]");
+    text.to_string()
+}
+
+// ==================== Store ====================
+
+/// One cache per directory, shared by everything in the process
+static CACHES: Lazy<Mutex<HashMap<PathBuf, Arc<Cache>>>> = Lazy::new(Default::default);
+
+/// The cache next to the store's settings.json
+fn open(store: &dyn SettingsStore) -> io::Result<Arc<Cache>> {
+    let dir = store
+        .data_dir()
+        .ok_or_else(|| io::Error::other("Could not locate the data directory"))?;
+    let mut caches = CACHES
+        .lock()
+        .map_err(|_| io::Error::other("Cache registry lock poisoned"))?;
+    if let Some(cache) = caches.get(&dir) {
+        return Ok(cache.clone());
+    }
+    let cache = Arc::new(Cache::new(dir.clone()));
+    if let Err(e) = cache.discard_legacy(store) {
+        log::warn!("Failed to remove the old translation cache: {}", e);
+    }
+    caches.insert(dir, cache.clone());
+    Ok(cache)
+}
+
+/// Entries kept before the least recently used are evicted
+pub fn capacity(settings: &Settings) -> usize {
+    settings.cache_capacity.clamp(MIN_CAPACITY, MAX_CAPACITY)
+}
+
+/// Age after which an entry is no longer served
+fn ttl_secs(settings: &Settings) -> i64 {
+    i64::from(settings.cache_ttl_days.clamp(1, MAX_TTL_DAYS)) * DAY_SECS
+}
+
+fn get_entry(
+    store: &dyn SettingsStore,
+    namespace: Namespace,
+    text: &str,
+    fingerprint: &Fingerprint,
+) -> Option<String> {
+    let settings = settings::get_settings(store);
+    if !settings.cache_enabled {
+        return None;
+    }
+    let key = fingerprint.key(namespace, text);
+    match open(store).and_then(|cache| cache.get(&key, ttl_secs(&settings))) {
+        Ok(value) => value,
+        Err(e) => {
+            log::warn!("Cache lookup failed: {}", e);
+            None
+        }
+    }
+}
+
+fn save_entry(
+    store: &dyn SettingsStore,
+    namespace: Namespace,
+    text: &str,
+    value: &str,
+    fingerprint: &Fingerprint,
+) -> Result<(), String> {
+    let settings = settings::get_settings(store);
+    // Confirmed sends of credential-like text are never persisted
+    if !settings.cache_enabled || secrets::contains_secrets(text) {
+        return Ok(());
+    }
+    let contents = Contents {
+        preview: create_safe_preview(text),
+        value: value.to_string(),
+    };
+    open(store)
+        .and_then(|cache| {
+            cache.put(
+                &fingerprint.key(namespace, text),
+                namespace,
+                &fingerprint.model,
+                &contents,
+                &Limits::new(&settings),
+            )
+        })
+        .map_err(|e| format!("Failed to write translation cache: {}", e))
+}
+
+/// Get cached translation if exists (respects cache_enabled setting)
+pub fn get_cached_translation(
+    store: &dyn SettingsStore,
+    text: &str,
+    fingerprint: &Fingerprint,
+) -> Option<String> {
+    get_entry(store, Namespace::Translation, text, fingerprint)
+}
+
+/// Save translation to cache (respects cache_enabled setting, LRU eviction when full)
+pub fn save_cached_translation(
+    store: &dyn SettingsStore,
+    text: &str,
+    translated_text: &str,
+    fingerprint: &Fingerprint,
+) -> Result<(), String> {
+    save_entry(
+        store,
+        Namespace::Translation,
+        text,
+        translated_text,
+        fingerprint,
+    )
+}
+
+/// Get cached dictionary entry JSON if exists (respects cache_enabled setting)
+pub fn get_cached_dictionary_entry(
+    store: &dyn SettingsStore,
+    word: &str,
+    fingerprint: &Fingerprint,
+) -> Option<String> {
+    get_entry(store, Namespace::Dictionary, word, fingerprint)
+}
+
+/// Save dictionary entry JSON to its own cache namespace
+pub fn save_cached_dictionary_entry(
+    store: &dyn SettingsStore,
+    word: &str,
+    entry_json: &str,
+    fingerprint: &Fingerprint,
+) -> Result<(), String> {
+    save_entry(store, Namespace::Dictionary, word, entry_json, fingerprint)
+}
+
+/// Get cache statistics, with savings priced by each model's average usage cost
+pub fn get_cache_stats(store: &dyn SettingsStore) -> CacheStats {
+    let (entry_count, counts) = match open(store).and_then(|cache| cache.stats()) {
+        Ok(stats) => stats,
+        Err(e) => {
+            log::warn!("Failed to read cache stats: {}", e);
+            return CacheStats::default();
+        }
+    };
+    let usage = settings::get_usage_stats(store);
+    let lookups = counts.hits + counts.misses;
+    CacheStats {
+        entry_count,
+        hits: counts.hits,
+        misses: counts.misses,
+        hit_rate: if lookups == 0 {
+            0.0
+        } else {
+            counts.hits as f64 / lookups as f64
+        },
+        estimated_savings: counts
+            .model_hits
+            .iter()
+            .map(|(model, hits)| *hits as f64 * usage.average_cost(model))
+            .sum(),
+    }
+}
+
+/// One page of the cache browser (`query` empty: all entries)
+pub fn list_entries(
+    store: &dyn SettingsStore,
+    query: &str,
+    offset: usize,
+    limit: usize,
+) -> Result<CachePage, String> {
+    let settings = settings::get_settings(store);
+    open(store)
+        .and_then(|cache| {
+            cache.list(
+                query,
+                offset,
+                limit.clamp(1, MAX_PAGE_SIZE),
+                ttl_secs(&settings),
+            )
+        })
+        .map_err(|e| format!("Failed to read translation cache: {}", e))
+}
+
+/// Remove one entry by its id (from `list_entries`)
+pub fn delete_entry(store: &dyn SettingsStore, id: &str) -> Result<bool, String> {
+    open(store)
+        .and_then(|cache| cache.delete(id))
+        .map_err(|e| format!("Failed to delete cache entry: {}", e))
+}
+
+/// Clear translation cache (called from UI)
+pub fn clear(store: &dyn SettingsStore) -> Result<(), String> {
+    open(store)
+        .and_then(|cache| cache.clear())
+        .map_err(|e| format!("Failed to clear translation cache: {}", e))
+}
+
+/// Replace the data key and wipe the cache sealed with the old one (called from UI)
+pub fn rotate_key(store: &dyn SettingsStore) -> Result<(), String> {
+    open(store)
+        .and_then(|cache| cache.rotate_key())
+        .map_err(|e| format!("Failed to reset the cache key: {}", e))
+}
+
+/// Encrypt data kept outside the cache (translation memory, queued batch texts)
+/// with the cache's key, so "New key & wipe" leaves it unreadable too
+pub fn seal<T: Serialize>(
+    store: &dyn SettingsStore,
+    aad: &str,
+    value: &T,
+) -> Result<String, String> {
+    open(store)
+        .and_then(|cache| cache.seal_value(aad, value))
+        .map_err(|e| format!("Failed to encrypt: {}", e))
+}
+
+/// Decrypt what `seal` returned; None when it is corrupt or sealed with an old key.
+/// Errs when the key can't be read (a locked Keychain).
+pub fn unseal<T: DeserializeOwned>(
+    store: &dyn SettingsStore,
+    aad: &str,
+    sealed: &str,
+) -> Result<Option<T>, String> {
+    open(store)
+        .and_then(|cache| cache.open_value(aad, sealed))
+        .map_err(|e| format!("Failed to decrypt: {}", e))
+}
+
+/// Write pending hit counts and LRU order (before the process exits)
+pub fn flush_all() {
+    let Ok(caches) = CACHES.lock() else {
+        return;
+    };
+    for cache in caches.values() {
+        if let Err(e) = cache.flush() {
+            log::warn!("Failed to flush translation cache: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::{Settings, TempStore};
+
+    const TTL: i64 = DEFAULT_TTL_DAYS as i64 * DAY_SECS;
+
+    fn limits(capacity: usize) -> Limits {
+        Limits {
+            capacity,
+            ttl_secs: TTL,
+            per_model: BTreeMap::new(),
+        }
+    }
+
+    static TEST_KEYS: Lazy<Mutex<HashMap<PathBuf, String>>> = Lazy::new(Default::default);
+
+    /// Data directories whose Keychain is "locked"
+    static LOCKED_KEYS: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(Default::default);
+
+    /// One key per data directory, like a Keychain per machine
+    pub(super) struct MemoryKeys(pub(super) PathBuf);
+
+    impl KeyStore for MemoryKeys {
+        fn load(&self) -> Result<Option<String>, String> {
+            if LOCKED_KEYS.lock().unwrap().contains(&self.0) {
+                return Err("User interaction is not allowed.".to_string());
+            }
+            Ok(TEST_KEYS.lock().unwrap().get(&self.0).cloned())
+        }
+
+        fn save(&self, key: &str) -> Result<(), String> {
+            TEST_KEYS
+                .lock()
+                .map_err(|e| e.to_string())?
+                .insert(self.0.clone(), key.to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_create_safe_preview() {
+        // Long text is truncated
+        let long_text = "a".repeat(100);
+        assert_eq!(create_safe_preview(&long_text).len(), SOURCE_PREVIEW_LENGTH);
+
+        // Short text with sensitive data is masked
+        assert!(create_safe_preview("user@example.com").contains("[EMAIL]"));
+    }
+
+    #[test]
+    fn test_mask_sensitive_patterns() {
+        // Email masking
+        assert_eq!(
+            mask_sensitive_patterns("Contact: user@example.com"),
+            "Contact: [EMAIL]"
+        );
+
+        // URL masking
+        assert_eq!(
+            mask_sensitive_patterns("See https://example.com/path"),
+            "See [URL]"
+        );
+
+        // Long number masking (4+ digits)
+        assert_eq!(mask_sensitive_patterns("Card: 1234567890"), "Card: [***]");
+
+        // Short numbers are kept
+        assert_eq!(mask_sensitive_patterns("Code: 123"), "Code: 123");
+
+        // Combined
+        assert_eq!(
+            mask_sensitive_patterns("Email user@test.com or call 12345"),
+            "Email [EMAIL] or call [***]"
+        );
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let fingerprint = Fingerprint::new("m", "prompt", "Please restart the app.");
+        assert_eq!(fingerprint.languages, "en-ja");
+        assert_eq!(Fingerprint::new("m", "prompt", "Hi").languages, "auto");
+
+        let key = fingerprint.key(Namespace::Translation, "Please restart the app.");
+        assert_eq!(
+            key,
+            fingerprint
+                .clone()
+                .key(Namespace::Translation, "Please restart the app.")
+        );
+        assert!(key.starts_with("t:"));
+        assert_ne!(
+            key,
+            fingerprint.key(Namespace::Dictionary, "Please restart the app.")
+        );
+
+        // Any part of the request changes the key
+        let changed = [
+            Fingerprint::new("m2", "prompt", "Please restart the app."),
+            Fingerprint::new("m", "prompt v2", "Please restart the app."),
+            fingerprint.clone().with_option("tone", "formal"),
+        ];
+        for other in changed {
+            assert_ne!(
+                key,
+                other.key(Namespace::Translation, "Please restart the app.")
+            );
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let store = TempStore::new("cache");
+        let dir = store.dir.clone();
+        let fingerprint = Fingerprint::new("m", "prompt", "Hello");
+        assert!(get_cached_translation(&store, "Hello", &fingerprint).is_none());
+        save_cached_translation(&store, "Hello", "こんにちは", &fingerprint).unwrap();
+        save_cached_dictionary_entry(&store, "Hello", "{}", &fingerprint).unwrap();
+
+        assert_eq!(
+            get_cached_translation(&store, "Hello", &fingerprint).as_deref(),
+            Some("こんにちは")
+        );
+        let new_prompt = Fingerprint::new("m", "prompt v2", "Hello");
+        assert!(get_cached_translation(&store, "Hello", &new_prompt).is_none());
+        assert_eq!(
+            get_cached_dictionary_entry(&store, "Hello", &fingerprint).as_deref(),
+            Some("{}")
+        );
+
+        // Another process (a fresh index) reads the same log
+        let other = Cache::new(dir.clone());
+        assert_eq!(
+            other
+                .get(&fingerprint.key(Namespace::Translation, "Hello"), TTL)
+                .unwrap()
+                .as_deref(),
+            Some("こんにちは")
+        );
+
+        // Only this test's cache: flush_all would recreate other tests' removed directories
+        open(&store).unwrap().flush().unwrap();
+        let stats = get_cache_stats(&store);
+        assert_eq!((stats.entry_count, stats.hits, stats.misses), (2, 2, 2));
+
+        clear(&store).unwrap();
+        assert!(get_cached_translation(&store, "Hello", &fingerprint).is_none());
+        assert_eq!(get_cache_stats(&store).entry_count, 0);
+    }
+
+    #[test]
+    fn test_lru_eviction_and_compaction() {
+        let temp = TempStore::new("lru");
+        let cache = Cache::new(temp.dir.clone());
+        let put = |text: &str| {
+            let contents = Contents {
+                preview: String::new(),
+                value: text.to_uppercase(),
+            };
+            cache.put(text, Namespace::Translation, "m", &contents, &limits(2))
+        };
+
+        put("a").unwrap();
+        put("b").unwrap();
+        // "a" was used more recently than "b", so "b" goes
+        assert!(cache.get("a", TTL).unwrap().is_some());
+        put("c").unwrap();
+        assert!(cache.get("b", TTL).unwrap().is_none());
+        assert_eq!(cache.get("a", TTL).unwrap().as_deref(), Some("A"));
+
+        // Rewriting the same key leaves dead records behind until compaction
+        for _ in 0..COMPACT_SLACK + 10 {
+            put("c").unwrap();
+        }
+        let index = cache.lock().unwrap();
+        assert!(index.records < COMPACT_SLACK);
+        assert_eq!(index.slots.len(), 2);
+        drop(index);
+        assert_eq!(cache.get("c", TTL).unwrap().as_deref(), Some("C"));
+    }
+
+    #[test]
+    fn test_old_key_format_is_discarded() {
+        let store = TempStore::new("legacy-cache");
+        let dir = store.dir.clone();
+        store.set(
+            "translation_cache",
+            serde_json::json!([{ "source_hash": hash_text("Hello"), "model": "m" }]),
+        );
+        store.set(
+            "cache_stats",
+            serde_json::json!({ "entry_count": 1, "hits": 5, "misses": 7 }),
+        );
+        // A log from before fingerprints: no key format in the header
+        fs::create_dir_all(&dir).unwrap();
+        let old_key = format!("t:m:{}", hash_text("Hello"));
+        let log = format!(
+            "{}\n{}\n",
+            serde_json::json!({ "op": "header", "generation": 1 }),
+            serde_json::json!({
+                "op": "put", "key": old_key, "namespace": "translation", "model": "m",
+                "preview": "Hello", "value": "こんにちは", "timestamp": now(),
+            })
+        );
+        fs::write(dir.join(LOG_FILE), log).unwrap();
+
+        let fingerprint = Fingerprint::new("m", "prompt", "Hello");
+        assert!(get_cached_translation(&store, "Hello", &fingerprint).is_none());
+        let stats = get_cache_stats(&store);
+        assert_eq!((stats.entry_count, stats.hits, stats.misses), (0, 5, 8));
+        assert_eq!(
+            store.get("translation_cache"),
+            Some(Value::Array(Vec::new()))
+        );
+        let header = fs::read_to_string(dir.join(LOG_FILE)).unwrap();
+        assert!(header.contains(&format!("\"key_format\":{}", KEY_FORMAT)));
+    }
+
+    #[test]
+    fn test_entries_are_encrypted() {
+        let store = TempStore::new("sealed-cache");
+        let dir = store.dir.clone();
+        let text = "Quarterly numbers look great";
+        let fingerprint = Fingerprint::new("m", "prompt", text);
+        save_cached_translation(&store, text, "四半期の数字は好調です", &fingerprint).unwrap();
+
+        let log = fs::read_to_string(dir.join(LOG_FILE)).unwrap();
+        assert!(!log.contains("Quarterly"));
+        assert!(!log.contains("四半期"));
+
+        // A damaged entry is a miss, and is dropped
+        let damaged: Vec<String> = log
+            .lines()
+            .map(|line| {
+                let mut record: Value = serde_json::from_str(line).unwrap();
+                if record["op"] == "put" {
+                    record["sealed"] = Value::String(BASE64.encode([0u8; 40]));
+                }
+                record.to_string()
+            })
+            .collect();
+        fs::write(dir.join(LOG_FILE), damaged.join("\n") + "\n").unwrap();
+        let other = Cache::new(dir.clone());
+        let key = fingerprint.key(Namespace::Translation, text);
+        assert!(other.get(&key, TTL).unwrap().is_none());
+        assert_eq!(other.stats().unwrap().0, 0);
+    }
+
+    #[test]
+    fn test_rotate_key() {
+        let store = TempStore::new("rotate-cache");
+        let dir = store.dir.clone();
+        let fingerprint = Fingerprint::new("m", "prompt", "Hello");
+        let key = fingerprint.key(Namespace::Translation, "Hello");
+        save_cached_translation(&store, "Hello", "こんにちは", &fingerprint).unwrap();
+        // Another process that has the old key loaded
+        let other = Cache::new(dir.clone());
+        assert!(other.get(&key, TTL).unwrap().is_some());
+
+        rotate_key(&store).unwrap();
+        assert!(get_cached_translation(&store, "Hello", &fingerprint).is_none());
+        save_cached_translation(&store, "Hello", "やあ", &fingerprint).unwrap();
+
+        // It picks up the new key instead of discarding the log
+        assert_eq!(other.get(&key, TTL).unwrap().as_deref(), Some("やあ"));
+    }
+
+    #[test]
+    fn test_seal_with_cache_key() {
+        let store = TempStore::new("seal-cache");
+        let sealed = seal(&store, "memory", &vec!["Hello".to_string()]).unwrap();
+        assert!(!sealed.contains("Hello"));
+        assert_eq!(
+            unseal::<Vec<String>>(&store, "memory", &sealed).unwrap(),
+            Some(vec!["Hello".to_string()])
+        );
+        // Bound to its name
+        assert_eq!(
+            unseal::<Vec<String>>(&store, "other", &sealed).unwrap(),
+            None
+        );
+
+        rotate_key(&store).unwrap();
+        assert_eq!(
+            unseal::<Vec<String>>(&store, "memory", &sealed).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unreadable_key_keeps_cache() {
+        let store = TempStore::new("locked-cache");
+        let dir = store.dir.clone();
+        let fingerprint = Fingerprint::new("m", "prompt", "Hello");
+        let key = fingerprint.key(Namespace::Translation, "Hello");
+        save_cached_translation(&store, "Hello", "こんにちは", &fingerprint).unwrap();
+
+        // Another process that can't read the Keychain: no cache, but no new key either
+        LOCKED_KEYS.lock().unwrap().insert(dir.clone());
+        let other = Cache::new(dir.clone());
+        assert!(other.get(&key, TTL).is_err());
+        LOCKED_KEYS.lock().unwrap().remove(&dir);
+
+        assert_eq!(other.get(&key, TTL).unwrap().as_deref(), Some("こんにちは"));
+        assert_eq!(
+            get_cached_translation(&store, "Hello", &fingerprint).as_deref(),
+            Some("こんにちは")
+        );
+    }
+
+    #[test]
+    fn test_model_limits() {
+        let temp = TempStore::new("model-limits");
+        let cache = Cache::new(temp.dir.clone());
+        let limits = Limits {
+            per_model: BTreeMap::from([("sonnet".to_string(), 1), ("opus".to_string(), 0)]),
+            ..limits(3)
+        };
+        let put = |key: &str, model: &str| {
+            let contents = Contents {
+                preview: String::new(),
+                value: key.to_uppercase(),
+            };
+            cache.put(key, Namespace::Translation, model, &contents, &limits)
+        };
+
+        put("a", "haiku").unwrap();
+        put("b", "sonnet").unwrap();
+        put("c", "sonnet").unwrap();
+        put("d", "opus").unwrap();
+        // "b" made room for "c" within sonnet's limit; opus is never cached
+        assert!(cache.get("b", TTL).unwrap().is_none());
+        assert!(cache.get("d", TTL).unwrap().is_none());
+        assert!(cache.get("a", TTL).unwrap().is_some());
+        assert!(cache.get("c", TTL).unwrap().is_some());
+        // Expired under a shorter TTL
+        assert!(cache.get("a", -1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_browse_and_delete() {
+        let store = TempStore::new("browse-cache");
+        for (text, translation) in [
+            ("Good morning everyone", "皆さんおはようございます"),
+            (
+                "Contact me at user@example.com",
+                "user@example.com までご連絡ください",
+            ),
+            ("See you tomorrow", "また明日"),
+        ] {
+            let fingerprint = Fingerprint::new("m", "prompt", text);
+            save_cached_translation(&store, text, translation, &fingerprint).unwrap();
+        }
+
+        let page = list_entries(&store, "", 0, 2).unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.entries.len(), 2);
+        // Most recent first
+        assert_eq!(page.entries[0].value, "また明日");
+        assert_eq!(list_entries(&store, "", 2, 2).unwrap().entries.len(), 1);
+
+        // Previews are masked, translations are searched as stored
+        let page = list_entries(&store, "[EMAIL]", 0, 10).unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.entries[0].preview, "Contact me at [EMAIL]");
+        assert_eq!(list_entries(&store, "おはよう", 0, 10).unwrap().total, 1);
+        assert_eq!(
+            list_entries(&store, "GOOD MORNING", 0, 10).unwrap().total,
+            1
+        );
+
+        let id = page.entries[0].id.clone();
+        assert!(delete_entry(&store, &id).unwrap());
+        assert!(!delete_entry(&store, &id).unwrap());
+        assert_eq!(list_entries(&store, "", 0, 10).unwrap().total, 2);
+    }
+
+    #[test]
+    fn test_estimated_savings() {
+        let store = TempStore::new("savings-cache");
+        settings::save_settings(
+            &store,
+            &Settings {
+                cache_ttl_days: 7,
+                ..Settings::default()
+            },
+        )
+        .unwrap();
+        settings::record_usage(&store, Some("m"), 100, 20, 0.002, false).unwrap();
+        let fingerprint = Fingerprint::new("m", "prompt", "Good morning");
+        save_cached_translation(&store, "Good morning", "おはよう", &fingerprint).unwrap();
+        save_cached_dictionary_entry(&store, "Good morning", "{}", &fingerprint).unwrap();
+        for _ in 0..3 {
+            get_cached_translation(&store, "Good morning", &fingerprint).unwrap();
+        }
+        // Dictionary hits don't count towards the savings
+        get_cached_dictionary_entry(&store, "Good morning", &fingerprint).unwrap();
+        assert!(get_cached_translation(&store, "Good night", &fingerprint).is_none());
+
+        let stats = get_cache_stats(&store);
+        assert_eq!((stats.hits, stats.misses), (4, 1));
+        assert!((stats.hit_rate - 0.8).abs() < 1e-12);
+        assert!((stats.estimated_savings - 0.006).abs() < 1e-12);
+    }
+}
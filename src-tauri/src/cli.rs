@@ -0,0 +1,308 @@
+//! `traylingo-cli`: translate from the terminal with the app's API key, settings,
+//! cache and prompt. Reads arguments, `-f FILE` or stdin; writes the translation to stdout.
+
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use serde_json::json;
+
+use crate::cache;
+use crate::engine::{
+    self, CollectingSink, TextUpdate, TranslateContext, TranslateOptions, TranslationResult,
+};
+use crate::error::TranslateError;
+use crate::keychain;
+use crate::secrets;
+use crate::settings::{self, resolve_model, FileStore, OutputValidation};
+use crate::validation::Language;
+use crate::SettingsStorage;
+
+const SESSION_ID: &str = "cli";
+
+const USAGE: &str = "Usage: traylingo-cli [OPTIONS] [TEXT]...
+
+Translates TEXT, the file given with -f, or stdin (Japanese <-> English).
+
+Options:
+  -f, --file <PATH>     Translate a file (\"-\" for stdin)
+  -t, --to <en|ja>      Target language; text already in it is printed unchanged
+  -m, --model <MODEL>   Model id or alias (haiku, sonnet) instead of the app setting
+      --json            Print one JSON object (translation, usage, issues) when done
+      --allow-sensitive Send text that looks like it contains credentials
+  -h, --help            Print this help
+  -V, --version         Print the version
+
+Exit codes:
+  0 success          2 usage error           3 API key missing or invalid
+  4 rate limited or overloaded               5 network error or timeout
+  6 API error        7 unusable response     8 blocked: looks like credentials
+  1 other errors";
+
+/// Usage errors (bad flags, no input)
+const EXIT_USAGE: u8 = 2;
+
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    text: Vec<String>,
+    file: Option<PathBuf>,
+    to: Option<Language>,
+    model: Option<String>,
+    json: bool,
+    allow_sensitive: bool,
+    help: bool,
+    version: bool,
+}
+
+/// Value of an option that takes one (`--to en`)
+fn option_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("{} requires a value", flag))
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--file" => parsed.file = Some(PathBuf::from(option_value(&mut args, &arg)?)),
+            "-t" | "--to" => {
+                let code = option_value(&mut args, &arg)?;
+                parsed.to = Some(
+                    Language::from_code(&code)
+                        .ok_or_else(|| format!("Unsupported target language: {}", code))?,
+                )
+            }
+            "-m" | "--model" => {
+                parsed.model = Some(resolve_model(&option_value(&mut args, &arg)?)?)
+            }
+            "--json" => parsed.json = true,
+            "--allow-sensitive" => parsed.allow_sensitive = true,
+            "-h" | "--help" => parsed.help = true,
+            "-V" | "--version" => parsed.version = true,
+            "--" => parsed.text.extend(args.by_ref()),
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("Unknown option: {}", flag))
+            }
+            _ => parsed.text.push(arg),
+        }
+    }
+    if parsed.file.is_some() && !parsed.text.is_empty() {
+        return Err("Pass either TEXT or --file, not both".to_string());
+    }
+    Ok(parsed)
+}
+
+/// Exit status for scripts (see USAGE)
+fn exit_code(error: &TranslateError) -> u8 {
+    match error {
+        TranslateError::ApiKeyMissing | TranslateError::AuthenticationFailed { .. } => 3,
+        TranslateError::RateLimitExceeded { .. } | TranslateError::Overloaded => 4,
+        TranslateError::Timeout { .. } | TranslateError::NetworkError { .. } => 5,
+        TranslateError::ApiError { .. } => 6,
+        TranslateError::ParseError { .. }
+        | TranslateError::IncompleteResponse
+        | TranslateError::PlaceholderMismatch { .. } => 7,
+        TranslateError::SensitiveContentBlocked { .. }
+        | TranslateError::PrivateContentBlocked { .. } => 8,
+        TranslateError::Unknown { .. } => 1,
+    }
+}
+
+fn read_input(args: &Args) -> Result<String, String> {
+    let text = match &args.file {
+        Some(path) if path.as_os_str() != "-" => std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?,
+        Some(_) => read_stdin()?,
+        None if !args.text.is_empty() => args.text.join(" "),
+        None if std::io::stdin().is_terminal() => return Err("No input".to_string()),
+        None => read_stdin()?,
+    };
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("No input".to_string());
+    }
+    Ok(text.to_string())
+}
+
+fn read_stdin() -> Result<String, String> {
+    let mut text = String::new();
+    std::io::stdin()
+        .read_to_string(&mut text)
+        .map_err(|e| format!("Failed to read stdin: {}", e))?;
+    Ok(text)
+}
+
+fn fail(error: &TranslateError, json: bool) -> ExitCode {
+    if json {
+        println!(
+            "{}",
+            json!({ "error": error, "message": error.user_message() })
+        );
+    } else {
+        eprintln!("traylingo-cli: {}", error.user_message());
+    }
+    ExitCode::from(exit_code(error))
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("traylingo-cli: {}\n\n{}", message, USAGE);
+    ExitCode::from(EXIT_USAGE)
+}
+
+pub fn run() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(message) => return usage_error(&message),
+    };
+    if args.help {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    if args.version {
+        println!("traylingo-cli {}", env!("CARGO_PKG_VERSION"));
+        return ExitCode::SUCCESS;
+    }
+    let text = match read_input(&args) {
+        Ok(text) => text,
+        Err(message) => return usage_error(&message),
+    };
+
+    // Already in the requested language: pass through, so `| traylingo-cli --to en` is safe
+    // on mixed input. Too-short text can't be detected and is translated as usual.
+    if args.to.is_some() && Language::detect(&text) == args.to {
+        if args.json {
+            println!("{}", json!({ "translation": text, "skipped": true }));
+        } else {
+            println!("{}", text);
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    if let Err(error) = secrets::check(&[&text], args.allow_sensitive) {
+        return fail(&error, args.json);
+    }
+    let Some(api_key) = keychain::get_api_key() else {
+        return fail(&TranslateError::ApiKeyMissing, args.json);
+    };
+    let store = match FileStore::open_default() {
+        Ok(store) => store,
+        Err(message) => return fail(&TranslateError::Unknown { message }, args.json),
+    };
+    let mut options = TranslateOptions::from_settings(&settings::get_settings(&store), api_key);
+    if let Some(model) = &args.model {
+        options.model = model.clone();
+    }
+
+    // WHY: Stdout can't be rewritten, so a retried translation can't replace what was
+    // already printed. In retry mode (and for --json) the result is printed at the end.
+    let stream = !args.json && options.output_validation != OutputValidation::Retry;
+    let sink = CollectingSink::new(|update| {
+        // Replace never arrives while streaming: retry mode prints at the end
+        if let (true, TextUpdate::Chunk(text)) = (stream, update) {
+            let mut stdout = std::io::stdout().lock();
+            let _ = stdout.write_all(text.as_bytes());
+            let _ = stdout.flush();
+        }
+    });
+    let storage = SettingsStorage(store);
+    let ctx = TranslateContext {
+        sink: &sink,
+        storage: &storage,
+        options,
+    };
+
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            let error = TranslateError::Unknown {
+                message: e.to_string(),
+            };
+            return fail(&error, args.json);
+        }
+    };
+    let result = runtime.block_on(engine::translate(&ctx, &text, SESSION_ID));
+    // Hit counts are written in batches, and this process is about to exit
+    cache::flush_all();
+    let translation = match result {
+        Ok(translation) => translation,
+        Err(error) => {
+            if stream {
+                println!();
+            }
+            return fail(&error, args.json);
+        }
+    };
+
+    let result = TranslationResult::new(translation, &ctx.options.model, sink.take_report());
+    if args.json {
+        let mut output = json!(result);
+        output["skipped"] = json!(false);
+        println!("{}", output);
+        return ExitCode::SUCCESS;
+    }
+
+    if stream {
+        println!();
+    } else {
+        println!("{}", result.translation);
+    }
+    if !result.issues.is_empty() {
+        eprintln!(
+            "traylingo-cli: warning: translation flagged by output validation: {:?}",
+            result.issues
+        );
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = parse(&["--to", "en", "-m", "sonnet", "--json", "設定を", "保存"]).unwrap();
+        assert_eq!(args.to, Some(Language::English));
+        assert_eq!(args.model.as_deref(), Some("claude-sonnet-4-5-20250514"));
+        assert!(args.json);
+        assert_eq!(args.text, ["設定を", "保存"]);
+
+        let args = parse(&["-f", "notes.md", "--", "--not-a-flag"]);
+        assert!(args.is_err()); // TEXT and --file together
+
+        let args = parse(&["-f", "notes.md"]).unwrap();
+        assert_eq!(args.file, Some(PathBuf::from("notes.md")));
+        assert_eq!(parse(&["-"]).unwrap().text, ["-"]);
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert!(parse(&["--to"]).is_err());
+        assert!(parse(&["--to", "fr"]).is_err());
+        assert!(parse(&["--model", "gpt-4"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(exit_code(&TranslateError::ApiKeyMissing), 3);
+        assert_eq!(exit_code(&TranslateError::Overloaded), 4);
+        assert_eq!(exit_code(&TranslateError::IncompleteResponse), 7);
+        let blocked = TranslateError::SensitiveContentBlocked {
+            categories: vec!["password".into()],
+        };
+        assert_eq!(exit_code(&blocked), 8);
+        let unknown = TranslateError::Unknown {
+            message: "boom".into(),
+        };
+        assert_eq!(exit_code(&unknown), 1);
+    }
+}
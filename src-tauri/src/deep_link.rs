@@ -0,0 +1,155 @@
+//! `traylingo://translate?text=...&mode=popup&to=en` links from bookmarklets,
+//! launchers and shell scripts. Parsing and validation only; lib.rs asks for
+//! confirmation and shows the popup or main window.
+
+use url::Url;
+
+use crate::validation::Language;
+
+/// Longest text accepted from a link (characters)
+pub const MAX_TEXT_CHARS: usize = 10_000;
+
+/// Characters of the text shown in the confirmation dialog
+const PREVIEW_CHARS: usize = 200;
+
+/// Where the translation is shown (`mode=`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Popup,
+    Window,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DeepLink {
+    pub text: String,
+    pub mode: Mode,
+}
+
+/// Validate a `traylingo://` URL. Unknown or repeated parameters are rejected
+/// rather than ignored, so a typo doesn't silently change what happens.
+pub fn parse(url: &Url) -> Result<DeepLink, String> {
+    if url.scheme() != "traylingo" {
+        return Err(format!("Unsupported URL scheme: {}", url.scheme()));
+    }
+    if url.host_str() != Some("translate") {
+        return Err("Unknown action (expected traylingo://translate)".to_string());
+    }
+
+    let mut text = None;
+    let mut mode = None;
+    let mut to = None;
+    for (key, value) in url.query_pairs() {
+        let repeated = match key.as_ref() {
+            "text" => text.replace(value.into_owned()).is_some(),
+            "mode" => {
+                let parsed = match value.as_ref() {
+                    "popup" => Mode::Popup,
+                    "window" => Mode::Window,
+                    other => return Err(format!("Unsupported mode: {}", other)),
+                };
+                mode.replace(parsed).is_some()
+            }
+            "to" => {
+                let parsed = Language::from_code(&value)
+                    .ok_or_else(|| format!("Unsupported target language: {}", value))?;
+                to.replace(parsed).is_some()
+            }
+            other => return Err(format!("Unknown parameter: {}", other)),
+        };
+        if repeated {
+            return Err(format!("Repeated parameter: {}", key));
+        }
+    }
+
+    let text = text
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+        .ok_or_else(|| "Missing text".to_string())?;
+    let length = text.chars().count();
+    if length > MAX_TEXT_CHARS {
+        return Err(format!(
+            "Text is too long ({} characters, max {})",
+            length, MAX_TEXT_CHARS
+        ));
+    }
+    if text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+    {
+        return Err("Text contains control characters".to_string());
+    }
+    // Direction is detected from the text; `to` only guards against a no-op request
+    if let Some(to) = to.filter(|to| Language::detect(&text) == Some(*to)) {
+        return Err(format!("Text is already in {}", to.name()));
+    }
+
+    Ok(DeepLink {
+        text,
+        mode: mode.unwrap_or_default(),
+    })
+}
+
+/// Start of the text, for the confirmation dialog
+pub fn preview(text: &str) -> String {
+    let mut preview: String = text.chars().take(PREVIEW_CHARS).collect();
+    if text.chars().count() > PREVIEW_CHARS {
+        preview.push('…');
+    }
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_str(url: &str) -> Result<DeepLink, String> {
+        parse(&Url::parse(url).unwrap())
+    }
+
+    #[test]
+    fn test_parse() {
+        let link = parse_str("traylingo://translate?text=Hello+world%21").unwrap();
+        assert_eq!(link.text, "Hello world!");
+        assert_eq!(link.mode, Mode::Popup);
+
+        let link = parse_str(
+            "traylingo://translate?text=%E8%A8%AD%E5%AE%9A%E3%82%92%E4%BF%9D%E5%AD%98&mode=window&to=en",
+        )
+        .unwrap();
+        assert_eq!(link.text, "設定を保存");
+        assert_eq!(link.mode, Mode::Window);
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid() {
+        assert!(parse_str("https://translate?text=hi").is_err());
+        assert!(parse_str("traylingo://settings?text=hi").is_err());
+        assert!(parse_str("traylingo://translate").is_err());
+        assert!(parse_str("traylingo://translate?text=+++").is_err());
+        assert!(parse_str("traylingo://translate?text=hi&mode=fullscreen").is_err());
+        assert!(parse_str("traylingo://translate?text=hi&to=fr").is_err());
+        assert!(parse_str("traylingo://translate?text=hi&lang=en").is_err());
+        assert!(parse_str("traylingo://translate?text=a&text=b").is_err());
+        assert!(parse_str("traylingo://translate?text=a%00b").is_err());
+
+        let long = format!(
+            "traylingo://translate?text={}",
+            "a".repeat(MAX_TEXT_CHARS + 1)
+        );
+        assert!(parse_str(&long).unwrap_err().contains("too long"));
+
+        let english = "traylingo://translate?text=Please+restart+the+app+now&to=en";
+        assert_eq!(
+            parse_str(english).unwrap_err(),
+            "Text is already in English"
+        );
+    }
+
+    #[test]
+    fn test_preview() {
+        assert_eq!(preview("short"), "short");
+        let long = "あ".repeat(PREVIEW_CHARS + 5);
+        assert_eq!(preview(&long).chars().count(), PREVIEW_CHARS + 1);
+    }
+}
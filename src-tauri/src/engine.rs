@@ -0,0 +1,432 @@
+//! Boundary between the translation engine and the app around it.
+//! The engine reports progress through an `EventSink` and persists through a
+//! `Storage`; lib.rs implements both on top of Tauri events and the store.
+
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+use crate::anthropic::{self, Usage};
+use crate::cache::Fingerprint;
+use crate::error::TranslateError;
+use crate::markdown;
+use crate::redaction::RedactionReport;
+use crate::settings::{ErrorHistoryEntry, MemoryMode, OutputValidation, Settings};
+use crate::translation_memory::MemoryMatch;
+use crate::validation::OutputIssue;
+use crate::verification::QualityAssessment;
+
+/// Messages API endpoint used outside of tests
+pub const MESSAGES_URL: &str = "https://api.anthropic.com/v1/messages";
+
+// Event payloads with session ID for filtering
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ChunkPayload {
+    pub session_id: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DonePayload {
+    pub session_id: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct UsagePayload {
+    pub session_id: String,
+    /// None for cache hits
+    pub model: Option<String>,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub estimated_cost: f64,
+    #[serde(default)]
+    pub cached: bool,
+}
+
+/// Output validation result (no session: popup translation)
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FlaggedPayload {
+    pub session_id: Option<String>,
+    pub issues: Vec<OutputIssue>,
+}
+
+/// Translation memory match for the text being translated (no session: popup translation)
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct MemoryMatchPayload {
+    pub session_id: Option<String>,
+    #[serde(flatten)]
+    pub memory_match: MemoryMatch,
+}
+
+/// Back-translation check of a finished translation
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct VerificationPayload {
+    pub session_id: String,
+    pub back_translation: String,
+    pub assessment: QualityAssessment,
+    pub estimated_cost: f64,
+}
+
+/// Receives translation progress
+pub trait EventSink: Send + Sync {
+    fn chunk(&self, payload: ChunkPayload);
+    /// The whole translation replaces what was streamed so far (after a retry)
+    fn replace(&self, payload: ChunkPayload);
+    fn usage(&self, payload: UsagePayload);
+    fn done(&self, payload: DonePayload);
+    fn flagged(&self, payload: FlaggedPayload);
+    /// Values redacted in privacy mode
+    fn redaction(&self, report: RedactionReport);
+    /// Similar earlier translation from the translation memory
+    fn memory_match(&self, payload: MemoryMatchPayload);
+    /// Result of a back-translation check
+    fn verification(&self, payload: VerificationPayload);
+}
+
+/// Translation cache, error history and usage totals
+pub trait Storage: Send + Sync {
+    /// None on a miss or when the cache is disabled
+    fn cached_translation(&self, text: &str, fingerprint: &Fingerprint) -> Option<String>;
+    fn save_translation(
+        &self,
+        text: &str,
+        translation: &str,
+        fingerprint: &Fingerprint,
+    ) -> Result<(), String>;
+    /// Best effort: failures are ignored
+    fn save_error(&self, entry: ErrorHistoryEntry);
+    /// Add one translation to the usage totals (best effort)
+    fn record_usage(&self, usage: &UsagePayload);
+    /// Add an auxiliary call (dictionary, reading, verification, refinement) to the
+    /// usage totals (best effort)
+    fn record_request_usage(&self, model: &str, usage: &Usage);
+    /// Closest translation memory segment (None when the cache is disabled)
+    fn memory_match(&self, text: &str) -> Option<MemoryMatch>;
+    /// Remember the segments of a finished translation (best effort)
+    fn save_memory(&self, source: &str, translation: &str);
+}
+
+/// Per-request configuration, resolved from settings and the keychain by the caller
+#[derive(Debug, Clone)]
+pub struct TranslateOptions {
+    pub api_key: String,
+    pub model: String,
+    /// Privacy mode patterns (None when privacy mode is off)
+    pub redaction_patterns: Option<Vec<String>>,
+    pub output_validation: OutputValidation,
+    pub memory: MemoryMode,
+    pub endpoint: String,
+}
+
+impl TranslateOptions {
+    pub fn from_settings(settings: &Settings, api_key: String) -> Self {
+        Self {
+            api_key,
+            model: settings.model.clone(),
+            redaction_patterns: settings
+                .privacy_mode
+                .then(|| settings.redaction_patterns.clone()),
+            output_validation: settings.output_validation,
+            memory: settings.translation_memory,
+            endpoint: MESSAGES_URL.to_string(),
+        }
+    }
+}
+
+/// Everything a translation needs besides the text
+pub struct TranslateContext<'a> {
+    pub sink: &'a dyn EventSink,
+    pub storage: &'a dyn Storage,
+    pub options: TranslateOptions,
+}
+
+/// Translate `text` the way the main window does: Markdown documents prose-only
+/// (emitted at once), everything else streamed
+pub async fn translate(
+    ctx: &TranslateContext<'_>,
+    text: &str,
+    session_id: &str,
+) -> Result<String, TranslateError> {
+    if markdown::looks_like_markdown(text) {
+        // Prose-only translation can't stream; the reassembled document is emitted at once
+        anthropic::translate_markdown(ctx, text, Some(session_id)).await
+    } else {
+        anthropic::translate_stream(ctx, text.to_string(), session_id.to_string()).await
+    }
+}
+
+/// Text as it arrives at a `CollectingSink`
+pub enum TextUpdate<'a> {
+    Chunk(&'a str),
+    /// Replaces everything received so far (after a retry)
+    Replace(&'a str),
+}
+
+/// Usage, validation issues and redactions of one translation
+#[derive(Debug, Default)]
+pub struct Report {
+    pub usage: Option<UsagePayload>,
+    pub issues: Vec<OutputIssue>,
+    pub redacted: usize,
+    pub memory_match: Option<MemoryMatch>,
+}
+
+/// Sink for callers outside the app windows (CLI, local API): passes text updates
+/// to `on_text` and keeps the rest for the final result
+pub struct CollectingSink<F> {
+    on_text: F,
+    report: Mutex<Report>,
+}
+
+impl<F: Fn(TextUpdate<'_>) + Send + Sync> CollectingSink<F> {
+    pub fn new(on_text: F) -> Self {
+        Self {
+            on_text,
+            report: Mutex::new(Report::default()),
+        }
+    }
+
+    pub fn take_report(&self) -> Report {
+        std::mem::take(&mut *self.report.lock().unwrap())
+    }
+}
+
+impl<F: Fn(TextUpdate<'_>) + Send + Sync> EventSink for CollectingSink<F> {
+    fn chunk(&self, payload: ChunkPayload) {
+        (self.on_text)(TextUpdate::Chunk(&payload.text));
+    }
+
+    fn replace(&self, payload: ChunkPayload) {
+        (self.on_text)(TextUpdate::Replace(&payload.text));
+    }
+
+    fn usage(&self, payload: UsagePayload) {
+        self.report.lock().unwrap().usage = Some(payload);
+    }
+
+    fn done(&self, _payload: DonePayload) {}
+
+    fn flagged(&self, payload: FlaggedPayload) {
+        self.report.lock().unwrap().issues = payload.issues;
+    }
+
+    fn redaction(&self, report: RedactionReport) {
+        self.report.lock().unwrap().redacted = report.items.len();
+    }
+
+    fn memory_match(&self, payload: MemoryMatchPayload) {
+        self.report.lock().unwrap().memory_match = Some(payload.memory_match);
+    }
+
+    fn verification(&self, _payload: VerificationPayload) {}
+}
+
+/// Token usage of a finished translation
+#[derive(Debug, Serialize)]
+pub struct UsageSummary {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub estimated_cost: f64,
+}
+
+/// Finished translation as returned by the CLI (`--json`) and the local API
+#[derive(Debug, Serialize)]
+pub struct TranslationResult {
+    pub translation: String,
+    pub model: String,
+    pub cached: bool,
+    pub usage: Option<UsageSummary>,
+    pub issues: Vec<OutputIssue>,
+    pub redacted: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory_match: Option<MemoryMatch>,
+}
+
+impl TranslationResult {
+    pub fn new(translation: String, model: &str, report: Report) -> Self {
+        Self {
+            translation,
+            model: model.to_string(),
+            cached: report.usage.as_ref().is_some_and(|usage| usage.cached),
+            usage: report.usage.map(|usage| UsageSummary {
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+                estimated_cost: usage.estimated_cost,
+            }),
+            issues: report.issues,
+            redacted: report.redacted,
+            memory_match: report.memory_match,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod memory {
+    //! In-memory sink and storage for engine tests
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::translation_memory;
+
+    /// Event as recorded by `MemorySink`
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum SinkEvent {
+        Chunk(ChunkPayload),
+        Replace(ChunkPayload),
+        Usage(UsagePayload),
+        Done(DonePayload),
+        Flagged(FlaggedPayload),
+        Redaction(usize),
+        MemoryMatch(MemoryMatchPayload),
+        Verification(VerificationPayload),
+    }
+
+    #[derive(Default)]
+    pub struct MemorySink {
+        pub events: Mutex<Vec<SinkEvent>>,
+    }
+
+    impl MemorySink {
+        pub fn events(&self) -> Vec<SinkEvent> {
+            self.events.lock().unwrap().clone()
+        }
+
+        /// Concatenated chunk text
+        pub fn streamed_text(&self) -> String {
+            self.events()
+                .into_iter()
+                .filter_map(|event| match event {
+                    SinkEvent::Chunk(payload) => Some(payload.text),
+                    _ => None,
+                })
+                .collect()
+        }
+    }
+
+    impl EventSink for MemorySink {
+        fn chunk(&self, payload: ChunkPayload) {
+            self.events.lock().unwrap().push(SinkEvent::Chunk(payload));
+        }
+
+        fn replace(&self, payload: ChunkPayload) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(SinkEvent::Replace(payload));
+        }
+
+        fn usage(&self, payload: UsagePayload) {
+            self.events.lock().unwrap().push(SinkEvent::Usage(payload));
+        }
+
+        fn done(&self, payload: DonePayload) {
+            self.events.lock().unwrap().push(SinkEvent::Done(payload));
+        }
+
+        fn flagged(&self, payload: FlaggedPayload) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(SinkEvent::Flagged(payload));
+        }
+
+        fn redaction(&self, report: RedactionReport) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(SinkEvent::Redaction(report.items.len()));
+        }
+
+        fn memory_match(&self, payload: MemoryMatchPayload) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(SinkEvent::MemoryMatch(payload));
+        }
+
+        fn verification(&self, payload: VerificationPayload) {
+            self.events
+                .lock()
+                .unwrap()
+                .push(SinkEvent::Verification(payload));
+        }
+    }
+
+    #[derive(Default)]
+    pub struct MemoryStorage {
+        pub cache: Mutex<HashMap<(String, Fingerprint), String>>,
+        pub errors: Mutex<Vec<ErrorHistoryEntry>>,
+        pub usage: Mutex<Vec<UsagePayload>>,
+        /// Model and usage of auxiliary calls
+        pub requests: Mutex<Vec<(String, Usage)>>,
+        pub memory: Mutex<Vec<translation_memory::Segment>>,
+    }
+
+    impl Storage for MemoryStorage {
+        fn cached_translation(&self, text: &str, fingerprint: &Fingerprint) -> Option<String> {
+            self.cache
+                .lock()
+                .unwrap()
+                .get(&(text.to_string(), fingerprint.clone()))
+                .cloned()
+        }
+
+        fn save_translation(
+            &self,
+            text: &str,
+            translation: &str,
+            fingerprint: &Fingerprint,
+        ) -> Result<(), String> {
+            self.cache.lock().unwrap().insert(
+                (text.to_string(), fingerprint.clone()),
+                translation.to_string(),
+            );
+            Ok(())
+        }
+
+        fn save_error(&self, entry: ErrorHistoryEntry) {
+            self.errors.lock().unwrap().push(entry);
+        }
+
+        fn record_usage(&self, usage: &UsagePayload) {
+            self.usage.lock().unwrap().push(usage.clone());
+        }
+
+        fn record_request_usage(&self, model: &str, usage: &Usage) {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((model.to_string(), usage.clone()));
+        }
+
+        fn memory_match(&self, text: &str) -> Option<MemoryMatch> {
+            translation_memory::best_match(&self.memory.lock().unwrap(), text)
+        }
+
+        fn save_memory(&self, source: &str, translation: &str) {
+            let mut memory = self.memory.lock().unwrap();
+            for (source, target) in translation_memory::align(source, translation) {
+                memory.push(translation_memory::Segment {
+                    source,
+                    target,
+                    timestamp: 0,
+                    imported: false,
+                });
+            }
+        }
+    }
+
+    /// Options pointing at a mock server, privacy mode off, validation flagging,
+    /// translation memory suggestions
+    pub fn options(endpoint: &str) -> TranslateOptions {
+        TranslateOptions {
+            api_key: "test-key".to_string(),
+            model: "claude-haiku-4-5-20251001".to_string(),
+            redaction_patterns: None,
+            output_validation: OutputValidation::Flag,
+            memory: MemoryMode::Suggest,
+            endpoint: endpoint.to_string(),
+        }
+    }
+}
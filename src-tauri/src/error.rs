@@ -33,6 +33,19 @@ pub enum TranslateError {
 
     /// Stream ended without message_stop (incomplete response)
     IncompleteResponse,
+
+    /// Protected placeholders ({name}, %d, URLs...) missing or duplicated in the output
+    PlaceholderMismatch {
+        missing: Vec<String>,
+        duplicated: Vec<String>,
+    },
+
+    /// Text looks like it contains credentials (category names only, never the values)
+    SensitiveContentBlocked { categories: Vec<String> },
+
+    /// Privacy mode is on and a request that can't use placeholders (dictionary
+    /// lookup, reading) would send personal data (category names only)
+    PrivateContentBlocked { categories: Vec<String> },
 }
 
 impl TranslateError {
@@ -68,6 +81,24 @@ impl TranslateError {
                 "Translation was interrupted. The response may be incomplete. Please try again."
                     .into()
             }
+            // WHY: Counts only; placeholders can be URLs or emails and this message
+            // is stored in the error history
+            Self::PlaceholderMismatch {
+                missing,
+                duplicated,
+            } => format!(
+                "Translation changed placeholders ({} missing, {} duplicated). Please try again.",
+                missing.len(),
+                duplicated.len()
+            ),
+            Self::SensitiveContentBlocked { categories } => format!(
+                "This text looks like it contains credentials ({}). It was not sent for translation.",
+                categories.join(", ")
+            ),
+            Self::PrivateContentBlocked { categories } => format!(
+                "Privacy mode is on and this text contains personal data ({}). It was not sent.",
+                categories.join(", ")
+            ),
         }
     }
 }
@@ -105,4 +136,15 @@ mod tests {
         let err = TranslateError::ApiKeyMissing;
         assert!(err.user_message().contains("API key not configured"));
     }
+
+    #[test]
+    fn test_user_message_placeholder_mismatch_hides_values() {
+        let err = TranslateError::PlaceholderMismatch {
+            missing: vec!["https://example.com/secret".into()],
+            duplicated: vec![],
+        };
+        let message = err.user_message();
+        assert!(message.contains("1 missing, 0 duplicated"));
+        assert!(!message.contains("example.com"));
+    }
 }
@@ -0,0 +1,671 @@
+//! Opt-in history of finished translations (Settings → History).
+//!
+//! Entries are JSON lines in `translation_history.jsonl` next to settings.json,
+//! unencrypted, so nothing is written unless `history_enabled` is on, and never
+//! while incognito, for credential-like text, or for text privacy mode redacted.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::anthropic::{self, Usage};
+use crate::cache::Fingerprint;
+use crate::engine::{Storage, UsagePayload};
+use crate::redaction::Redactor;
+use crate::secrets;
+use crate::settings::{self, ErrorHistoryEntry, Settings, SettingsStore};
+use crate::tmx;
+use crate::translation_memory::MemoryMatch;
+
+const HISTORY_FILE: &str = "translation_history.jsonl";
+pub const DEFAULT_LIMIT: usize = 1_000;
+pub const MIN_LIMIT: usize = 10;
+pub const MAX_LIMIT: usize = 10_000;
+pub const DEFAULT_RETENTION_DAYS: u32 = 90;
+const MAX_PAGE_SIZE: usize = 200;
+const DAY_SECS: i64 = 86_400;
+
+/// Runtime only: incognito ends with the app
+static INCOGNITO: AtomicBool = AtomicBool::new(false);
+/// Serializes read-modify-write of the history file within the process
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Which translation path produced the entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Translation,
+    Markdown,
+    Dictionary,
+}
+
+/// Where the translation was requested
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Window {
+    Main,
+    Popup,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    /// Unix seconds
+    pub timestamp: i64,
+    pub source: String,
+    pub translation: String,
+    /// Model id, `offline` for offline dictionary hits
+    pub model: String,
+    pub mode: Mode,
+    /// Estimated USD (0 for cache hits, None when unknown)
+    pub cost: Option<f64>,
+    pub window: Window,
+    #[serde(default)]
+    pub starred: bool,
+}
+
+impl HistoryEntry {
+    pub fn new(
+        source: &str,
+        translation: &str,
+        model: &str,
+        mode: Mode,
+        cost: Option<f64>,
+        window: Window,
+    ) -> Self {
+        Self {
+            id: new_id(),
+            timestamp: now(),
+            source: source.to_string(),
+            translation: translation.to_string(),
+            model: model.to_string(),
+            mode,
+            cost,
+            window,
+            starred: false,
+        }
+    }
+}
+
+/// One page of search results, newest first
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+    /// Matches before paging
+    pub total: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Markdown,
+}
+
+// ==================== Incognito ====================
+
+/// Pause recording until turned off again or the app quits
+pub fn set_incognito(enabled: bool) {
+    INCOGNITO.store(enabled, Ordering::SeqCst);
+}
+
+pub fn is_incognito() -> bool {
+    INCOGNITO.load(Ordering::SeqCst)
+}
+
+// ==================== Cost ====================
+
+/// Storage wrapper that adds up the usage it records, so callers can put the
+/// cost of a translation into its history entry
+pub struct CostMeter<'a> {
+    inner: &'a dyn Storage,
+    cost: Mutex<Option<f64>>,
+}
+
+impl<'a> CostMeter<'a> {
+    pub fn new(inner: &'a dyn Storage) -> Self {
+        Self {
+            inner,
+            cost: Mutex::new(None),
+        }
+    }
+
+    /// Total estimated cost so far (None if no usage was recorded)
+    pub fn cost(&self) -> Option<f64> {
+        *self.cost.lock().unwrap()
+    }
+
+    fn add(&self, estimated_cost: f64) {
+        let mut cost = self.cost.lock().unwrap();
+        *cost = Some(cost.unwrap_or(0.0) + estimated_cost);
+    }
+}
+
+impl Storage for CostMeter<'_> {
+    fn cached_translation(&self, text: &str, fingerprint: &Fingerprint) -> Option<String> {
+        self.inner.cached_translation(text, fingerprint)
+    }
+
+    fn save_translation(
+        &self,
+        text: &str,
+        translation: &str,
+        fingerprint: &Fingerprint,
+    ) -> Result<(), String> {
+        self.inner.save_translation(text, translation, fingerprint)
+    }
+
+    fn save_error(&self, entry: ErrorHistoryEntry) {
+        self.inner.save_error(entry)
+    }
+
+    fn record_usage(&self, usage: &UsagePayload) {
+        self.add(usage.estimated_cost);
+        self.inner.record_usage(usage)
+    }
+
+    fn record_request_usage(&self, model: &str, usage: &Usage) {
+        self.add(anthropic::calculate_cost(
+            usage.input_tokens,
+            usage.output_tokens,
+            model,
+        ));
+        self.inner.record_request_usage(model, usage)
+    }
+
+    fn memory_match(&self, text: &str) -> Option<MemoryMatch> {
+        self.inner.memory_match(text)
+    }
+
+    fn save_memory(&self, source: &str, translation: &str) {
+        self.inner.save_memory(source, translation)
+    }
+}
+
+// ==================== Store ====================
+
+fn history_path(store: &dyn SettingsStore) -> Result<PathBuf, String> {
+    store
+        .data_dir()
+        .map(|dir| dir.join(HISTORY_FILE))
+        .ok_or_else(|| "Could not locate the data directory".to_string())
+}
+
+/// Every entry, oldest first; unreadable lines are skipped
+fn load(path: &Path) -> io::Result<Vec<HistoryEntry>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => log::warn!("Skipping unreadable history entry: {}", e),
+        }
+    }
+    Ok(entries)
+}
+
+fn write(path: &Path, entries: &[HistoryEntry]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp = path.with_extension("jsonl.tmp");
+    let mut out = io::BufWriter::new(fs::File::create(&temp)?);
+    for entry in entries {
+        serde_json::to_writer(&mut out, entry)?;
+        out.write_all(b"\n")?;
+    }
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    fs::rename(&temp, path)
+}
+
+/// Load, change and write back the history under the file lock
+fn update<T>(
+    store: &dyn SettingsStore,
+    change: impl FnOnce(&mut Vec<HistoryEntry>) -> T,
+) -> Result<T, String> {
+    let path = history_path(store)?;
+    let _guard = FILE_LOCK.lock().map_err(|e| e.to_string())?;
+    let mut entries = load(&path).map_err(|e| e.to_string())?;
+    let result = change(&mut entries);
+    write(&path, &entries).map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+/// Drop unstarred entries past the retention period, then the oldest unstarred
+/// ones beyond the limit
+fn prune(entries: &mut Vec<HistoryEntry>, limit: usize, retention_days: u32, now: i64) {
+    if retention_days > 0 {
+        let cutoff = now - i64::from(retention_days) * DAY_SECS;
+        entries.retain(|entry| entry.starred || entry.timestamp >= cutoff);
+    }
+    let limit = limit.clamp(MIN_LIMIT, MAX_LIMIT);
+    let unstarred = entries.iter().filter(|entry| !entry.starred).count();
+    let mut excess = unstarred.saturating_sub(limit);
+    // Oldest first, so the first unstarred entries are the ones to go
+    entries.retain(|entry| {
+        if excess > 0 && !entry.starred {
+            excess -= 1;
+            return false;
+        }
+        true
+    });
+}
+
+// ==================== Operations ====================
+
+/// Add a finished translation unless incognito (see `add` for the other rules)
+pub fn record(store: &dyn SettingsStore, entry: HistoryEntry) -> Result<(), String> {
+    if is_incognito() {
+        return Ok(());
+    }
+    add(store, entry)
+}
+
+/// Nothing while history is off, never credential-like text or personal data
+/// privacy mode kept from the API
+fn add(store: &dyn SettingsStore, entry: HistoryEntry) -> Result<(), String> {
+    let current_settings = settings::get_settings(store);
+    if !current_settings.history_enabled
+        || entry.translation.trim().is_empty()
+        || secrets::contains_secrets(&entry.source)
+        || is_redacted(&current_settings, &entry)
+    {
+        return Ok(());
+    }
+    let now = entry.timestamp;
+    update(store, |entries| {
+        entries.push(entry);
+        prune(
+            entries,
+            current_settings.history_limit,
+            current_settings.history_retention_days,
+            now,
+        );
+    })
+}
+
+/// Whether privacy mode would redact part of the entry.
+// WHY: The translation has the redacted values restored locally; writing it to an
+// unencrypted file would keep exactly what privacy mode is meant to protect.
+fn is_redacted(current: &Settings, entry: &HistoryEntry) -> bool {
+    if !current.privacy_mode {
+        return false;
+    }
+    let mut redactor = Redactor::new(&current.redaction_patterns);
+    redactor.redact(&entry.source);
+    redactor.redact(&entry.translation);
+    !redactor.is_empty()
+}
+
+/// Entries whose source or translation contains every word of `query`
+/// (case-insensitive), newest first
+pub fn search(
+    store: &dyn SettingsStore,
+    query: &str,
+    starred_only: bool,
+    offset: usize,
+    limit: usize,
+) -> Result<HistoryPage, String> {
+    let path = history_path(store)?;
+    let entries = {
+        let _guard = FILE_LOCK.lock().map_err(|e| e.to_string())?;
+        load(&path).map_err(|e| e.to_string())?
+    };
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    let matches: Vec<HistoryEntry> = entries
+        .into_iter()
+        .rev()
+        .filter(|entry| !starred_only || entry.starred)
+        .filter(|entry| {
+            let (source, translation) = (
+                entry.source.to_lowercase(),
+                entry.translation.to_lowercase(),
+            );
+            words
+                .iter()
+                .all(|word| source.contains(word) || translation.contains(word))
+        })
+        .collect();
+    let total = matches.len();
+    let entries = matches
+        .into_iter()
+        .skip(offset)
+        .take(limit.min(MAX_PAGE_SIZE))
+        .collect();
+    Ok(HistoryPage { entries, total })
+}
+
+/// Star or unstar an entry; false if it no longer exists
+pub fn set_starred(store: &dyn SettingsStore, id: &str, starred: bool) -> Result<bool, String> {
+    update(store, |entries| {
+        entries
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .map(|entry| entry.starred = starred)
+            .is_some()
+    })
+}
+
+/// Remove one entry; false if it no longer exists
+pub fn delete(store: &dyn SettingsStore, id: &str) -> Result<bool, String> {
+    update(store, |entries| {
+        let before = entries.len();
+        entries.retain(|entry| entry.id != id);
+        entries.len() != before
+    })
+}
+
+/// Remove every entry, starred ones included
+pub fn clear(store: &dyn SettingsStore) -> Result<(), String> {
+    let path = history_path(store)?;
+    let _guard = FILE_LOCK.lock().map_err(|e| e.to_string())?;
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Write the whole history (oldest first) to `out`; returns the entry count
+pub fn export<W: Write>(
+    store: &dyn SettingsStore,
+    format: ExportFormat,
+    out: &mut W,
+) -> Result<usize, String> {
+    let path = history_path(store)?;
+    let entries = {
+        let _guard = FILE_LOCK.lock().map_err(|e| e.to_string())?;
+        load(&path).map_err(|e| e.to_string())?
+    };
+    let written = match format {
+        ExportFormat::Csv => write_csv(&entries, out),
+        ExportFormat::Json => serde_json::to_writer_pretty(&mut *out, &entries)
+            .map_err(io::Error::from)
+            .and_then(|_| out.write_all(b"\n")),
+        ExportFormat::Markdown => write_markdown(&entries, out),
+    };
+    written.map_err(|e| e.to_string())?;
+    Ok(entries.len())
+}
+
+fn write_csv<W: Write>(entries: &[HistoryEntry], out: &mut W) -> io::Result<()> {
+    writeln!(
+        out,
+        "date,source,translation,model,mode,window,cost_usd,starred"
+    )?;
+    for entry in entries {
+        let cost = entry.cost.map(|c| format!("{:.6}", c)).unwrap_or_default();
+        writeln!(
+            out,
+            "{},{},{},{},{},{},{},{}",
+            format_date(entry.timestamp),
+            csv_field(&entry.source),
+            csv_field(&entry.translation),
+            csv_field(&entry.model),
+            mode_name(entry.mode),
+            window_name(entry.window),
+            cost,
+            entry.starred
+        )?;
+    }
+    Ok(())
+}
+
+/// RFC 4180: quote fields with commas, quotes or line breaks, doubling quotes
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_markdown<W: Write>(entries: &[HistoryEntry], out: &mut W) -> io::Result<()> {
+    writeln!(out, "# TrayLingo history")?;
+    for entry in entries {
+        let star = if entry.starred { " ★" } else { "" };
+        writeln!(out)?;
+        writeln!(
+            out,
+            "## {}{}\n\n_{} · {} · {}_\n",
+            format_date(entry.timestamp),
+            star,
+            entry.model,
+            mode_name(entry.mode),
+            window_name(entry.window)
+        )?;
+        for line in entry.source.lines() {
+            writeln!(out, "> {}", line)?;
+        }
+        writeln!(out, "\n{}", entry.translation.trim_end())?;
+    }
+    Ok(())
+}
+
+fn mode_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Translation => "translation",
+        Mode::Markdown => "markdown",
+        Mode::Dictionary => "dictionary",
+    }
+}
+
+fn window_name(window: Window) -> &'static str {
+    match window {
+        Window::Main => "main",
+        Window::Popup => "popup",
+    }
+}
+
+/// Unix seconds as ISO 8601 (`2026-10-18T09:30:00Z`)
+fn format_date(timestamp: i64) -> String {
+    let (days, seconds) = (
+        timestamp.div_euclid(DAY_SECS),
+        timestamp.rem_euclid(DAY_SECS),
+    );
+    let (year, month, day) = tmx::civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Random 16-hex-digit id (time-ordered ids would collide across processes)
+fn new_id() -> String {
+    let mut bytes = [0u8; 8];
+    if getrandom::getrandom(&mut bytes).is_err() {
+        // Fall back to the clock; ids only need to be unique within the file
+        bytes = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+            .to_be_bytes();
+    }
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::TempStore;
+
+    fn temp_store(name: &str, enabled: bool) -> TempStore {
+        let store = TempStore::new(&format!("history-{}", name));
+        let current = Settings {
+            history_enabled: enabled,
+            ..Settings::default()
+        };
+        settings::save_settings(&store, &current).unwrap();
+        store
+    }
+
+    fn entry(source: &str, translation: &str, timestamp: i64) -> HistoryEntry {
+        HistoryEntry {
+            timestamp,
+            ..HistoryEntry::new(
+                source,
+                translation,
+                "claude-haiku-4-5-20251001",
+                Mode::Translation,
+                Some(0.001),
+                Window::Main,
+            )
+        }
+    }
+
+    #[test]
+    fn test_records_only_when_enabled() {
+        let store = temp_store("disabled", false);
+        add(&store, entry("Hello", "こんにちは", now())).unwrap();
+        assert_eq!(search(&store, "", false, 0, 50).unwrap().total, 0);
+
+        let store = temp_store("enabled", true);
+        add(&store, entry("Hello", "こんにちは", now())).unwrap();
+        // Credential-like text is never written
+        add(
+            &store,
+            entry("key sk-ant-REDACTED", "キー", now()),
+        )
+        .unwrap();
+        assert_eq!(search(&store, "", false, 0, 50).unwrap().total, 1);
+
+        set_incognito(true);
+        record(&store, entry("Incognito", "シークレット", now())).unwrap();
+        set_incognito(false);
+        record(&store, entry("Visible", "見える", now())).unwrap();
+        let page = search(&store, "", false, 0, 50).unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.entries[0].source, "Visible");
+    }
+
+    #[test]
+    fn test_privacy_mode_skips_redacted_text() {
+        let store = temp_store("privacy", true);
+        let current = Settings {
+            privacy_mode: true,
+            ..settings::get_settings(&store)
+        };
+        settings::save_settings(&store, &current).unwrap();
+
+        add(&store, entry("Hello", "こんにちは", now())).unwrap();
+        add(
+            &store,
+            entry(
+                "Mail alice@example.com or call 090-1234-5678",
+                "alice@example.com にメールするか 090-1234-5678 に電話してください",
+                now(),
+            ),
+        )
+        .unwrap();
+        let page = search(&store, "", false, 0, 50).unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.entries[0].source, "Hello");
+    }
+
+    #[test]
+    fn test_search_star_and_delete() {
+        let store = temp_store("search", true);
+        add(&store, entry("Good morning", "おはよう", 100)).unwrap();
+        add(&store, entry("Good night", "おやすみ", 200)).unwrap();
+        add(&store, entry("Thank you", "ありがとう", 300)).unwrap();
+
+        // Every word must match, in either text, ignoring case
+        let page = search(&store, "good NIGHT", false, 0, 50).unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.entries[0].translation, "おやすみ");
+        assert_eq!(search(&store, "おはよう", false, 0, 50).unwrap().total, 1);
+
+        // Newest first, paged
+        let page = search(&store, "", false, 1, 1).unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.entries[0].source, "Good night");
+
+        let id = page.entries[0].id.clone();
+        assert!(set_starred(&store, &id, true).unwrap());
+        let starred = search(&store, "", true, 0, 50).unwrap();
+        assert_eq!(starred.total, 1);
+        assert!(starred.entries[0].starred);
+
+        assert!(delete(&store, &id).unwrap());
+        assert!(!delete(&store, &id).unwrap());
+        assert!(!set_starred(&store, &id, true).unwrap());
+        assert_eq!(search(&store, "", false, 0, 50).unwrap().total, 2);
+
+        clear(&store).unwrap();
+        assert_eq!(search(&store, "", false, 0, 50).unwrap().total, 0);
+    }
+
+    #[test]
+    fn test_prune_keeps_starred() {
+        let now = 1_000 * DAY_SECS;
+        let mut entries: Vec<HistoryEntry> = (0..15)
+            .map(|i| entry(&format!("text {}", i), "訳", now - 15 + i))
+            .collect();
+        entries[0].starred = true;
+        let mut old = entry("old", "古い", now - 100 * DAY_SECS);
+        old.starred = false;
+        entries.insert(0, old);
+
+        prune(&mut entries, MIN_LIMIT, 90, now);
+        // The old entry expired; the starred one doesn't count towards the limit
+        assert_eq!(entries.len(), MIN_LIMIT + 1);
+        assert!(entries.iter().all(|e| e.source != "old"));
+        assert_eq!(entries[0].source, "text 0");
+        assert_eq!(entries[1].source, "text 5");
+
+        // Retention 0 keeps entries of any age
+        let mut entries = vec![entry("ancient", "昔", 0)];
+        prune(&mut entries, DEFAULT_LIMIT, 0, now);
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn test_export_formats() {
+        let store = temp_store("export", true);
+        add(&store, entry("Hello, \"world\"", "こんにちは\n世界", 0)).unwrap();
+
+        let mut csv = Vec::new();
+        assert_eq!(export(&store, ExportFormat::Csv, &mut csv).unwrap(), 1);
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("date,source,translation,"));
+        assert!(csv.contains(
+            "1970-01-01T00:00:00Z,\"Hello, \"\"world\"\"\",\"こんにちは\n世界\",\
+             claude-haiku-4-5-20251001,translation,main,0.001000,false"
+        ));
+
+        let mut json = Vec::new();
+        export(&store, ExportFormat::Json, &mut json).unwrap();
+        let parsed: Vec<HistoryEntry> = serde_json::from_slice(&json).unwrap();
+        assert_eq!(parsed[0].translation, "こんにちは\n世界");
+
+        let mut markdown = Vec::new();
+        export(&store, ExportFormat::Markdown, &mut markdown).unwrap();
+        let markdown = String::from_utf8(markdown).unwrap();
+        assert!(markdown.contains("## 1970-01-01T00:00:00Z"));
+        assert!(markdown.contains("> Hello, \"world\""));
+    }
+}
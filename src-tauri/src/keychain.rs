@@ -2,43 +2,82 @@ use std::process::Command;
 
 const SERVICE_NAME: &str = "com.ebiyy.traylingo";
 const ACCOUNT_NAME: &str = "anthropic_api_key";
+/// Data key the translation cache is encrypted with (see cache.rs)
+const CACHE_KEY_ACCOUNT: &str = "translation_cache_key";
 
 // WHY: Use absolute path to prevent PATH hijacking attacks
 const SECURITY_CMD: &str = "/usr/bin/security";
+/// `security` exit status for errSecItemNotFound
+const ITEM_NOT_FOUND_EXIT: i32 = 44;
 
 /// Get API key from macOS Keychain using `security` command
 pub fn get_api_key() -> Option<String> {
+    find_password(ACCOUNT_NAME)
+}
+
+/// Save API key to macOS Keychain using `security` command
+pub fn set_api_key(key: &str) -> Result<(), String> {
+    log::info!("Attempting to save API key to Keychain...");
+    add_password(ACCOUNT_NAME, key)?;
+    log::info!("API key saved to Keychain successfully");
+    Ok(())
+}
+
+/// Delete API key from macOS Keychain using `security` command
+pub fn delete_api_key() -> Result<(), String> {
+    delete_password(ACCOUNT_NAME)
+}
+
+/// Check if API key exists in Keychain
+pub fn has_api_key() -> bool {
+    get_api_key().is_some()
+}
+
+/// Get the translation cache's data key (base64). Ok(None) only when there is none;
+/// a locked Keychain or a denied prompt is an error.
+pub fn get_cache_key() -> Result<Option<String>, String> {
+    lookup_password(CACHE_KEY_ACCOUNT)
+}
+
+/// Save the translation cache's data key (base64), replacing the old one
+pub fn set_cache_key(key: &str) -> Result<(), String> {
+    add_password(CACHE_KEY_ACCOUNT, key)
+}
+
+fn find_password(account: &str) -> Option<String> {
+    lookup_password(account).ok().flatten()
+}
+
+fn lookup_password(account: &str) -> Result<Option<String>, String> {
     let output = Command::new(SECURITY_CMD)
         .args([
             "find-generic-password",
             "-s",
             SERVICE_NAME,
             "-a",
-            ACCOUNT_NAME,
+            account,
             "-w", // Output only the password
         ])
         .output()
-        .ok()?;
+        .map_err(|e| format!("Failed to execute security command: {}", e))?;
 
+    let stderr = String::from_utf8_lossy(&output.stderr);
     if output.status.success() {
         let password = String::from_utf8_lossy(&output.stdout);
         let password = password.trim();
-        if password.is_empty() {
-            None
-        } else {
-            Some(password.to_string())
-        }
+        Ok((!password.is_empty()).then(|| password.to_string()))
+    } else if output.status.code() == Some(ITEM_NOT_FOUND_EXIT)
+        || stderr.contains("could not be found")
+    {
+        Ok(None)
     } else {
-        None
+        Err(format!("Keychain read error: {}", stderr.trim()))
     }
 }
 
-/// Save API key to macOS Keychain using `security` command
-pub fn set_api_key(key: &str) -> Result<(), String> {
-    log::info!("Attempting to save API key to Keychain...");
-
+fn add_password(account: &str, password: &str) -> Result<(), String> {
     // First, try to delete any existing entry (ignore errors)
-    let _ = delete_api_key();
+    let _ = delete_password(account);
 
     // Add the new password
     let output = Command::new(SECURITY_CMD)
@@ -47,16 +86,15 @@ pub fn set_api_key(key: &str) -> Result<(), String> {
             "-s",
             SERVICE_NAME,
             "-a",
-            ACCOUNT_NAME,
+            account,
             "-w",
-            key,
+            password,
             "-U", // Update if exists
         ])
         .output()
         .map_err(|e| format!("Failed to execute security command: {}", e))?;
 
     if output.status.success() {
-        log::info!("API key saved to Keychain successfully");
         Ok(())
     } else {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -65,16 +103,9 @@ pub fn set_api_key(key: &str) -> Result<(), String> {
     }
 }
 
-/// Delete API key from macOS Keychain using `security` command
-pub fn delete_api_key() -> Result<(), String> {
+fn delete_password(account: &str) -> Result<(), String> {
     let output = Command::new(SECURITY_CMD)
-        .args([
-            "delete-generic-password",
-            "-s",
-            SERVICE_NAME,
-            "-a",
-            ACCOUNT_NAME,
-        ])
+        .args(["delete-generic-password", "-s", SERVICE_NAME, "-a", account])
         .output()
         .map_err(|e| format!("Failed to execute security command: {}", e))?;
 
@@ -88,8 +119,3 @@ pub fn delete_api_key() -> Result<(), String> {
         Err(format!("Keychain delete error: {}", stderr.trim()))
     }
 }
-
-/// Check if API key exists in Keychain
-pub fn has_api_key() -> bool {
-    get_api_key().is_some()
-}
@@ -3,11 +3,14 @@ use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tauri::{
     image::Image,
-    menu::{Menu, MenuItem},
+    menu::{CheckMenuItem, Menu, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder, TrayIconEvent},
     Emitter, Manager, RunEvent, WindowEvent,
 };
+use tauri_plugin_deep_link::DeepLinkExt;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons};
 use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Modifiers, Shortcut, ShortcutState};
+use tauri_plugin_notification::NotificationExt;
 
 static POPUP_READY: AtomicBool = AtomicBool::new(false);
 
@@ -18,20 +21,411 @@ static POPUP_READY: AtomicBool = AtomicBool::new(false);
 static SENTRY_GUARD: Mutex<Option<sentry::ClientInitGuard>> = Mutex::new(None);
 
 mod anthropic;
+mod api_server;
+mod batch;
+mod cache;
+mod cli;
+mod deep_link;
+mod dictionary;
+mod engine;
 mod error;
+mod history;
 mod keychain;
+mod markdown;
+mod mcp;
+#[cfg(test)]
+mod mock_server;
+mod offline_dictionary;
+mod placeholders;
+mod reading;
+mod redaction;
+mod refinement;
+mod secrets;
 mod settings;
+mod tmx;
+mod translation_memory;
+mod validation;
+mod verification;
+
+use anthropic::Usage;
+use cache::Fingerprint;
+use engine::{
+    ChunkPayload, DonePayload, EventSink, FlaggedPayload, MemoryMatchPayload, Storage,
+    TranslateContext, TranslateOptions, UsagePayload, VerificationPayload,
+};
+use error::TranslateError;
+use redaction::RedactionReport;
+use settings::{ErrorHistoryEntry, Settings, SettingsStore, UsageStats};
+use translation_memory::MemoryMatch;
+
+// ==================== Engine (Tauri implementations) ====================
+
+/// Engine events as Tauri events. Flagged/redaction results without a session
+/// belong to a popup translation and go to the popup window only.
+pub(crate) struct TauriSink(tauri::AppHandle);
+
+impl TauriSink {
+    pub(crate) fn new(app: &tauri::AppHandle) -> Self {
+        Self(app.clone())
+    }
+}
+
+impl EventSink for TauriSink {
+    fn chunk(&self, payload: ChunkPayload) {
+        let _ = self.0.emit("translate-chunk", payload);
+    }
+
+    fn replace(&self, payload: ChunkPayload) {
+        let _ = self.0.emit("translate-replace", payload);
+    }
+
+    fn usage(&self, payload: UsagePayload) {
+        let _ = self.0.emit("translate-usage", payload);
+    }
+
+    fn done(&self, payload: DonePayload) {
+        let _ = self.0.emit("translate-done", payload);
+    }
+
+    fn flagged(&self, payload: FlaggedPayload) {
+        let _ = match payload.session_id {
+            Some(_) => self.0.emit("translate-flagged", payload),
+            None => self.0.emit_to("popup", "translate-flagged", payload),
+        };
+    }
+
+    fn redaction(&self, report: RedactionReport) {
+        let _ = match report.session_id {
+            Some(_) => self.0.emit("translate-redaction", report),
+            None => self.0.emit_to("popup", "translate-redaction", report),
+        };
+    }
+
+    fn memory_match(&self, payload: MemoryMatchPayload) {
+        let _ = match payload.session_id {
+            Some(_) => self.0.emit("translate-memory-match", payload),
+            None => self.0.emit_to("popup", "translate-memory-match", payload),
+        };
+    }
+
+    fn verification(&self, payload: VerificationPayload) {
+        let _ = self.0.emit("translate-verification", payload);
+    }
+}
+
+/// Cache files and settings.json (the Tauri store, or the file in the CLI)
+pub(crate) struct SettingsStorage<S>(pub(crate) S);
+
+impl<S: SettingsStore> Storage for SettingsStorage<S> {
+    fn cached_translation(&self, text: &str, fingerprint: &Fingerprint) -> Option<String> {
+        cache::get_cached_translation(&self.0, text, fingerprint)
+    }
+
+    fn save_translation(
+        &self,
+        text: &str,
+        translation: &str,
+        fingerprint: &Fingerprint,
+    ) -> Result<(), String> {
+        cache::save_cached_translation(&self.0, text, translation, fingerprint)
+    }
+
+    fn save_error(&self, entry: ErrorHistoryEntry) {
+        let _ = settings::save_error(&self.0, entry);
+    }
+
+    fn record_usage(&self, usage: &UsagePayload) {
+        let _ = settings::record_usage(
+            &self.0,
+            usage.model.as_deref(),
+            usage.prompt_tokens,
+            usage.completion_tokens,
+            usage.estimated_cost,
+            usage.cached,
+        );
+    }
+
+    fn record_request_usage(&self, model: &str, usage: &Usage) {
+        anthropic::record_request_usage(&self.0, model, usage);
+    }
+
+    fn memory_match(&self, text: &str) -> Option<MemoryMatch> {
+        translation_memory::lookup(&self.0, text)
+    }
+
+    fn save_memory(&self, source: &str, translation: &str) {
+        let _ = translation_memory::record(&self.0, source, translation);
+    }
+}
+
+// ==================== Local API server ====================
+
+/// Local API backed by the app's settings, keychain and store
+struct TauriBackend(SettingsStorage<tauri::AppHandle>);
+
+impl api_server::Backend for TauriBackend {
+    fn token(&self) -> String {
+        settings::get_settings(&self.0 .0).api_server_token
+    }
+
+    fn options(&self) -> Result<TranslateOptions, TranslateError> {
+        let api_key = keychain::get_api_key().ok_or(TranslateError::ApiKeyMissing)?;
+        let current_settings = settings::get_settings(&self.0 .0);
+        Ok(TranslateOptions::from_settings(&current_settings, api_key))
+    }
+
+    fn storage(&self) -> &dyn Storage {
+        &self.0
+    }
+
+    fn usage(&self) -> UsageStats {
+        settings::get_usage_stats(&self.0 .0)
+    }
+}
+
+/// Running local API server (None while disabled)
+#[derive(Default)]
+struct ApiServerState(Mutex<Option<api_server::ApiServer>>);
+
+/// Start, restart (port changed) or stop the local API to match the settings
+fn apply_api_server_settings(app: &tauri::AppHandle) -> Result<(), String> {
+    let current_settings = settings::get_settings(app);
+    let state = app.state::<ApiServerState>();
+    let mut running = state.0.lock().unwrap();
+    if !current_settings.api_server_enabled {
+        *running = None; // Dropping the handle stops the server
+        return Ok(());
+    }
+    if running
+        .as_ref()
+        .is_some_and(|server| server.port() == current_settings.api_server_port)
+    {
+        return Ok(());
+    }
+
+    *running = None;
+    settings::ensure_api_server_token(app)?;
+    let backend = Arc::new(TauriBackend(SettingsStorage(app.clone())));
+    let (server, serve) = api_server::bind(current_settings.api_server_port, backend)?;
+    tauri::async_runtime::spawn(serve);
+    log::info!("Local API listening on 127.0.0.1:{}", server.port());
+    *running = Some(server);
+    Ok(())
+}
+
+// ==================== Batch translation ====================
+
+fn notify(app: &tauri::AppHandle, body: String) {
+    let _ = app
+        .notification()
+        .builder()
+        .title("TrayLingo")
+        .body(body)
+        .show();
+}
+
+fn batch_options(app: &tauri::AppHandle) -> Result<TranslateOptions, TranslateError> {
+    let api_key = keychain::get_api_key().ok_or(TranslateError::ApiKeyMissing)?;
+    Ok(TranslateOptions::from_settings(
+        &settings::get_settings(app),
+        api_key,
+    ))
+}
+
+/// Check unfinished batch jobs every POLL_INTERVAL, starting at launch (jobs may
+/// have ended while the app was closed). Changes are emitted as `batch-updated`.
+fn spawn_batch_poller(app: tauri::AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if batch::has_pending(&app) {
+                if let Ok(options) = batch_options(&app) {
+                    let storage = SettingsStorage(app.clone());
+                    for job in batch::poll(&app, &storage, &options).await {
+                        if let Some(result) = &job.result {
+                            notify(
+                                &app,
+                                format!(
+                                    "Batch translation finished: {} cached, {} flagged, {} failed (${:.4})",
+                                    result.cached,
+                                    result.flagged,
+                                    result.failed,
+                                    result.estimated_cost
+                                ),
+                            );
+                        }
+                        let _ = app.emit("batch-updated", job);
+                    }
+                }
+            }
+            tokio::time::sleep(batch::POLL_INTERVAL).await;
+        }
+    });
+}
+
+/// Send texts to the Message Batches API; results go to the translation cache
+/// when the job ends
+#[tauri::command]
+async fn submit_batch(
+    app: tauri::AppHandle,
+    texts: Vec<String>,
+) -> Result<batch::Submission, String> {
+    let submission = async {
+        let options = batch_options(&app)?;
+        let storage = SettingsStorage(app.clone());
+        batch::submit(&app, &storage, &options, &texts).await
+    }
+    .await
+    .map_err(|err| serde_json::to_string(&err).unwrap_or_else(|_| err.to_string()))?;
+
+    if let Some(job) = &submission.job {
+        notify(
+            &app,
+            format!(
+                "Batch submitted: {} texts. Results arrive within 24 hours.",
+                job.total
+            ),
+        );
+        let _ = app.emit("batch-updated", job);
+    }
+    Ok(submission)
+}
+
+#[tauri::command]
+fn get_batch_jobs(app: tauri::AppHandle) -> Vec<batch::BatchJob> {
+    batch::list_jobs(&app)
+}
+
+#[tauri::command]
+async fn cancel_batch(app: tauri::AppHandle, id: String) -> Result<batch::BatchJob, String> {
+    let options = batch_options(&app)
+        .map_err(|err| serde_json::to_string(&err).unwrap_or_else(|_| err.to_string()))?;
+    batch::cancel(&app, &options, &id)
+        .await
+        .map_err(|err| serde_json::to_string(&err).unwrap_or_else(|_| err.to_string()))
+}
+
+#[tauri::command]
+fn clear_finished_batches(app: tauri::AppHandle) -> Result<(), String> {
+    batch::clear_finished(&app)
+}
+
+/// Refuse credential-like text unless the user confirmed sending it anyway
+fn check_sensitive(texts: &[&str], allow_sensitive: Option<bool>) -> Result<(), String> {
+    secrets::check(texts, allow_sensitive.unwrap_or(false))
+        .map_err(|err| serde_json::to_string(&err).unwrap_or_else(|_| err.to_string()))
+}
+
+#[tauri::command]
+async fn translate(
+    app: tauri::AppHandle,
+    text: String,
+    session_id: String,
+    allow_sensitive: Option<bool>,
+) -> Result<(), String> {
+    check_sensitive(&[&text], allow_sensitive)?;
+    let api_key = keychain::get_api_key().ok_or_else(|| {
+        let err = error::TranslateError::ApiKeyMissing;
+        serde_json::to_string(&err).unwrap()
+    })?;
+    let current_settings = settings::get_settings(&app);
+    let sink = TauriSink::new(&app);
+    let storage = SettingsStorage(app.clone());
+    let meter = history::CostMeter::new(&storage);
+    let ctx = TranslateContext {
+        sink: &sink,
+        storage: &meter,
+        options: TranslateOptions::from_settings(&current_settings, api_key),
+    };
+    let translation = engine::translate(&ctx, &text, &session_id)
+        .await
+        .map_err(|err| serde_json::to_string(&err).unwrap_or_else(|_| err.to_string()))?;
+
+    let mode = if markdown::looks_like_markdown(&text) {
+        history::Mode::Markdown
+    } else {
+        history::Mode::Translation
+    };
+    record_history(
+        &app,
+        history::HistoryEntry::new(
+            &text,
+            &translation,
+            &current_settings.model,
+            mode,
+            meter.cost(),
+            history::Window::Main,
+        ),
+    );
+
+    // Keep the result so follow-ups ("more formal") can refine it
+    app.state::<refinement::RefinementSessions>()
+        .start(session_id, text, translation);
+    Ok(())
+}
+
+/// Refine the session's latest translation with a follow-up instruction
+#[tauri::command]
+async fn refine_translation(
+    app: tauri::AppHandle,
+    session_id: String,
+    instruction: String,
+    allow_sensitive: Option<bool>,
+) -> Result<String, String> {
+    check_sensitive(&[&instruction], allow_sensitive)?;
+    let api_key = keychain::get_api_key().ok_or_else(|| {
+        let err = error::TranslateError::ApiKeyMissing;
+        serde_json::to_string(&err).unwrap()
+    })?;
+    let current_settings = settings::get_settings(&app);
+    let sink = TauriSink::new(&app);
+    let storage = SettingsStorage(app.clone());
+    let ctx = TranslateContext {
+        sink: &sink,
+        storage: &storage,
+        options: TranslateOptions::from_settings(&current_settings, api_key),
+    };
+    let sessions = app.state::<refinement::RefinementSessions>();
+    refinement::refine(&ctx, &sessions, &session_id, &instruction)
+        .await
+        .map_err(|err| serde_json::to_string(&err).unwrap_or_else(|_| err.to_string()))
+}
 
-use settings::Settings;
+#[tauri::command]
+fn get_refinement_session(
+    app: tauri::AppHandle,
+    session_id: String,
+) -> Option<refinement::Conversation> {
+    app.state::<refinement::RefinementSessions>()
+        .get(&session_id)
+}
 
+/// Back-translate a finished translation and report semantic drift
+/// (result is emitted as `translate-verification`)
 #[tauri::command]
-async fn translate(app: tauri::AppHandle, text: String, session_id: String) -> Result<(), String> {
+async fn verify_translation(
+    app: tauri::AppHandle,
+    source: String,
+    translation: String,
+    session_id: String,
+    allow_sensitive: Option<bool>,
+) -> Result<(), String> {
+    check_sensitive(&[&source, &translation], allow_sensitive)?;
     let api_key = keychain::get_api_key().ok_or_else(|| {
         let err = error::TranslateError::ApiKeyMissing;
         serde_json::to_string(&err).unwrap()
     })?;
     let current_settings = settings::get_settings(&app);
-    anthropic::translate_stream(app, text, session_id, api_key, current_settings.model).await
+    let sink = TauriSink::new(&app);
+    let storage = SettingsStorage(app.clone());
+    let ctx = TranslateContext {
+        sink: &sink,
+        storage: &storage,
+        options: TranslateOptions::from_settings(&current_settings, api_key),
+    };
+    verification::verify(&ctx, &source, &translation, session_id)
+        .await
+        .map(|_| ())
+        .map_err(|err| serde_json::to_string(&err).unwrap_or_else(|_| err.to_string()))
 }
 
 #[tauri::command]
@@ -40,8 +434,20 @@ fn get_settings(app: tauri::AppHandle) -> Settings {
 }
 
 #[tauri::command]
-fn save_settings(app: tauri::AppHandle, new_settings: Settings) -> Result<(), String> {
-    settings::save_settings(&app, &new_settings)
+fn save_settings(app: tauri::AppHandle, mut new_settings: Settings) -> Result<(), String> {
+    // The token only changes through regenerate_api_server_token
+    new_settings.api_server_token = settings::get_settings(&app).api_server_token;
+    settings::save_settings(&app, &new_settings)?;
+    apply_api_server_settings(&app)
+}
+
+/// Replace the local API token; clients using the old one get 401 from now on
+#[tauri::command]
+fn regenerate_api_server_token(app: tauri::AppHandle) -> Result<String, String> {
+    let mut current_settings = settings::get_settings(&app);
+    current_settings.api_server_token = settings::generate_api_server_token()?;
+    settings::save_settings(&app, &current_settings)?;
+    Ok(current_settings.api_server_token)
 }
 
 #[tauri::command]
@@ -54,7 +460,152 @@ fn get_available_models() -> Vec<(String, String)> {
 
 #[tauri::command]
 fn clear_translation_cache(app: tauri::AppHandle) -> Result<(), String> {
-    settings::clear_translation_cache(&app)
+    cache::clear(&app)?;
+    translation_memory::clear(&app)
+}
+
+#[tauri::command]
+fn get_cache_stats(app: tauri::AppHandle) -> cache::CacheStats {
+    cache::get_cache_stats(&app)
+}
+
+/// Cache browser page, most recently used first (`query` searches previews and values)
+#[tauri::command]
+fn list_cache_entries(
+    app: tauri::AppHandle,
+    query: String,
+    offset: usize,
+    limit: usize,
+) -> Result<cache::CachePage, String> {
+    cache::list_entries(&app, &query, offset, limit)
+}
+
+#[tauri::command]
+fn delete_cache_entry(app: tauri::AppHandle, id: String) -> Result<bool, String> {
+    cache::delete_entry(&app, &id)
+}
+
+#[tauri::command]
+fn reset_translation_cache_key(app: tauri::AppHandle) -> Result<(), String> {
+    cache::rotate_key(&app)?;
+    translation_memory::clear(&app)?;
+    batch::discard_texts(&app)
+}
+
+/// Write the translation memory to a TMX 1.4b file; returns the units written
+#[tauri::command]
+fn export_translation_memory(app: tauri::AppHandle, path: String) -> Result<usize, String> {
+    let segments = translation_memory::segments(&app)?;
+    let mut file =
+        std::fs::File::create(&path).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+    tmx::write_tmx(&segments, &mut file)
+}
+
+/// Merge a TMX file into the translation memory (duplicates per `policy`,
+/// default: keep the newer)
+#[tauri::command]
+fn import_translation_memory(
+    app: tauri::AppHandle,
+    path: String,
+    policy: Option<translation_memory::MergePolicy>,
+) -> Result<translation_memory::ImportSummary, String> {
+    let file = std::fs::File::open(&path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let segments = tmx::parse_tmx(std::io::BufReader::new(file))?;
+    translation_memory::import(&app, segments, policy.unwrap_or_default())
+}
+
+// ==================== History Commands ====================
+
+/// Tray "Incognito" item, kept in sync when the window toggles incognito
+struct IncognitoMenuItem(CheckMenuItem<tauri::Wry>);
+
+/// Search the translation history, newest first
+#[tauri::command]
+fn search_history(
+    app: tauri::AppHandle,
+    query: Option<String>,
+    starred_only: Option<bool>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> Result<history::HistoryPage, String> {
+    history::search(
+        &app,
+        query.as_deref().unwrap_or(""),
+        starred_only.unwrap_or(false),
+        offset.unwrap_or(0),
+        limit.unwrap_or(50),
+    )
+}
+
+#[tauri::command]
+fn set_history_starred(app: tauri::AppHandle, id: String, starred: bool) -> Result<bool, String> {
+    history::set_starred(&app, &id, starred)
+}
+
+#[tauri::command]
+fn delete_history_entry(app: tauri::AppHandle, id: String) -> Result<bool, String> {
+    history::delete(&app, &id)
+}
+
+#[tauri::command]
+fn clear_history(app: tauri::AppHandle) -> Result<(), String> {
+    history::clear(&app)
+}
+
+/// Write the whole history as CSV, JSON or Markdown to `path`, or to a file
+/// picked in a save dialog; returns the entries written (None if cancelled)
+#[tauri::command]
+async fn export_history(
+    app: tauri::AppHandle,
+    path: Option<String>,
+    format: history::ExportFormat,
+) -> Result<Option<usize>, String> {
+    let path = match path {
+        Some(path) => std::path::PathBuf::from(path),
+        None => {
+            let (name, extension) = match format {
+                history::ExportFormat::Csv => ("CSV", "csv"),
+                history::ExportFormat::Json => ("JSON", "json"),
+                history::ExportFormat::Markdown => ("Markdown", "md"),
+            };
+            // WHY: async command, so the blocking dialog doesn't run on the main thread
+            let picked = app
+                .dialog()
+                .file()
+                .set_file_name(format!("traylingo-history.{}", extension))
+                .add_filter(name, &[extension])
+                .blocking_save_file();
+            match picked {
+                Some(picked) => picked.into_path().map_err(|e| e.to_string())?,
+                None => return Ok(None),
+            }
+        }
+    };
+    let file = std::fs::File::create(&path)
+        .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut out = std::io::BufWriter::new(file);
+    let count = history::export(&app, format, &mut out)?;
+    std::io::Write::flush(&mut out).map_err(|e| e.to_string())?;
+    Ok(Some(count))
+}
+
+#[tauri::command]
+fn get_incognito() -> bool {
+    history::is_incognito()
+}
+
+#[tauri::command]
+fn set_incognito(app: tauri::AppHandle, enabled: bool) {
+    apply_incognito(&app, enabled);
+}
+
+/// Switch incognito and update the tray item and the windows
+fn apply_incognito(app: &tauri::AppHandle, enabled: bool) {
+    history::set_incognito(enabled);
+    if let Some(item) = app.try_state::<IncognitoMenuItem>() {
+        let _ = item.0.set_checked(enabled);
+    }
+    let _ = app.emit("incognito-changed", enabled);
 }
 
 // ==================== API Key (Keychain) Commands ====================
@@ -332,6 +883,44 @@ fn show_popup(app: &tauri::AppHandle, clipboard_text: Option<String>) {
     }
 }
 
+/// Handle a `traylingo://` link: validate, confirm, then translate in the popup or window
+fn handle_deep_link(app: &tauri::AppHandle, url: &tauri::Url) {
+    let link = match deep_link::parse(url) {
+        Ok(link) => link,
+        Err(message) => {
+            log::warn!("Ignored deep link: {}", message);
+            notify(app, format!("Ignored traylingo:// link: {}", message));
+            return;
+        }
+    };
+
+    // WHY: Any app or web page can open a traylingo:// URL. Confirming keeps it from
+    // sending text to the API (and spending the user's credits) unnoticed.
+    let app_handle = app.clone();
+    app.dialog()
+        .message(format!(
+            "Another app asked TrayLingo to translate:\n\n{}",
+            deep_link::preview(&link.text)
+        ))
+        .title("Translate text from a link?")
+        .buttons(MessageDialogButtons::OkCancelCustom(
+            "Translate".to_string(),
+            "Cancel".to_string(),
+        ))
+        .show(move |confirmed| {
+            if !confirmed {
+                return;
+            }
+            match link.mode {
+                deep_link::Mode::Popup => show_popup(&app_handle, Some(link.text)),
+                deep_link::Mode::Window => {
+                    show_window(&app_handle);
+                    let _ = app_handle.emit_to("main", "deep-link-text", link.text);
+                }
+            }
+        });
+}
+
 fn hide_popup(app: &tauri::AppHandle) {
     if let Some(window) = app.get_webview_window("popup") {
         let _ = window.hide();
@@ -343,13 +932,155 @@ fn hide_popup(app: &tauri::AppHandle) {
 }
 
 #[tauri::command]
-async fn quick_translate(app: tauri::AppHandle, text: String) -> Result<String, String> {
+async fn quick_translate(
+    app: tauri::AppHandle,
+    text: String,
+    allow_sensitive: Option<bool>,
+) -> Result<String, String> {
+    // Offline hit needs neither an API key nor the network
+    if dictionary::is_dictionary_candidate(&text) {
+        if let Some(entry) = offline_dictionary::lookup(&app, &text) {
+            let summary = entry.summary();
+            let _ = app.emit_to("popup", "dictionary-result", entry);
+            record_history(
+                &app,
+                history::HistoryEntry::new(
+                    &text,
+                    &summary,
+                    "offline",
+                    history::Mode::Dictionary,
+                    Some(0.0),
+                    history::Window::Popup,
+                ),
+            );
+            return Ok(summary);
+        }
+    }
+
+    check_sensitive(&[&text], allow_sensitive)?;
     let api_key = keychain::get_api_key().ok_or_else(|| {
         let err = error::TranslateError::ApiKeyMissing;
         serde_json::to_string(&err).unwrap()
     })?;
     let current_settings = settings::get_settings(&app);
-    anthropic::translate_once(&app, text, api_key, current_settings.model).await
+    let options = TranslateOptions::from_settings(&current_settings, api_key);
+
+    let sink = TauriSink::new(&app);
+    let storage = SettingsStorage(app.clone());
+    let meter = history::CostMeter::new(&storage);
+
+    // Single words/short phrases: structured dictionary entry instead of a sentence
+    if dictionary::is_dictionary_candidate(&text) {
+        match dictionary::lookup(&app, &meter, &text, &options).await {
+            Ok(entry) => {
+                let summary = entry.summary();
+                let _ = app.emit_to("popup", "dictionary-result", entry);
+                record_history(
+                    &app,
+                    history::HistoryEntry::new(
+                        &text,
+                        &summary,
+                        &current_settings.model,
+                        history::Mode::Dictionary,
+                        meter.cost(),
+                        history::Window::Popup,
+                    ),
+                );
+                return Ok(summary);
+            }
+            // Unparseable dictionary output: fall back to plain translation
+            Err(error::TranslateError::ParseError { message }) => {
+                log::warn!("Dictionary lookup failed, falling back: {}", message);
+            }
+            // Privacy mode: plain translation can redact the personal data
+            Err(error::TranslateError::PrivateContentBlocked { .. }) => {
+                log::info!("Dictionary lookup skipped in privacy mode, translating instead");
+            }
+            Err(err) => {
+                return Err(serde_json::to_string(&err).unwrap_or_else(|_| err.to_string()));
+            }
+        }
+    }
+
+    let ctx = TranslateContext {
+        sink: &sink,
+        storage: &meter,
+        options,
+    };
+    let (result, mode) = if markdown::looks_like_markdown(&text) {
+        (
+            anthropic::translate_markdown(&ctx, &text, None).await,
+            history::Mode::Markdown,
+        )
+    } else {
+        (
+            anthropic::translate_once(&ctx, text.clone()).await,
+            history::Mode::Translation,
+        )
+    };
+    let translation =
+        result.map_err(|err| serde_json::to_string(&err).unwrap_or_else(|_| err.to_string()))?;
+
+    record_history(
+        &app,
+        history::HistoryEntry::new(
+            &text,
+            &translation,
+            &current_settings.model,
+            mode,
+            meter.cost(),
+            history::Window::Popup,
+        ),
+    );
+    Ok(translation)
+}
+
+/// Best effort: a failed history write must not fail the translation
+fn record_history(app: &tauri::AppHandle, entry: history::HistoryEntry) {
+    if let Err(e) = history::record(app, entry) {
+        log::warn!("Failed to record translation history: {}", e);
+    }
+}
+
+/// Look up a word in the offline dictionary (None if not imported or not found)
+#[tauri::command]
+async fn lookup_word(app: tauri::AppHandle, word: String) -> Option<dictionary::DictionaryEntry> {
+    offline_dictionary::lookup(&app, &word)
+}
+
+/// Furigana (ruby spans) and romaji for Japanese text
+#[tauri::command]
+async fn get_reading(
+    app: tauri::AppHandle,
+    text: String,
+    allow_sensitive: Option<bool>,
+) -> Result<reading::ReadingResult, String> {
+    check_sensitive(&[&text], allow_sensitive)?;
+    // Without a key, readings come from the offline dictionary only
+    let api_key = keychain::get_api_key().unwrap_or_default();
+    let current_settings = settings::get_settings(&app);
+    let sink = TauriSink::new(&app);
+    let storage = SettingsStorage(app.clone());
+    let ctx = TranslateContext {
+        sink: &sink,
+        storage: &storage,
+        options: TranslateOptions::from_settings(&current_settings, api_key),
+    };
+    let index = offline_dictionary::get_index(&app);
+    reading::annotate(&ctx, index.as_deref(), &text)
+        .await
+        .map_err(|err| serde_json::to_string(&err).unwrap_or_else(|_| err.to_string()))
+}
+
+/// Import JMdict XML or UTF-8 EDICT into the offline dictionary index
+#[tauri::command]
+async fn import_offline_dictionary(app: tauri::AppHandle, path: String) -> Result<usize, String> {
+    // WHY: JMdict is ~100MB of XML; keep the parse off the async runtime threads
+    tauri::async_runtime::spawn_blocking(move || {
+        offline_dictionary::import(&app, std::path::Path::new(&path))
+    })
+    .await
+    .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
@@ -471,6 +1202,16 @@ fn install_panic_handler_with_flush(
     }));
 }
 
+/// Entry point of the `traylingo-cli` binary
+pub fn run_cli() -> std::process::ExitCode {
+    cli::run()
+}
+
+/// Entry point of the `traylingo-mcp` binary
+pub fn run_mcp() -> std::process::ExitCode {
+    mcp::run()
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     // =========================================================================
@@ -528,21 +1269,49 @@ pub fn run() {
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .plugin(tauri_plugin_process::init())
+        .manage(refinement::RefinementSessions::default())
+        .manage(ApiServerState::default())
         .invoke_handler(tauri::generate_handler![
             translate,
+            refine_translation,
+            get_refinement_session,
+            verify_translation,
             get_settings,
             save_settings,
+            regenerate_api_server_token,
             get_available_models,
             clear_translation_cache,
+            get_cache_stats,
+            list_cache_entries,
+            delete_cache_entry,
+            reset_translation_cache_key,
+            export_translation_memory,
+            import_translation_memory,
+            search_history,
+            set_history_starred,
+            delete_history_entry,
+            clear_history,
+            export_history,
+            get_incognito,
+            set_incognito,
             get_api_key,
             set_api_key,
             has_api_key,
             get_error_history,
             clear_error_history,
             quick_translate,
+            lookup_word,
+            get_reading,
+            import_offline_dictionary,
+            submit_batch,
+            get_batch_jobs,
+            cancel_batch,
+            clear_finished_batches,
             close_popup,
             popup_ready,
             app_log,
@@ -564,6 +1333,17 @@ pub fn run() {
             }
             // If telemetry is ON, guard stays in SENTRY_GUARD for entire program lifetime
 
+            // CLI and MCP usage recorded while the app wasn't running
+            if let Err(e) = settings::fold_usage_ledger(app.handle()) {
+                log::warn!("Failed to fold usage ledger: {}", e);
+            }
+
+            // A failure (port in use) only disables the local API, not the app
+            if let Err(e) = apply_api_server_settings(app.handle()) {
+                log::error!("{}", e);
+            }
+            spawn_batch_poller(app.handle().clone());
+
             // Create tray menu
             let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
             let show = MenuItem::with_id(app, "show", "Show Window", true, None::<&str>)?;
@@ -575,7 +1355,10 @@ pub fn run() {
                 None::<&str>,
             )?;
             let privacy = MenuItem::with_id(app, "privacy", "Privacy Policy", true, None::<&str>)?;
-            let menu = Menu::with_items(app, &[&show, &check_update, &privacy, &quit])?;
+            let incognito =
+                CheckMenuItem::with_id(app, "incognito", "Incognito", true, false, None::<&str>)?;
+            let menu = Menu::with_items(app, &[&show, &incognito, &check_update, &privacy, &quit])?;
+            app.manage(IncognitoMenuItem(incognito));
 
             // Load tray icon from embedded bytes (monochrome template)
             let icon = Image::from_bytes(include_bytes!("../icons/trayTemplate@2x.png"))
@@ -592,6 +1375,9 @@ pub fn run() {
                     "show" => {
                         show_window(app);
                     }
+                    "incognito" => {
+                        apply_incognito(app, !history::is_incognito());
+                    }
                     "check_update" => {
                         check_for_updates(app.clone());
                     }
@@ -667,6 +1453,14 @@ pub fn run() {
                     show_popup(app, clipboard_text);
                 })?;
 
+            // traylingo:// links (bookmarklets, launchers, scripts)
+            let handle = app.handle().clone();
+            app.deep_link().on_open_url(move |event| {
+                for url in event.urls() {
+                    handle_deep_link(&handle, &url);
+                }
+            });
+
             // Preload popup window to ensure JS is loaded before first use
             // Tauri v2 webview JS doesn't load until window is first shown
             if let Some(popup) = app.get_webview_window("popup") {
@@ -682,6 +1476,17 @@ pub fn run() {
                 let _ = popup.hide();
             }
 
+            // WHY: A link that launched the app arrives before on_open_url is registered;
+            // it's handled here, after the popup is loaded so it can show the text.
+            match app.deep_link().get_current() {
+                Ok(urls) => {
+                    for url in urls.unwrap_or_default() {
+                        handle_deep_link(app.handle(), &url);
+                    }
+                }
+                Err(e) => log::warn!("Failed to read launch deep link: {}", e),
+            }
+
             // Log plugin (debug only)
             if cfg!(debug_assertions) {
                 app.handle().plugin(
@@ -730,6 +1535,7 @@ pub fn run() {
                 // Prevent app exit when all windows are hidden
                 api.prevent_exit();
             }
+            RunEvent::Exit => cache::flush_all(),
             _ => {}
         }
     });
@@ -0,0 +1,621 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use log::info;
+use once_cell::sync::Lazy;
+use pulldown_cmark::{Event, LinkType, Options, Parser, Tag, TagEnd};
+use regex::Regex;
+
+use crate::anthropic::{self, Usage, SYSTEM_PROMPT};
+use crate::cache::Fingerprint;
+use crate::engine::TranslateContext;
+use crate::error::TranslateError;
+use crate::redaction::Redactor;
+
+/// Max characters of node text sent in one request
+const MAX_BATCH_CHARS: usize = 6000;
+
+// WHY: Asking the model to "preserve" Markdown is not enough: tables, link targets,
+// inline code and front matter still get rewritten. Only prose is sent, one node per
+// block, and every piece of inline markup is replaced by a marker restored locally.
+const MARKDOWN_RULES: &str = r#"
+
+MARKDOWN NODES:
+- The text contains <node id="N"> elements, each a separate piece of prose
+- Translate each node and output it as <node id="N">translation</node> with the same id
+- Output every node exactly once, in order, and nothing else
+- Markers like ⟦0⟧ stand for formatting, links, or code: keep each marker exactly once,
+  next to the words it belongs to"#;
+
+static NODE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?s)<node id="(\d+)">(.*?)</node>"#).unwrap());
+static MARKER_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"⟦(\d+)⟧").unwrap());
+
+/// A run of prose inside one block, with inline markup replaced by markers
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProseNode {
+    /// Stable ID (document order)
+    pub id: usize,
+    /// Byte range in the source replaced on reassembly
+    range: Range<usize>,
+    /// Text sent for translation
+    pub text: String,
+    /// Original markup for each marker `⟦i⟧`
+    markers: Vec<String>,
+}
+
+/// Markdown source split into translatable prose nodes
+#[derive(Debug)]
+pub struct MarkdownDocument {
+    source: String,
+    pub nodes: Vec<ProseNode>,
+}
+
+fn parser_options() -> Options {
+    Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        | Options::ENABLE_PLUSES_DELIMITED_METADATA_BLOCKS
+}
+
+/// Heuristic: does the text use Markdown structure worth preserving?
+// WHY: Bullets and inline code alone are common in plain prose (emails, chat), and
+// plain translation already keeps code and URLs. Only fences, headings, tables and
+// front matter, or list/quote structure together with links or inline code, count.
+pub fn looks_like_markdown(text: &str) -> bool {
+    if !text.contains('\n') {
+        return false;
+    }
+    let mut strong = false;
+    let mut structure = 0;
+    let mut inline = 0;
+    for line in text.lines() {
+        let line = line.trim_start();
+        if line.starts_with("```")
+            || line.starts_with("~~~")
+            || line.starts_with("# ")
+            || line.starts_with("## ")
+            || line.starts_with("### ")
+            || (line.starts_with('|') && line.contains("---"))
+        {
+            strong = true;
+        }
+        if line.starts_with("- ") || line.starts_with("* ") || line.starts_with("> ") {
+            structure += 1;
+        }
+        if line.contains("](") || line.matches('`').count() >= 2 {
+            inline += 1;
+        }
+    }
+    strong || text.starts_with("---\n") || (structure >= 2 && inline >= 2)
+}
+
+/// System prompt for node batches (`extra_rules`: e.g. the retry reminder)
+fn system_prompt(extra_rules: &str) -> String {
+    format!("{}{}{}", SYSTEM_PROMPT, MARKDOWN_RULES, extra_rules)
+}
+
+/// Cache key parts of a Markdown translation of `text` with `model`
+// WHY: Separate from plain translations of the same text, which use another prompt
+pub(crate) fn fingerprint(model: &str, text: &str) -> Fingerprint {
+    Fingerprint::new(model, &system_prompt(""), text)
+}
+
+fn is_inline_tag(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis
+            | Tag::Strong
+            | Tag::Strikethrough
+            | Tag::Superscript
+            | Tag::Subscript
+            | Tag::Link { .. }
+            | Tag::Image { .. }
+    )
+}
+
+fn is_inline_tag_end(tag: &TagEnd) -> bool {
+    matches!(
+        tag,
+        TagEnd::Emphasis
+            | TagEnd::Strong
+            | TagEnd::Strikethrough
+            | TagEnd::Superscript
+            | TagEnd::Subscript
+            | TagEnd::Link
+            | TagEnd::Image
+    )
+}
+
+/// Text piece of a run, and whether markup precedes it
+struct Piece {
+    range: Range<usize>,
+    markup_before: bool,
+}
+
+/// Inline events of one block seen so far
+#[derive(Default)]
+struct Run {
+    range: Option<Range<usize>>,
+    pieces: Vec<Piece>,
+    markup_pending: bool,
+}
+
+impl Run {
+    fn extend(&mut self, range: &Range<usize>) {
+        self.range = Some(match self.range.take() {
+            Some(r) => r.start.min(range.start)..r.end.max(range.end),
+            None => range.clone(),
+        });
+    }
+}
+
+impl MarkdownDocument {
+    pub fn parse(source: &str) -> Self {
+        let mut nodes = Vec::new();
+        let mut run = Run::default();
+        // Text inside code blocks, front matter, and HTML blocks is never prose
+        let mut verbatim_depth = 0usize;
+        // Autolinks (<https://…>) show their URL as link text
+        let mut links: Vec<bool> = Vec::new();
+
+        for (event, range) in Parser::new_ext(source, parser_options()).into_offset_iter() {
+            match event {
+                Event::Start(tag) if is_inline_tag(&tag) => {
+                    if let Tag::Link { link_type, .. } = &tag {
+                        links.push(matches!(link_type, LinkType::Autolink | LinkType::Email));
+                    }
+                    run.extend(&range);
+                    run.markup_pending = true;
+                }
+                Event::End(tag) if is_inline_tag_end(&tag) => {
+                    if tag == TagEnd::Link {
+                        links.pop();
+                    }
+                    run.extend(&range);
+                    run.markup_pending = true;
+                }
+                Event::Start(tag) => {
+                    flush(source, &mut run, &mut nodes);
+                    if matches!(
+                        tag,
+                        Tag::CodeBlock(_) | Tag::MetadataBlock(_) | Tag::HtmlBlock
+                    ) {
+                        verbatim_depth += 1;
+                    }
+                }
+                Event::End(tag) => {
+                    flush(source, &mut run, &mut nodes);
+                    if matches!(
+                        tag,
+                        TagEnd::CodeBlock | TagEnd::MetadataBlock(_) | TagEnd::HtmlBlock
+                    ) {
+                        verbatim_depth = verbatim_depth.saturating_sub(1);
+                    }
+                }
+                Event::Text(_) if verbatim_depth > 0 => {}
+                Event::Text(_) if links.last() == Some(&true) => {
+                    run.extend(&range);
+                    run.markup_pending = true;
+                }
+                Event::Text(_) => {
+                    run.extend(&range);
+                    run.pieces.push(Piece {
+                        range,
+                        markup_before: std::mem::take(&mut run.markup_pending),
+                    });
+                }
+                Event::SoftBreak => run.extend(&range),
+                Event::Code(_)
+                | Event::InlineHtml(_)
+                | Event::InlineMath(_)
+                | Event::FootnoteReference(_)
+                | Event::HardBreak => {
+                    run.extend(&range);
+                    run.markup_pending = true;
+                }
+                // Block-level: Html, DisplayMath, Rule, TaskListMarker
+                _ => flush(source, &mut run, &mut nodes),
+            }
+        }
+        flush(source, &mut run, &mut nodes);
+
+        Self {
+            source: source.to_string(),
+            nodes,
+        }
+    }
+
+    /// Replace each node with its translation; everything else is kept byte for byte
+    pub fn reassemble(
+        &self,
+        translations: &HashMap<usize, String>,
+    ) -> Result<String, TranslateError> {
+        let mut output = self.source.clone();
+        for node in self.nodes.iter().rev() {
+            let translated =
+                translations
+                    .get(&node.id)
+                    .ok_or_else(|| TranslateError::ParseError {
+                        message: format!("Missing translation for Markdown node {}", node.id),
+                    })?;
+            output.replace_range(node.range.clone(), &node.restore(translated)?);
+        }
+        Ok(output)
+    }
+}
+
+/// Turn the collected run into a node if it contains prose
+fn flush(source: &str, run: &mut Run, nodes: &mut Vec<ProseNode>) {
+    let run = std::mem::take(run);
+    let Some(range) = run.range else {
+        return;
+    };
+    let has_prose = run
+        .pieces
+        .iter()
+        .any(|p| source[p.range.clone()].chars().any(char::is_alphabetic));
+    if !has_prose {
+        return;
+    }
+
+    let mut text = String::new();
+    let mut markers = Vec::new();
+    let mut push_gap = |gap: &str, markup: bool, text: &mut String| {
+        if gap.is_empty() {
+            return;
+        }
+        if markup {
+            text.push_str(&format!("⟦{}⟧", markers.len()));
+            markers.push(gap.to_string());
+        } else if gap.contains('\n') {
+            // Soft break (may include container prefixes like "> ")
+            text.push(' ');
+        } else {
+            // Backslash escapes split text events; keep them as written
+            text.push_str(gap);
+        }
+    };
+
+    let mut cursor = range.start;
+    for piece in &run.pieces {
+        push_gap(
+            &source[cursor..piece.range.start],
+            piece.markup_before,
+            &mut text,
+        );
+        text.push_str(&source[piece.range.clone()]);
+        cursor = piece.range.end;
+    }
+    push_gap(&source[cursor..range.end], run.markup_pending, &mut text);
+
+    nodes.push(ProseNode {
+        id: nodes.len(),
+        range,
+        text,
+        markers,
+    });
+}
+
+impl ProseNode {
+    /// Put the original markup back in place of the markers
+    fn restore(&self, translated: &str) -> Result<String, TranslateError> {
+        let mut seen = vec![false; self.markers.len()];
+        let mut error = None;
+        let restored = MARKER_REGEX.replace_all(translated.trim(), |caps: &regex::Captures| {
+            let index: usize = caps[1].parse().unwrap_or(usize::MAX);
+            match seen.get_mut(index) {
+                Some(seen) if !*seen => {
+                    *seen = true;
+                    self.markers[index].clone()
+                }
+                _ => {
+                    error = Some(format!(
+                        "Unexpected marker {} in node {}",
+                        &caps[0], self.id
+                    ));
+                    String::new()
+                }
+            }
+        });
+        if let Some(message) = error {
+            return Err(TranslateError::ParseError { message });
+        }
+        if let Some(missing) = seen.iter().position(|s| !s) {
+            return Err(TranslateError::ParseError {
+                message: format!("Marker ⟦{}⟧ missing in node {}", missing, self.id),
+            });
+        }
+        Ok(restored.into_owned())
+    }
+}
+
+/// Split nodes into request-sized batches
+fn batches(nodes: &[ProseNode]) -> Vec<&[ProseNode]> {
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut chars = 0;
+    for (i, node) in nodes.iter().enumerate() {
+        let len = node.text.chars().count();
+        if i > start && chars + len > MAX_BATCH_CHARS {
+            result.push(&nodes[start..i]);
+            start = i;
+            chars = 0;
+        }
+        chars += len;
+    }
+    if start < nodes.len() {
+        result.push(&nodes[start..]);
+    }
+    result
+}
+
+fn format_batch(nodes: &[ProseNode]) -> String {
+    nodes
+        .iter()
+        .map(|node| format!("<node id=\"{}\">{}</node>", node.id, node.text))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn parse_batch(response: &str) -> HashMap<usize, String> {
+    NODE_REGEX
+        .captures_iter(response)
+        .filter_map(|caps| Some((caps[1].parse().ok()?, caps[2].trim().to_string())))
+        .collect()
+}
+
+/// Translated Markdown document, before caching and validation
+pub(crate) struct NodeTranslation {
+    pub text: String,
+    /// Combined token usage of all batches
+    pub usage: Usage,
+    /// Whether privacy mode replaced anything
+    pub redacted: bool,
+}
+
+/// Translate only the prose of a Markdown document and reassemble it.
+/// Called by `anthropic::translate_markdown`, which adds cache, validation and usage.
+pub(crate) async fn translate_nodes(
+    ctx: &TranslateContext<'_>,
+    text: &str,
+    session_id: Option<&str>,
+    extra_rules: &str,
+) -> Result<NodeTranslation, TranslateError> {
+    let model = &ctx.options.model;
+    // Privacy mode applies to the whole document before it is split into nodes
+    let mut redactor = ctx.options.redaction_patterns.as_deref().map(Redactor::new);
+    let outgoing = match redactor.as_mut() {
+        Some(redactor) => redactor.redact(text),
+        None => text.to_string(),
+    };
+    if let Some(redactor) = &redactor {
+        redactor.emit_report(ctx.sink, session_id);
+    }
+
+    let redacted = redactor.as_ref().is_some_and(|r| !r.is_empty());
+
+    let document = MarkdownDocument::parse(&outgoing);
+    let mut usage = Usage::default();
+    if document.nodes.is_empty() {
+        return Ok(NodeTranslation {
+            text: text.to_string(),
+            usage,
+            redacted,
+        });
+    }
+
+    info!(
+        "Starting Markdown translation: {} nodes, model={}",
+        document.nodes.len(),
+        model
+    );
+    let system = system_prompt(extra_rules);
+    let mut translations = HashMap::new();
+    for batch in batches(&document.nodes) {
+        let (response, batch_usage) = anthropic::complete_with(
+            &ctx.options,
+            &system,
+            anthropic::wrap_text_to_translate(&format_batch(batch)),
+            8192,
+        )
+        .await?;
+        if let Some(batch_usage) = batch_usage {
+            usage.input_tokens += batch_usage.input_tokens;
+            usage.output_tokens += batch_usage.output_tokens;
+        }
+        translations.extend(parse_batch(&response));
+    }
+
+    let translated = document.reassemble(&translations)?;
+    let translated = match &redactor {
+        Some(redactor) => redactor.restore(&outgoing, &translated)?,
+        None => translated,
+    };
+    Ok(NodeTranslation {
+        text: translated,
+        usage,
+        redacted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const README: &str = r#"---
+title: TrayLingo
+tags: [rust, tauri]
+---
+
+# TrayLingo
+
+A **tray** translator for [macOS](https://www.apple.com/macos/ "Apple") and *Windows*.
+See <https://example.com/docs> or `cargo run` for details.
+
+## Install
+
+| Command | Description |
+|---------|-------------|
+| `pnpm install` | Install dependencies |
+| `pnpm tauri dev` | Run the app |
+
+```bash
+# Build the app
+pnpm tauri build
+```
+
+- [x] Streaming output
+- Cache with **SHA256** keys
+
+> Note: requires an [API key][key].
+
+<div align="center">Made with love</div>
+
+[key]: https://console.anthropic.com/
+"#;
+
+    /// Translate every node with `f` applied outside markers
+    fn translate_with(document: &MarkdownDocument, f: fn(&str) -> String) -> String {
+        let translations = document
+            .nodes
+            .iter()
+            .map(|node| {
+                let mut translated = String::new();
+                let mut last = 0;
+                for m in MARKER_REGEX.find_iter(&node.text) {
+                    translated.push_str(&f(&node.text[last..m.start()]));
+                    translated.push_str(m.as_str());
+                    last = m.end();
+                }
+                translated.push_str(&f(&node.text[last..]));
+                (node.id, translated)
+            })
+            .collect();
+        document.reassemble(&translations).unwrap()
+    }
+
+    fn structure(markdown: &str) -> Vec<String> {
+        Parser::new_ext(markdown, parser_options())
+            .filter_map(|event| match event {
+                // Soft breaks inside a node are reflowed into spaces
+                Event::Text(_) | Event::SoftBreak => None,
+                Event::Start(Tag::Heading { level, .. }) => Some(format!("Heading({:?})", level)),
+                Event::Start(tag) => Some(format!("{:?}", std::mem::discriminant(&tag))),
+                other => Some(format!("{:?}", other)),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identity_round_trip() {
+        let document = MarkdownDocument::parse(README);
+        let expected = README.replace("*Windows*.\nSee", "*Windows*. See");
+        assert_eq!(translate_with(&document, str::to_string), expected);
+    }
+
+    #[test]
+    fn test_round_trip_preserves_structure() {
+        let document = MarkdownDocument::parse(README);
+        let translated = translate_with(&document, |s| s.to_uppercase());
+
+        assert_eq!(structure(&translated), structure(README));
+        for verbatim in [
+            "title: TrayLingo\ntags: [rust, tauri]",
+            "(https://www.apple.com/macos/ \"Apple\")",
+            "<https://example.com/docs>",
+            "`cargo run`",
+            "| `pnpm install` |",
+            "```bash\n# Build the app\npnpm tauri build\n```",
+            "<div align=\"center\">Made with love</div>",
+            "[key]: https://console.anthropic.com/",
+        ] {
+            assert!(translated.contains(verbatim), "changed: {}", verbatim);
+        }
+        assert!(translated.contains("# TRAYLINGO\n"));
+        assert!(translated.contains("A **TRAY** TRANSLATOR FOR [MACOS]"));
+        assert!(translated.contains("| INSTALL DEPENDENCIES |"));
+        assert!(translated.contains("- [x] STREAMING OUTPUT"));
+        assert!(translated.contains("[API KEY][key]"));
+    }
+
+    #[test]
+    fn test_nodes_exclude_code_and_urls() {
+        let document = MarkdownDocument::parse(README);
+        let all_text: String = document.nodes.iter().map(|n| n.text.as_str()).collect();
+        assert!(!all_text.contains("cargo run"));
+        assert!(!all_text.contains("https://"));
+        assert!(!all_text.contains("pnpm tauri build"));
+        assert!(!all_text.contains("title:"));
+        assert!(!all_text.contains("Made with love"));
+
+        let ids: Vec<_> = document.nodes.iter().map(|n| n.id).collect();
+        assert_eq!(ids, (0..document.nodes.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_soft_breaks_become_spaces() {
+        let source = "> First line\n> second line\n";
+        let document = MarkdownDocument::parse(source);
+        assert_eq!(document.nodes.len(), 1);
+        assert_eq!(document.nodes[0].text, "First line second line");
+        assert_eq!(
+            translate_with(&document, str::to_string),
+            "> First line second line\n"
+        );
+    }
+
+    #[test]
+    fn test_restore_rejects_bad_markers() {
+        let document = MarkdownDocument::parse("Run `make` now, please.\n");
+        let node = &document.nodes[0];
+        assert_eq!(node.text, "Run ⟦0⟧ now, please.");
+
+        assert_eq!(
+            node.restore("今すぐ⟦0⟧を実行").unwrap(),
+            "今すぐ`make`を実行"
+        );
+        assert!(node.restore("今すぐ実行").is_err());
+        assert!(node.restore("⟦0⟧⟦0⟧").is_err());
+        assert!(node.restore("⟦0⟧⟦1⟧").is_err());
+    }
+
+    #[test]
+    fn test_parse_batch() {
+        let response = "<node id=\"0\">こんにちは</node>\n<node id=\"3\">\n世界\n</node>";
+        let parsed = parse_batch(response);
+        assert_eq!(parsed[&0], "こんにちは");
+        assert_eq!(parsed[&3], "世界");
+    }
+
+    #[test]
+    fn test_looks_like_markdown() {
+        assert!(looks_like_markdown(README));
+        assert!(looks_like_markdown("Intro\n\n```\ncode\n```"));
+        assert!(!looks_like_markdown("Hello world"));
+        assert!(!looks_like_markdown(
+            "Dear team,\n\nThanks for the update - see you soon."
+        ));
+        assert!(looks_like_markdown(
+            "Setup:\n- Read the [guide](https://example.com)\n- Run `make` once"
+        ));
+    }
+
+    #[test]
+    fn test_plain_bulleted_prose_is_not_markdown() {
+        assert!(!looks_like_markdown(
+            "Agenda for Friday:\n- Budget review\n- Hiring plan\n- Q&A"
+        ));
+        assert!(!looks_like_markdown(
+            "Hi all,\n* the office is closed Monday\n* the party is on Tuesday\nThanks!"
+        ));
+        assert!(!looks_like_markdown(
+            "Reminders:\n- use `--force` only when asked\n- ask before deploying"
+        ));
+        assert!(!looks_like_markdown(
+            "Set `timeout` to 30.\nThen restart the `worker` service."
+        ));
+    }
+}
@@ -0,0 +1,533 @@
+//! `traylingo-mcp`: Model Context Protocol server on stdio for AI agents.
+//! Exposes `translate`, `detect_language` and `lookup_word` tools backed by the
+//! app's engine, cache, settings.json (usage totals) and keychain entry.
+//!
+//! Messages are newline-delimited JSON-RPC 2.0; stdout carries protocol messages only.
+
+use std::process::ExitCode;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::cache;
+use crate::dictionary::{self, DictionaryEntry};
+use crate::engine::{self, CollectingSink, TranslateContext, TranslateOptions, TranslationResult};
+use crate::error::TranslateError;
+use crate::keychain;
+use crate::offline_dictionary;
+use crate::secrets;
+use crate::settings::{self, resolve_model, FileStore};
+use crate::validation;
+use crate::SettingsStorage;
+
+/// Newest first; a client asking for anything else gets the newest
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+const SESSION_ID: &str = "mcp";
+
+const INSTRUCTIONS: &str = "TrayLingo translates between Japanese and English. Use \
+lookup_word for dictionary entries of single words and translate for sentences and documents; \
+both share the user's TrayLingo settings, cache and usage totals.";
+
+type RpcError = (i64, String);
+
+pub struct McpServer {
+    storage: SettingsStorage<FileStore>,
+    /// Keychain lookup, read per call so a key changed in the app applies at once
+    api_key: fn() -> Option<String>,
+    endpoint: String,
+}
+
+impl McpServer {
+    pub fn new(store: FileStore) -> Self {
+        Self {
+            storage: SettingsStorage(store),
+            api_key: keychain::get_api_key,
+            endpoint: engine::MESSAGES_URL.to_string(),
+        }
+    }
+
+    fn options(&self) -> Result<TranslateOptions, TranslateError> {
+        let api_key = (self.api_key)().ok_or(TranslateError::ApiKeyMissing)?;
+        let current_settings = settings::get_settings(&self.storage.0);
+        let mut options = TranslateOptions::from_settings(&current_settings, api_key);
+        options.endpoint = self.endpoint.clone();
+        Ok(options)
+    }
+
+    /// Serve until stdin closes
+    pub async fn serve(&self) -> std::io::Result<()> {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut stdout = tokio::io::stdout();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle(&line).await {
+                stdout
+                    .write_all(format!("{}\n", response).as_bytes())
+                    .await?;
+                stdout.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Response to one JSON-RPC message (None for notifications and client responses)
+    pub async fn handle(&self, line: &str) -> Option<Value> {
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(e) => return Some(error_response(Value::Null, PARSE_ERROR, &e.to_string())),
+        };
+        // Notifications (initialized, cancelled) need no answer
+        let id = message.get("id").cloned()?;
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // We never send requests, so this can't be a response to one of ours
+            return Some(error_response(id, INVALID_REQUEST, "Missing method"));
+        };
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let result = match method {
+            "initialize" => Ok(initialize(&params)),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": tools() })),
+            "tools/call" => self.call_tool(&params).await,
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+        };
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, &message),
+        })
+    }
+
+    async fn call_tool(&self, params: &Value) -> Result<Value, RpcError> {
+        let name = params
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| (INVALID_PARAMS, "Missing tool name".to_string()))?;
+        let arguments = params.get("arguments").cloned().unwrap_or(json!({}));
+
+        // Pick up settings and cache entries written by the app since the last call
+        if let Err(e) = self.storage.0.reload() {
+            log::warn!("Failed to reload settings: {}", e);
+        }
+
+        match name {
+            "translate" => Ok(self.translate(parse_arguments(arguments)?).await),
+            "detect_language" => Ok(detect_language(parse_arguments(arguments)?)),
+            "lookup_word" => Ok(self.lookup_word(parse_arguments(arguments)?).await),
+            _ => Err((INVALID_PARAMS, format!("Unknown tool: {}", name))),
+        }
+    }
+
+    async fn translate(&self, args: TranslateArgs) -> Value {
+        let text = args.text.trim();
+        if text.is_empty() {
+            return tool_error("text is empty");
+        }
+        // WHY: No allow_sensitive override here. The agent is not the user, so it
+        // must not be able to confirm sending credentials on the user's behalf.
+        if let Err(error) = secrets::check(&[text], false) {
+            return tool_error(&error.user_message());
+        }
+        let mut options = match self.options() {
+            Ok(options) => options,
+            Err(error) => return tool_error(&error.user_message()),
+        };
+        if let Some(model) = &args.model {
+            match resolve_model(model) {
+                Ok(model) => options.model = model,
+                Err(message) => return tool_error(&message),
+            }
+        }
+
+        let sink = CollectingSink::new(|_| {});
+        let ctx = TranslateContext {
+            sink: &sink,
+            storage: &self.storage,
+            options,
+        };
+        let translation = match engine::translate(&ctx, text, SESSION_ID).await {
+            Ok(translation) => translation,
+            Err(error) => return tool_error(&error.user_message()),
+        };
+        let result = TranslationResult::new(translation, &ctx.options.model, sink.take_report());
+
+        let mut content = vec![json!({ "type": "text", "text": result.translation })];
+        if !result.issues.is_empty() {
+            let warning = format!("Warning: flagged by output validation: {:?}", result.issues);
+            content.push(json!({ "type": "text", "text": warning }));
+        }
+        json!({ "content": content, "structuredContent": result })
+    }
+
+    async fn lookup_word(&self, args: LookupArgs) -> Value {
+        let term = args.term.trim();
+        if !dictionary::is_dictionary_candidate(term) {
+            return tool_error(
+                "Only single words and short phrases can be looked up; use translate instead",
+            );
+        }
+
+        // Offline dictionary first: no API key or cost needed
+        let (entry, source) = match offline_dictionary::lookup_default(term) {
+            Some(entry) => (entry, "offline"),
+            None => {
+                // Same gate as translate, without an override for the agent
+                if let Err(error) = secrets::check(&[term], false) {
+                    return tool_error(&error.user_message());
+                }
+                let options = match self.options() {
+                    Ok(options) => options,
+                    Err(error) => return tool_error(&error.user_message()),
+                };
+                match dictionary::lookup(&self.storage.0, &self.storage, term, &options).await {
+                    Ok(entry) => (entry, "claude"),
+                    Err(error) => return tool_error(&error.user_message()),
+                }
+            }
+        };
+
+        json!({
+            "content": [{ "type": "text", "text": format_entry(&entry) }],
+            "structuredContent": { "entry": entry, "source": source },
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TranslateArgs {
+    text: String,
+    model: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DetectArgs {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LookupArgs {
+    term: String,
+}
+
+fn parse_arguments<T: for<'de> Deserialize<'de>>(arguments: Value) -> Result<T, RpcError> {
+    serde_json::from_value(arguments)
+        .map_err(|e| (INVALID_PARAMS, format!("Invalid arguments: {}", e)))
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    let version = PROTOCOL_VERSIONS
+        .iter()
+        .find(|version| Some(**version) == requested)
+        .unwrap_or(&PROTOCOL_VERSIONS[0]);
+    json!({
+        "protocolVersion": version,
+        "capabilities": { "tools": {} },
+        "serverInfo": { "name": "traylingo", "version": env!("CARGO_PKG_VERSION") },
+        "instructions": INSTRUCTIONS,
+    })
+}
+
+fn tools() -> Value {
+    let models: Vec<&str> = settings::AVAILABLE_MODELS
+        .iter()
+        .map(|(id, _)| *id)
+        .collect();
+    json!([
+        {
+            "name": "translate",
+            "description": "Translate Japanese to English or English to Japanese (direction is \
+                detected). Code blocks and Markdown structure are preserved. Uses the \
+                TrayLingo app's model, cache and privacy settings.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "text": { "type": "string", "description": "Text to translate" },
+                    "model": {
+                        "type": "string",
+                        "description": format!(
+                            "Model id or alias (haiku, sonnet) instead of the app setting: {}",
+                            models.join(", ")
+                        ),
+                    },
+                },
+                "required": ["text"],
+            },
+        },
+        {
+            "name": "detect_language",
+            "description": "Detect whether text is Japanese (ja) or not (en), and the \
+                direction translate would use. Local, no API call.",
+            "inputSchema": {
+                "type": "object",
+                "properties": { "text": { "type": "string" } },
+                "required": ["text"],
+            },
+        },
+        {
+            "name": "lookup_word",
+            "description": "Dictionary entry (reading, senses, examples) for a single \
+                Japanese or English word or short phrase, from the user's offline dictionary \
+                when imported, otherwise from Claude. TrayLingo has no glossary or custom \
+                terminology; this is its word dictionary.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "term": { "type": "string", "description": "A word or short phrase" },
+                },
+                "required": ["term"],
+            },
+        },
+    ])
+}
+
+fn detect_language(args: DetectArgs) -> Value {
+    let (language, target) = match validation::is_japanese(&args.text) {
+        Some(true) => ("ja", Some("en")),
+        Some(false) => ("en", Some("ja")),
+        None => ("unknown", None),
+    };
+    json!({
+        "content": [{ "type": "text", "text": language }],
+        "structuredContent": { "language": language, "translate_to": target },
+    })
+}
+
+/// "食べる (たべる) [ichidan verb]: to eat; to live on"
+fn format_entry(entry: &DictionaryEntry) -> String {
+    let mut text = entry.headword.clone();
+    if let Some(reading) = entry.reading.as_deref().filter(|r| !r.is_empty()) {
+        text.push_str(&format!(" ({})", reading));
+    }
+    if let Some(part_of_speech) = entry.part_of_speech.as_deref().filter(|p| !p.is_empty()) {
+        text.push_str(&format!(" [{}]", part_of_speech));
+    }
+    text.push_str(&format!(": {}", entry.summary()));
+    for example in &entry.examples {
+        text.push_str(&format!("\n- {} = {}", example.source, example.translation));
+    }
+    text
+}
+
+/// Tool failures are results (the agent sees them), not protocol errors
+fn tool_error(message: &str) -> Value {
+    json!({ "content": [{ "type": "text", "text": message }], "isError": true })
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+pub fn run() -> ExitCode {
+    let store = match FileStore::open_default() {
+        Ok(store) => store,
+        Err(message) => {
+            eprintln!("traylingo-mcp: {}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+    let server = McpServer::new(store);
+    let runtime = match tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+    {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("traylingo-mcp: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let served = runtime.block_on(server.serve());
+    cache::flush_all();
+    match served {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("traylingo-mcp: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+    use crate::settings::TempStore;
+
+    const SOURCE: &str = "Please restart the app to apply changes.";
+    const TRANSLATION: &str = "変更を適用するにはアプリを再起動してください。";
+    const SSE_TRANSLATION: &str = include_str!("../tests/fixtures/sse/translation.sse");
+
+    /// A server on its own store; the store's directory goes when it is dropped
+    fn server(name: &str, endpoint: &str) -> (TempStore, McpServer) {
+        let store = TempStore::new(&format!("mcp-{}", name));
+        let server = McpServer {
+            storage: SettingsStorage(FileStore::open(store.path()).unwrap()),
+            api_key: || Some("test-key".to_string()),
+            endpoint: endpoint.to_string(),
+        };
+        (store, server)
+    }
+
+    async fn call(server: &McpServer, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        server.handle(&request.to_string()).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_initialize_and_list_tools() {
+        let (_store, server) = server("init", engine::MESSAGES_URL);
+
+        let response = call(
+            &server,
+            "initialize",
+            json!({ "protocolVersion": "2025-03-26" }),
+        )
+        .await;
+        assert_eq!(response["result"]["protocolVersion"], "2025-03-26");
+        let response = call(
+            &server,
+            "initialize",
+            json!({ "protocolVersion": "1999-01-01" }),
+        )
+        .await;
+        assert_eq!(response["result"]["protocolVersion"], PROTOCOL_VERSIONS[0]);
+
+        let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        assert!(server.handle(&initialized.to_string()).await.is_none());
+
+        let response = call(&server, "tools/list", json!({})).await;
+        let names: Vec<&str> = response["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["translate", "detect_language", "lookup_word"]);
+    }
+
+    #[tokio::test]
+    async fn test_protocol_errors() {
+        let (_store, server) = server("errors", engine::MESSAGES_URL);
+
+        let response = server.handle("{not json").await.unwrap();
+        assert_eq!(response["error"]["code"], PARSE_ERROR);
+        assert_eq!(response["id"], Value::Null);
+
+        let response = call(&server, "resources/list", json!({})).await;
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND);
+        let response = call(&server, "tools/call", json!({ "name": "summarize" })).await;
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+        let params = json!({ "name": "translate", "arguments": { "txt": "x" } });
+        let response = call(&server, "tools/call", params).await;
+        assert_eq!(response["error"]["code"], INVALID_PARAMS);
+    }
+
+    #[tokio::test]
+    async fn test_translate_tool() {
+        let upstream = MockServer::start(vec![MockResponse::sse(SSE_TRANSLATION, 64)]).await;
+        let (_store, server) = server("translate", upstream.url());
+
+        let params = json!({ "name": "translate", "arguments": { "text": SOURCE } });
+        let response = call(&server, "tools/call", params).await;
+        let result = &response["result"];
+        assert_eq!(result["content"][0]["text"], TRANSLATION);
+        assert_eq!(result["structuredContent"]["usage"]["prompt_tokens"], 183);
+        assert!(result.get("isError").is_none());
+        // Counted in the app's usage totals
+        assert_eq!(settings::get_usage_stats(&server.storage.0).translations, 1);
+
+        // Credentials are refused without a way for the agent to override
+        let params = json!({ "name": "translate", "arguments": { "text": "password=hunter22" } });
+        let response = call(&server, "tools/call", params).await;
+        assert_eq!(response["result"]["isError"], true);
+        assert_eq!(upstream.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_detect_language_and_lookup_limits() {
+        let (_store, server) = server("detect", engine::MESSAGES_URL);
+
+        let params = json!({ "name": "detect_language", "arguments": { "text": TRANSLATION } });
+        let response = call(&server, "tools/call", params).await;
+        assert_eq!(response["result"]["structuredContent"]["language"], "ja");
+        assert_eq!(
+            response["result"]["structuredContent"]["translate_to"],
+            "en"
+        );
+
+        let params = json!({ "name": "lookup_word", "arguments": { "term": SOURCE } });
+        let response = call(&server, "tools/call", params).await;
+        assert_eq!(response["result"]["isError"], true);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_word_api_fallback() {
+        let entry = json!({
+            "headword": "serendipity",
+            "senses": [{ "gloss": "思いがけない幸運" }]
+        });
+        let body = json!({
+            "content": [{ "type": "text", "text": entry.to_string() }],
+            "usage": { "input_tokens": 80, "output_tokens": 40 }
+        });
+        let upstream = MockServer::start(vec![MockResponse::json(200, &body.to_string())]).await;
+        let (_store, server) = server("lookup-word", upstream.url());
+
+        let params = json!({ "name": "lookup_word", "arguments": { "term": "serendipity" } });
+        let response = call(&server, "tools/call", params).await;
+        assert_eq!(response["result"]["structuredContent"]["source"], "claude");
+        // Sent to the configured endpoint and counted in the usage totals
+        assert_eq!(upstream.requests().len(), 1);
+        let usage = settings::get_usage_stats(&server.storage.0);
+        assert_eq!((usage.other_requests, usage.prompt_tokens), (1, 80));
+
+        // Credentials are refused before the API fallback
+        let params = json!({ "name": "lookup_word", "arguments": { "term": "password=hunter22" } });
+        let response = call(&server, "tools/call", params).await;
+        assert_eq!(response["result"]["isError"], true);
+        assert_eq!(upstream.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_lookup_refused_in_privacy_mode() {
+        let upstream = MockServer::start(Vec::new()).await;
+        let (_store, server) = server("privacy", upstream.url());
+        let mut current_settings = settings::get_settings(&server.storage.0);
+        current_settings.privacy_mode = true;
+        settings::save_settings(&server.storage.0, &current_settings).unwrap();
+
+        let params = json!({ "name": "lookup_word", "arguments": { "term": "090-1234-5678" } });
+        let response = call(&server, "tools/call", params).await;
+        assert_eq!(response["result"]["isError"], true);
+        assert!(upstream.requests().is_empty());
+    }
+
+    #[test]
+    fn test_format_entry() {
+        let entry: DictionaryEntry = serde_json::from_value(json!({
+            "headword": "食べる",
+            "reading": "たべる",
+            "part_of_speech": "ichidan verb",
+            "senses": [{ "gloss": "to eat" }, { "gloss": "to live on" }],
+            "examples": [{ "source": "ご飯を食べる", "translation": "to eat a meal" }],
+        }))
+        .unwrap();
+        assert_eq!(
+            format_entry(&entry),
+            "食べる (たべる) [ichidan verb]: to eat; to live on\n- ご飯を食べる = to eat a meal"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Local HTTP server for offline tests of the Messages API client.
+//! Serves canned responses (recorded SSE streams, error bodies) in order.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Canned response for one request
+pub struct MockResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// Written one after another with a flush in between (simulates network chunks)
+    parts: Vec<Vec<u8>>,
+}
+
+impl MockResponse {
+    pub fn json(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: vec![("content-type".into(), "application/json".into())],
+            parts: vec![body.as_bytes().to_vec()],
+        }
+    }
+
+    /// SSE body split every `chunk_size` bytes (may cut multi-byte characters)
+    pub fn sse(body: &str, chunk_size: usize) -> Self {
+        Self {
+            status: 200,
+            headers: vec![("content-type".into(), "text/event-stream".into())],
+            parts: body
+                .as_bytes()
+                .chunks(chunk_size)
+                .map(<[u8]>::to_vec)
+                .collect(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+}
+
+/// Request as received by the server
+pub struct RecordedRequest {
+    pub api_key: Option<String>,
+    pub body: Value,
+}
+
+pub struct MockServer {
+    url: String,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl MockServer {
+    /// Serve `responses` in order, one per connection
+    pub async fn start(responses: Vec<MockResponse>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1/messages", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            for response in responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let request = read_request(&mut socket).await;
+                recorded.lock().unwrap().push(request);
+                write_response(&mut socket, response).await;
+            }
+        });
+        Self { url, requests }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn requests(&self) -> std::sync::MutexGuard<'_, Vec<RecordedRequest>> {
+        self.requests.lock().unwrap()
+    }
+}
+
+async fn read_request(socket: &mut TcpStream) -> RecordedRequest {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = socket.read(&mut buf).await.unwrap();
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..n]);
+
+        let Some(header_end) = data.windows(4).position(|w| w == b"\r\n\r\n") else {
+            continue;
+        };
+        let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+        let header = |name: &str| {
+            head.lines().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.eq_ignore_ascii_case(name)
+                    .then(|| value.trim().to_string())
+            })
+        };
+        let length: usize = header("content-length")
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0);
+        let body_start = header_end + 4;
+        if data.len() >= body_start + length {
+            return RecordedRequest {
+                api_key: header("x-api-key"),
+                body: serde_json::from_slice(&data[body_start..body_start + length])
+                    .unwrap_or(Value::Null),
+            };
+        }
+    }
+    RecordedRequest {
+        api_key: None,
+        body: Value::Null,
+    }
+}
+
+async fn write_response(socket: &mut TcpStream, response: MockResponse) {
+    let reason = match response.status {
+        200 => "OK",
+        401 => "Unauthorized",
+        429 => "Too Many Requests",
+        529 => "Overloaded",
+        _ => "Error",
+    };
+    // No content-length: the body ends when the connection closes
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nconnection: close\r\n",
+        response.status, reason
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    socket.write_all(head.as_bytes()).await.unwrap();
+
+    for part in response.parts {
+        // The client may hang up early (e.g. after an error event); that's fine
+        if socket.write_all(&part).await.is_err() {
+            return;
+        }
+        let _ = socket.flush().await;
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    let _ = socket.shutdown().await;
+}
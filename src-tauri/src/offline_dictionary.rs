@@ -0,0 +1,1043 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+
+use crate::dictionary::{is_cjk, DictionaryEntry, Sense};
+use crate::settings;
+
+// WHY: Single-word lookups are the most common popup use and cost an API call each.
+// JMdict/EDICT is imported once into a line-oriented data file plus a key → offset
+// index, so lookups are a HashMap hit and one seek, with no network access.
+const INDEX_DIR: &str = "offline_dictionary";
+const DATA_FILE: &str = "entries.dat";
+const KEYS_FILE: &str = "keys.idx";
+const INDEX_HEADER: &str = "traylingo-dict v1";
+
+/// English glosses longer than this (in words) are not indexed for reverse lookup
+const MAX_GLOSS_KEY_WORDS: usize = 3;
+/// Max entries kept per English key (common words match hundreds of glosses)
+const MAX_OFFSETS_PER_ENGLISH_KEY: usize = 10;
+/// Max senses returned for an English → Japanese lookup
+const MAX_REVERSE_RESULTS: usize = 5;
+/// Safety cap on deinflection candidates per word
+const MAX_DEINFLECTIONS: usize = 256;
+
+/// Prefix for reverse (English gloss) keys, keeps them apart from Japanese headwords
+const ENGLISH_KEY_PREFIX: &str = "en:";
+
+/// Loaded index, shared across lookups (None until first successful open)
+static INDEX: Lazy<Mutex<Option<Arc<OfflineIndex>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Dictionary entry as stored in the data file (short keys keep the file compact)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct OfflineEntry {
+    /// Kanji writings (empty for kana-only words)
+    #[serde(rename = "k", default)]
+    pub kanji: Vec<String>,
+    /// Kana readings
+    #[serde(rename = "r", default)]
+    pub readings: Vec<String>,
+    #[serde(rename = "s", default)]
+    pub senses: Vec<OfflineSense>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub struct OfflineSense {
+    /// JMdict part-of-speech codes (e.g. "v1", "v5k", "adj-i")
+    #[serde(rename = "p", default)]
+    pub pos: Vec<String>,
+    /// English glosses
+    #[serde(rename = "g", default)]
+    pub glosses: Vec<String>,
+}
+
+impl OfflineEntry {
+    fn headword(&self) -> Option<&str> {
+        self.kanji
+            .first()
+            .or_else(|| self.readings.first())
+            .map(String::as_str)
+    }
+
+    /// Word-type bitmask from all sense POS codes (for deinflection matching)
+    fn word_types(&self) -> u8 {
+        self.senses
+            .iter()
+            .flat_map(|s| s.pos.iter())
+            .fold(0, |acc, pos| acc | pos_to_word_type(pos))
+    }
+}
+
+// ==================== Import ====================
+
+/// Parse one EDICT/EDICT2 line (UTF-8), e.g.
+/// `食べる;喰べる [たべる] /(v1,vt) (1) to eat/(2) to live on/(P)/EntL1358280X/`
+pub fn parse_edict_line(line: &str) -> Option<OfflineEntry> {
+    let line = line.trim();
+    // Header line starts with full-width question marks/space
+    if line.is_empty() || line.starts_with("？？？") || line.starts_with('\u{3000}') {
+        return None;
+    }
+
+    let (head, body) = line.split_once(" /")?;
+    let (kanji, readings) = match head.split_once(" [") {
+        Some((kanji, rest)) => (kanji, rest.trim_end_matches(']')),
+        // Kana-only word: headword is the reading
+        None => ("", head),
+    };
+    let split_forms = |s: &str| -> Vec<String> {
+        s.split(';')
+            .map(|f| strip_edict_tags(f.trim()).to_string())
+            .filter(|f| !f.is_empty())
+            .collect()
+    };
+
+    let mut senses: Vec<OfflineSense> = Vec::new();
+    let mut current_pos: Vec<String> = Vec::new();
+    for field in body.trim_end_matches('/').split('/') {
+        let mut field = field.trim();
+        if field.is_empty() || field == "(P)" || field.starts_with("EntL") {
+            continue;
+        }
+
+        // Leading tags: "(v1,vt)" carries POS, "(1)" starts a new numbered sense
+        let mut new_sense = senses.is_empty();
+        while let Some(rest) = field.strip_prefix('(') {
+            let Some((tag, after)) = rest.split_once(')') else {
+                break;
+            };
+            if tag.chars().all(|c| c.is_ascii_digit()) {
+                new_sense = true;
+            } else {
+                let codes: Vec<String> = tag.split(',').map(|c| c.trim().to_string()).collect();
+                if codes.iter().any(|c| is_pos_code(c)) {
+                    current_pos = codes;
+                }
+            }
+            field = after.trim_start();
+        }
+        if field.is_empty() {
+            continue;
+        }
+
+        if new_sense {
+            senses.push(OfflineSense {
+                pos: current_pos.clone(),
+                glosses: Vec::new(),
+            });
+        }
+        if let Some(sense) = senses.last_mut() {
+            sense.glosses.push(field.to_string());
+        }
+    }
+
+    let entry = OfflineEntry {
+        kanji: split_forms(kanji),
+        readings: split_forms(readings),
+        senses,
+    };
+    if entry.readings.is_empty() || entry.senses.is_empty() {
+        return None;
+    }
+    Some(entry)
+}
+
+/// Remove EDICT2 per-form tags like "(P)" or "(iK)" from a kanji/reading form
+fn strip_edict_tags(form: &str) -> &str {
+    match form.find('(') {
+        Some(pos) => form[..pos].trim(),
+        None => form,
+    }
+}
+
+/// Whether an EDICT tag is a part-of-speech code (as opposed to misc/field tags)
+fn is_pos_code(code: &str) -> bool {
+    pos_to_word_type(code) != 0
+        || code.starts_with("n-")
+        || code.starts_with("adj-")
+        || code.starts_with("adv-")
+        || code.starts_with("aux")
+        || code.starts_with("v2")
+        || code.starts_with("v4")
+        || code.starts_with("vs")
+        || matches!(
+            code,
+            "n" | "adv"
+                | "pn"
+                | "conj"
+                | "int"
+                | "exp"
+                | "prt"
+                | "suf"
+                | "pref"
+                | "ctr"
+                | "cop"
+                | "vt"
+                | "vi"
+                | "vz"
+                | "vn"
+                | "vr"
+        )
+}
+
+/// Parse a JMdict XML document.
+///
+/// Custom entities (`&v1;`, `&n;`) are kept as their code, which is exactly the
+/// POS notation used by EDICT, so both formats share the same word-type mapping.
+pub fn parse_jmdict<R: BufRead>(source: R) -> Result<Vec<OfflineEntry>, String> {
+    let mut reader = Reader::from_reader(source);
+    let mut buf = Vec::new();
+    let mut entries = Vec::new();
+
+    let mut entry = OfflineEntry::default();
+    let mut sense = OfflineSense::default();
+    // <pos> applies to following senses until a sense restates it
+    let mut inherited_pos: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut skip_gloss = false;
+
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(e)) => {
+                text.clear();
+                match e.name().as_ref() {
+                    b"entry" => {
+                        entry = OfflineEntry::default();
+                        inherited_pos.clear();
+                    }
+                    b"sense" => sense = OfflineSense::default(),
+                    b"gloss" => {
+                        skip_gloss = e
+                            .attributes()
+                            .flatten()
+                            .any(|a| a.key.as_ref() == b"xml:lang" && a.value.as_ref() != b"eng");
+                    }
+                    _ => {}
+                }
+            }
+            Ok(Event::Text(e)) => {
+                text.push_str(&e.decode().map_err(|e| e.to_string())?);
+            }
+            Ok(Event::GeneralRef(e)) => {
+                if let Ok(Some(c)) = e.resolve_char_ref() {
+                    text.push(c);
+                } else {
+                    let name = e.decode().map_err(|e| e.to_string())?;
+                    text.push_str(match name.as_ref() {
+                        "amp" => "&",
+                        "lt" => "<",
+                        "gt" => ">",
+                        "quot" => "\"",
+                        "apos" => "'",
+                        other => other,
+                    });
+                }
+            }
+            Ok(Event::End(e)) => {
+                match e.name().as_ref() {
+                    b"keb" => entry.kanji.push(text.trim().to_string()),
+                    b"reb" => entry.readings.push(text.trim().to_string()),
+                    b"pos" => sense.pos.push(text.trim().to_string()),
+                    b"gloss" if !skip_gloss => sense.glosses.push(text.trim().to_string()),
+                    b"sense" => {
+                        if sense.pos.is_empty() {
+                            sense.pos = inherited_pos.clone();
+                        } else {
+                            inherited_pos = sense.pos.clone();
+                        }
+                        if !sense.glosses.is_empty() {
+                            entry.senses.push(std::mem::take(&mut sense));
+                        }
+                    }
+                    b"entry" if !entry.readings.is_empty() && !entry.senses.is_empty() => {
+                        entries.push(std::mem::take(&mut entry));
+                    }
+                    _ => {}
+                }
+                text.clear();
+            }
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => {
+                return Err(format!(
+                    "JMdict parse error at byte {}: {}",
+                    reader.buffer_position(),
+                    e
+                ))
+            }
+        }
+        buf.clear();
+    }
+
+    Ok(entries)
+}
+
+/// Read JMdict XML or EDICT from a file (format detected from the first byte)
+fn read_source(path: &Path) -> Result<Vec<OfflineEntry>, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open dictionary: {}", e))?;
+    let mut reader = BufReader::new(file);
+
+    let is_xml = reader
+        .fill_buf()
+        .map_err(|e| e.to_string())?
+        .iter()
+        // Skip whitespace and a UTF-8 BOM
+        .find(|b| !b.is_ascii_whitespace() && ![0xEF, 0xBB, 0xBF].contains(*b))
+        == Some(&b'<');
+
+    if is_xml {
+        parse_jmdict(reader)
+    } else {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(|e| format!("EDICT must be UTF-8 encoded: {}", e))?;
+        Ok(content.lines().filter_map(parse_edict_line).collect())
+    }
+}
+
+/// Normalize an English gloss into a reverse-lookup key ("to eat" → "eat")
+fn english_key(gloss: &str) -> Option<String> {
+    // Drop trailing qualifiers: "bank (of a river)" → "bank"
+    let gloss = match gloss.find('(') {
+        Some(pos) => &gloss[..pos],
+        None => gloss,
+    };
+    let gloss = gloss.trim().to_lowercase();
+    let gloss = gloss.strip_prefix("to ").unwrap_or(&gloss);
+    if gloss.is_empty() || gloss.split_whitespace().count() > MAX_GLOSS_KEY_WORDS {
+        return None;
+    }
+    Some(format!("{}{}", ENGLISH_KEY_PREFIX, gloss))
+}
+
+/// Write entries to `dir` as a data file (one JSON record per line) and a key index
+pub fn build_index(entries: &[OfflineEntry], dir: &Path) -> Result<usize, String> {
+    let count = write_index(entries, dir)?;
+    install_index(dir)?;
+    Ok(count)
+}
+
+fn temp_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.tmp", name))
+}
+
+/// Write the data file and key index next to the live ones (`*.tmp`)
+fn write_index(entries: &[OfflineEntry], dir: &Path) -> Result<usize, String> {
+    std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+    let mut keys: HashMap<String, Vec<u64>> = HashMap::new();
+    let data_file = File::create(temp_path(dir, DATA_FILE)).map_err(|e| e.to_string())?;
+    let mut data = BufWriter::new(data_file);
+    let mut offset: u64 = 0;
+
+    for entry in entries {
+        let line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        data.write_all(line.as_bytes())
+            .and_then(|_| data.write_all(b"\n"))
+            .map_err(|e| e.to_string())?;
+
+        for form in entry.kanji.iter().chain(entry.readings.iter()) {
+            let offsets = keys.entry(form.clone()).or_default();
+            if offsets.last() != Some(&offset) {
+                offsets.push(offset);
+            }
+        }
+        for gloss in entry.senses.iter().flat_map(|s| s.glosses.iter()) {
+            if let Some(key) = english_key(gloss) {
+                let offsets = keys.entry(key).or_default();
+                if offsets.len() < MAX_OFFSETS_PER_ENGLISH_KEY && offsets.last() != Some(&offset) {
+                    offsets.push(offset);
+                }
+            }
+        }
+
+        offset += line.len() as u64 + 1;
+    }
+    data.flush().map_err(|e| e.to_string())?;
+
+    let mut sorted: Vec<_> = keys.into_iter().collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let index_file = File::create(temp_path(dir, KEYS_FILE)).map_err(|e| e.to_string())?;
+    let mut index = BufWriter::new(index_file);
+    writeln!(index, "{}", INDEX_HEADER).map_err(|e| e.to_string())?;
+    for (key, offsets) in sorted {
+        let offsets = offsets
+            .iter()
+            .map(|o| o.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(index, "{}\t{}", key, offsets).map_err(|e| e.to_string())?;
+    }
+    index.flush().map_err(|e| e.to_string())?;
+
+    Ok(entries.len())
+}
+
+/// Move the files from `write_index` over the live ones.
+// WHY: Rewriting entries.dat in place would hand indexes that are already open
+// (this process or the MCP server) garbage at their old offsets. A renamed file
+// is a new inode; open indexes keep reading the old one until they are reopened.
+fn install_index(dir: &Path) -> Result<(), String> {
+    for name in [DATA_FILE, KEYS_FILE] {
+        std::fs::rename(temp_path(dir, name), dir.join(name)).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// ==================== Lookup ====================
+
+/// Key index loaded in memory; entries are read from the data file on demand
+pub struct OfflineIndex {
+    /// Opened with the key index, so offsets always match the file they came from
+    data: Mutex<File>,
+    keys: HashMap<String, Vec<u64>>,
+}
+
+impl OfflineIndex {
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        let file = File::open(dir.join(KEYS_FILE)).map_err(|e| e.to_string())?;
+        let data = File::open(dir.join(DATA_FILE)).map_err(|e| e.to_string())?;
+        let mut lines = BufReader::new(file).lines();
+
+        match lines.next() {
+            Some(Ok(header)) if header == INDEX_HEADER => {}
+            _ => return Err("Unsupported offline dictionary index format".to_string()),
+        }
+
+        let mut keys = HashMap::new();
+        for line in lines {
+            let line = line.map_err(|e| e.to_string())?;
+            if let Some((key, offsets)) = line.split_once('\t') {
+                let offsets = offsets.split(',').filter_map(|o| o.parse().ok()).collect();
+                keys.insert(key.to_string(), offsets);
+            }
+        }
+
+        Ok(Self {
+            data: Mutex::new(data),
+            keys,
+        })
+    }
+
+    fn read_entries(&self, key: &str) -> Vec<OfflineEntry> {
+        let Some(offsets) = self.keys.get(key) else {
+            return Vec::new();
+        };
+        let Ok(mut file) = self.data.lock() else {
+            return Vec::new();
+        };
+        let mut reader = BufReader::new(&mut *file);
+
+        offsets
+            .iter()
+            .filter_map(|offset| {
+                reader.seek(SeekFrom::Start(*offset)).ok()?;
+                let mut line = String::new();
+                reader.read_line(&mut line).ok()?;
+                serde_json::from_str(&line).ok()
+            })
+            .collect()
+    }
+
+    /// Look up a Japanese word (with deinflection) or an English word (reverse lookup)
+    pub fn lookup(&self, word: &str) -> Option<DictionaryEntry> {
+        let word = word.trim();
+        if word.is_empty() {
+            return None;
+        }
+        if word.chars().any(is_cjk) {
+            self.lookup_japanese(word)
+        } else {
+            self.lookup_english(word)
+        }
+    }
+
+    fn lookup_japanese(&self, word: &str) -> Option<DictionaryEntry> {
+        for candidate in deinflect(word) {
+            let matched = self
+                .read_entries(&candidate.word)
+                .into_iter()
+                .find(|entry| {
+                    // Deinflected forms must match the entry's conjugation class
+                    candidate.word_types == 0 || entry.word_types() & candidate.word_types != 0
+                });
+            if let Some(entry) = matched {
+                let inflection =
+                    (!candidate.reasons.is_empty()).then(|| candidate.reasons.join(" < "));
+                return Some(to_dictionary_entry(&candidate.word, &entry, inflection));
+            }
+        }
+        None
+    }
+
+    /// Find the dictionary form written with kanji for `surface` (possibly inflected).
+    /// Returns (dictionary form, reading in kana).
+    pub fn match_reading(&self, surface: &str) -> Option<(String, String)> {
+        deinflect(surface).into_iter().find_map(|candidate| {
+            self.read_entries(&candidate.word)
+                .into_iter()
+                .find(|entry| {
+                    entry.kanji.contains(&candidate.word)
+                        && (candidate.word_types == 0
+                            || entry.word_types() & candidate.word_types != 0)
+                })
+                .and_then(|entry| entry.readings.first().cloned())
+                .map(|reading| (candidate.word, reading))
+        })
+    }
+
+    fn lookup_english(&self, word: &str) -> Option<DictionaryEntry> {
+        let key = english_key(word)?;
+        let entries = self.read_entries(&key);
+        if entries.is_empty() {
+            return None;
+        }
+
+        let senses = entries
+            .iter()
+            .take(MAX_REVERSE_RESULTS)
+            .filter_map(|entry| {
+                let headword = entry.headword()?;
+                let reading = entry.readings.first().filter(|r| r.as_str() != headword);
+                Some(Sense {
+                    gloss: match reading {
+                        Some(reading) => format!("{} ({})", headword, reading),
+                        None => headword.to_string(),
+                    },
+                    note: entry.senses.first().map(|s| s.glosses.join("; ")),
+                })
+            })
+            .collect();
+
+        Some(DictionaryEntry {
+            headword: word.trim().to_string(),
+            reading: None,
+            part_of_speech: None,
+            senses,
+            examples: Vec::new(),
+            inflection: None,
+        })
+    }
+}
+
+fn to_dictionary_entry(
+    matched_form: &str,
+    entry: &OfflineEntry,
+    inflection: Option<String>,
+) -> DictionaryEntry {
+    let headword = if entry.readings.iter().any(|r| r == matched_form) {
+        // Matched via reading: still show the usual kanji writing
+        entry.headword().unwrap_or(matched_form)
+    } else {
+        matched_form
+    };
+
+    DictionaryEntry {
+        headword: headword.to_string(),
+        reading: entry.readings.first().cloned(),
+        part_of_speech: entry
+            .senses
+            .first()
+            .filter(|s| !s.pos.is_empty())
+            .map(|s| s.pos.join(", ")),
+        senses: entry
+            .senses
+            .iter()
+            .map(|s| Sense {
+                gloss: s.glosses.join("; "),
+                note: None,
+            })
+            .collect(),
+        examples: Vec::new(),
+        inflection,
+    }
+}
+
+// ==================== Deinflection ====================
+
+// Word-type bits for deinflection rules (Yomichan-style)
+const V1: u8 = 1 << 0; // ichidan verb
+const V5: u8 = 1 << 1; // godan verb
+const VS: u8 = 1 << 2; // suru verb
+const VK: u8 = 1 << 3; // kuru verb
+const ADJ_I: u8 = 1 << 4; // i-adjective
+const TE: u8 = 1 << 5; // te-form (intermediate only)
+
+fn pos_to_word_type(pos: &str) -> u8 {
+    match pos {
+        p if p.starts_with("v1") => V1,
+        p if p.starts_with("v5") => V5,
+        "vs-i" | "vs-s" => VS,
+        "vk" => VK,
+        "adj-i" | "adj-ix" => ADJ_I,
+        _ => 0,
+    }
+}
+
+/// A candidate dictionary form produced by deinflection
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deinflection {
+    pub word: String,
+    /// Required word type of the dictionary entry (0 = unrestricted, original input)
+    word_types: u8,
+    /// Applied inflections, outermost first (e.g. ["negative", "past"])
+    pub reasons: Vec<&'static str>,
+}
+
+struct Rule {
+    from: String,
+    to: &'static str,
+    /// Word type of the inflected form (0 = only valid as the outermost inflection)
+    rules_in: u8,
+    /// Word type of the produced base form
+    rules_out: u8,
+    reason: &'static str,
+}
+
+/// Verb stems: (stem ending, dictionary ending, word type)
+type Stems = &'static [(&'static str, &'static str, u8)];
+
+// i-stem (連用形), used by ます/たい/ながら
+const I_STEMS: Stems = &[
+    ("", "る", V1),
+    ("い", "う", V5),
+    ("き", "く", V5),
+    ("ぎ", "ぐ", V5),
+    ("し", "す", V5),
+    ("ち", "つ", V5),
+    ("に", "ぬ", V5),
+    ("び", "ぶ", V5),
+    ("み", "む", V5),
+    ("り", "る", V5),
+    ("し", "する", VS),
+    ("き", "くる", VK),
+    ("来", "来る", VK),
+];
+
+// a-stem (未然形), used by ない/ず
+const A_STEMS: Stems = &[
+    ("", "る", V1),
+    ("わ", "う", V5),
+    ("か", "く", V5),
+    ("が", "ぐ", V5),
+    ("さ", "す", V5),
+    ("た", "つ", V5),
+    ("な", "ぬ", V5),
+    ("ば", "ぶ", V5),
+    ("ま", "む", V5),
+    ("ら", "る", V5),
+    ("し", "する", VS),
+    ("こ", "くる", VK),
+    ("来", "来る", VK),
+];
+
+// te/ta stem (音便): the ending that precedes て/た (or で/だ)
+const TE_STEMS: Stems = &[
+    // 行く is irregular (行った, not 行いた); listed first so it wins over 行う
+    ("行っ", "行く", V5),
+    ("いっ", "いく", V5),
+    ("", "る", V1),
+    ("っ", "う", V5),
+    ("っ", "つ", V5),
+    ("っ", "る", V5),
+    ("い", "く", V5),
+    ("い", "ぐ", V5), // voiced: いで/いだ
+    ("し", "す", V5),
+    ("ん", "ぬ", V5), // voiced: んで/んだ
+    ("ん", "ぶ", V5),
+    ("ん", "む", V5),
+    ("し", "する", VS),
+    ("き", "くる", VK),
+    ("来", "来る", VK),
+];
+
+// e-stem + る: godan potential (書ける → 書く), conjugates as ichidan
+const POTENTIAL_STEMS: Stems = &[
+    ("え", "う", V5),
+    ("け", "く", V5),
+    ("げ", "ぐ", V5),
+    ("せ", "す", V5),
+    ("て", "つ", V5),
+    ("ね", "ぬ", V5),
+    ("べ", "ぶ", V5),
+    ("め", "む", V5),
+    ("れ", "る", V5),
+];
+
+// o-stem + う: volitional
+const VOLITIONAL_STEMS: Stems = &[
+    ("よう", "る", V1),
+    ("おう", "う", V5),
+    ("こう", "く", V5),
+    ("ごう", "ぐ", V5),
+    ("そう", "す", V5),
+    ("とう", "つ", V5),
+    ("のう", "ぬ", V5),
+    ("ぼう", "ぶ", V5),
+    ("もう", "む", V5),
+    ("ろう", "る", V5),
+    ("しよう", "する", VS),
+    ("こよう", "くる", VK),
+];
+
+/// Whether the te/ta form of this stem is voiced (で/だ instead of て/た)
+fn is_voiced_te(dictionary_ending: &str) -> bool {
+    matches!(dictionary_ending, "ぐ" | "ぬ" | "ぶ" | "む")
+}
+
+static RULES: Lazy<Vec<Rule>> = Lazy::new(|| {
+    let mut rules = Vec::new();
+    let mut add = |from: String, to: &'static str, rules_in: u8, rules_out: u8, reason| {
+        rules.push(Rule {
+            from,
+            to,
+            rules_in,
+            rules_out,
+            reason,
+        })
+    };
+
+    // Verb suffixes on the i-stem
+    for &(stem, base, ty) in I_STEMS {
+        for (suffix, rules_in, reason) in [
+            ("ます", 0, "polite"),
+            ("ました", 0, "polite past"),
+            ("ません", 0, "polite negative"),
+            ("ませんでした", 0, "polite past negative"),
+            ("ましょう", 0, "polite volitional"),
+            ("たい", ADJ_I, "want"),
+            ("ながら", 0, "while"),
+            ("なさい", 0, "imperative"),
+        ] {
+            add(format!("{}{}", stem, suffix), base, rules_in, ty, reason);
+        }
+    }
+
+    // Verb suffixes on the a-stem
+    for &(stem, base, ty) in A_STEMS {
+        add(format!("{}ない", stem), base, ADJ_I, ty, "negative");
+        add(format!("{}ず", stem), base, 0, ty, "negative");
+        if ty == VS {
+            continue; // される/させる are added below (さ-stem, not し)
+        }
+        let (passive, causative) = if ty == V5 {
+            ("れる", "せる")
+        } else {
+            ("られる", "させる")
+        };
+        add(format!("{}{}", stem, passive), base, V1, ty, "passive");
+        add(format!("{}{}", stem, causative), base, V1, ty, "causative");
+    }
+    add("される".into(), "する", V1, VS, "passive");
+    add("させる".into(), "する", V1, VS, "causative");
+
+    // te-form and past
+    for &(stem, base, ty) in TE_STEMS {
+        let (te, ta) = if is_voiced_te(base) {
+            ("で", "だ")
+        } else {
+            ("て", "た")
+        };
+        add(format!("{}{}", stem, te), base, TE, ty, "te");
+        add(format!("{}{}", stem, ta), base, 0, ty, "past");
+        add(format!("{}{}ら", stem, ta), base, 0, ty, "conditional");
+    }
+    // Progressive: ている/でいる (いる conjugates as ichidan), colloquial てる
+    add("いる".into(), "", V1, TE, "progressive");
+    add("てる".into(), "て", V1, TE, "progressive");
+    add("でる".into(), "で", V1, TE, "progressive");
+
+    // Godan potential
+    for &(stem, base, ty) in POTENTIAL_STEMS {
+        add(format!("{}る", stem), base, V1, ty, "potential");
+    }
+
+    // Volitional
+    for &(stem, base, ty) in VOLITIONAL_STEMS {
+        add(stem.to_string(), base, 0, ty, "volitional");
+    }
+
+    // Conditional ば: e-stem + ば
+    add("れば".into(), "る", 0, V1 | V5, "conditional");
+    for &(stem, base, ty) in POTENTIAL_STEMS {
+        add(format!("{}ば", stem), base, 0, ty, "conditional");
+    }
+    add("すれば".into(), "する", 0, VS, "conditional");
+    add("くれば".into(), "くる", 0, VK, "conditional");
+
+    // i-adjectives
+    add("くない".into(), "い", ADJ_I, ADJ_I, "negative");
+    add("かった".into(), "い", 0, ADJ_I, "past");
+    add("くて".into(), "い", TE, ADJ_I, "te");
+    add("ければ".into(), "い", 0, ADJ_I, "conditional");
+    add("く".into(), "い", 0, ADJ_I, "adverb");
+    add("さ".into(), "い", 0, ADJ_I, "noun");
+    add("そう".into(), "い", 0, ADJ_I, "looks like");
+
+    rules
+});
+
+/// Generate candidate dictionary forms for an inflected Japanese word.
+/// The first candidate is always the input itself (unrestricted word type).
+pub fn deinflect(word: &str) -> Vec<Deinflection> {
+    let mut results = vec![Deinflection {
+        word: word.to_string(),
+        word_types: 0,
+        reasons: Vec::new(),
+    }];
+
+    let mut i = 0;
+    while i < results.len() && results.len() < MAX_DEINFLECTIONS {
+        let current = results[i].clone();
+        for rule in RULES.iter() {
+            if current.word_types != 0 && current.word_types & rule.rules_in == 0 {
+                continue;
+            }
+            let Some(prefix) = current.word.strip_suffix(rule.from.as_str()) else {
+                continue;
+            };
+            let base = format!("{}{}", prefix, rule.to);
+            // Progressive rule may strip everything (いる → "")
+            if base.is_empty() {
+                continue;
+            }
+            if results
+                .iter()
+                .any(|r| r.word == base && r.word_types == rule.rules_out)
+            {
+                continue;
+            }
+
+            let mut reasons = vec![rule.reason];
+            reasons.extend(current.reasons.iter().copied());
+            results.push(Deinflection {
+                word: base,
+                word_types: rule.rules_out,
+                reasons,
+            });
+        }
+        i += 1;
+    }
+
+    results
+}
+
+// ==================== App Integration ====================
+
+fn index_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path().app_data_dir().ok().map(|d| d.join(INDEX_DIR))
+}
+
+/// Get the loaded index, opening it from disk on first use
+pub(crate) fn get_index(app: &AppHandle) -> Option<Arc<OfflineIndex>> {
+    open_index(index_dir(app)?)
+}
+
+fn open_index(dir: PathBuf) -> Option<Arc<OfflineIndex>> {
+    let mut guard = INDEX.lock().ok()?;
+    if guard.is_none() {
+        if !dir.join(KEYS_FILE).exists() {
+            return None;
+        }
+        match OfflineIndex::open(&dir) {
+            Ok(index) => {
+                info!("Offline dictionary loaded ({} keys)", index.keys.len());
+                *guard = Some(Arc::new(index));
+            }
+            Err(e) => {
+                warn!("Failed to open offline dictionary: {}", e);
+                return None;
+            }
+        }
+    }
+    guard.clone()
+}
+
+/// Look up a word in the offline dictionary (None if not imported or not found)
+pub fn lookup(app: &AppHandle, word: &str) -> Option<DictionaryEntry> {
+    get_index(app)?.lookup(word)
+}
+
+/// `lookup` for processes without an AppHandle (MCP server), using the app's index
+pub fn lookup_default(word: &str) -> Option<DictionaryEntry> {
+    open_index(settings::app_data_dir()?.join(INDEX_DIR))?.lookup(word)
+}
+
+/// Import JMdict XML or EDICT from `path`, replacing any previous index.
+/// Returns the number of imported entries.
+pub fn import(app: &AppHandle, path: &Path) -> Result<usize, String> {
+    let dir = index_dir(app).ok_or("Failed to resolve app data directory")?;
+    info!("Importing offline dictionary from {:?}", path);
+
+    let entries = read_source(path)?;
+    if entries.is_empty() {
+        return Err("No dictionary entries found. Expected JMdict XML or UTF-8 EDICT.".into());
+    }
+    let count = write_index(&entries, &dir)?;
+
+    // Swap the files and drop the cached index under the index lock, so no lookup
+    // opens the data file of one import with the keys of the other
+    let mut guard = INDEX.lock().map_err(|e| e.to_string())?;
+    install_index(&dir)?;
+    *guard = None;
+    info!("Offline dictionary imported: {} entries", count);
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::TempStore;
+
+    fn deinflects_to(word: &str, base: &str) -> Option<Vec<&'static str>> {
+        deinflect(word)
+            .into_iter()
+            .find(|d| d.word == base)
+            .map(|d| d.reasons)
+    }
+
+    #[test]
+    fn test_deinflect_verbs() {
+        assert_eq!(
+            deinflects_to("食べました", "食べる"),
+            Some(vec!["polite past"])
+        );
+        assert_eq!(deinflects_to("読んで", "読む"), Some(vec!["te"]));
+        assert_eq!(
+            deinflects_to("行かなかった", "行く"),
+            Some(vec!["negative", "past"])
+        );
+        assert_eq!(deinflects_to("行った", "行く"), Some(vec!["past"]));
+        assert_eq!(
+            deinflects_to("食べている", "食べる"),
+            Some(vec!["te", "progressive"])
+        );
+        assert_eq!(
+            deinflects_to("食べたくない", "食べる"),
+            Some(vec!["want", "negative"])
+        );
+        assert_eq!(deinflects_to("した", "する"), Some(vec!["past"]));
+        assert_eq!(deinflects_to("書ける", "書く"), Some(vec!["potential"]));
+    }
+
+    #[test]
+    fn test_deinflect_adjectives() {
+        assert_eq!(deinflects_to("高くない", "高い"), Some(vec!["negative"]));
+        assert_eq!(deinflects_to("高かった", "高い"), Some(vec!["past"]));
+    }
+
+    #[test]
+    fn test_deinflect_keeps_original_first() {
+        let results = deinflect("猫");
+        assert_eq!(results[0].word, "猫");
+        assert!(results[0].reasons.is_empty());
+    }
+
+    #[test]
+    fn test_parse_edict_line() {
+        let entry = parse_edict_line(
+            "食べる;喰べる(iK) [たべる] /(v1,vt) (1) to eat/(2) to live on (e.g. a salary)/(P)/EntL1358280X/",
+        )
+        .unwrap();
+        assert_eq!(entry.kanji, vec!["食べる", "喰べる"]);
+        assert_eq!(entry.readings, vec!["たべる"]);
+        assert_eq!(entry.senses.len(), 2);
+        assert_eq!(entry.senses[0].pos, vec!["v1", "vt"]);
+        assert_eq!(entry.senses[0].glosses, vec!["to eat"]);
+        assert_eq!(entry.senses[1].glosses, vec!["to live on (e.g. a salary)"]);
+
+        // Kana-only entry
+        let entry = parse_edict_line("すごい /(adj-i) terrific/amazing/").unwrap();
+        assert!(entry.kanji.is_empty());
+        assert_eq!(entry.readings, vec!["すごい"]);
+        assert_eq!(entry.senses[0].glosses, vec!["terrific", "amazing"]);
+
+        assert!(parse_edict_line("").is_none());
+    }
+
+    #[test]
+    fn test_parse_jmdict() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE JMdict [
+<!ENTITY v5k "Godan verb with 'ku' ending">
+<!ENTITY vi "intransitive verb">
+]>
+<JMdict>
+<entry>
+<ent_seq>1578850</ent_seq>
+<k_ele><keb>行く</keb></k_ele>
+<r_ele><reb>いく</reb></r_ele>
+<sense>
+<pos>&v5k;</pos>
+<pos>&vi;</pos>
+<gloss>to go</gloss>
+<gloss xml:lang="ger">gehen</gloss>
+</sense>
+<sense>
+<gloss>to proceed &amp; progress</gloss>
+</sense>
+</entry>
+</JMdict>"#;
+        let entries = parse_jmdict(xml.as_bytes()).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].kanji, vec!["行く"]);
+        assert_eq!(entries[0].senses[0].pos, vec!["v5k", "vi"]);
+        assert_eq!(entries[0].senses[0].glosses, vec!["to go"]);
+        // POS carries over to the following sense
+        assert_eq!(entries[0].senses[1].pos, vec!["v5k", "vi"]);
+        assert_eq!(entries[0].senses[1].glosses, vec!["to proceed & progress"]);
+    }
+
+    #[test]
+    fn test_index_round_trip() {
+        let temp = TempStore::new("dict-test");
+        let entries: Vec<OfflineEntry> = [
+            "食べる [たべる] /(v1,vt) to eat/",
+            "高い [たかい] /(adj-i) high/tall/expensive/",
+            "猫 [ねこ] /(n) cat/",
+        ]
+        .iter()
+        .filter_map(|l| parse_edict_line(l))
+        .collect();
+        assert_eq!(build_index(&entries, &temp.dir).unwrap(), 3);
+
+        let index = OfflineIndex::open(&temp.dir).unwrap();
+
+        let entry = index.lookup("食べなかった").unwrap();
+        assert_eq!(entry.headword, "食べる");
+        assert_eq!(entry.reading.as_deref(), Some("たべる"));
+        assert_eq!(entry.inflection.as_deref(), Some("negative < past"));
+        assert_eq!(entry.summary(), "to eat");
+
+        // Reading lookup shows kanji headword
+        assert_eq!(index.lookup("ねこ").unwrap().headword, "猫");
+
+        // English reverse lookup
+        let entry = index.lookup("Eat").unwrap();
+        assert_eq!(entry.senses[0].gloss, "食べる (たべる)");
+
+        assert!(index.lookup("犬").is_none());
+
+        // A rebuild replaces the files; the open index keeps reading its own data
+        let entries: Vec<OfflineEntry> = ["犬 [いぬ] /(n) dog/"]
+            .iter()
+            .filter_map(|l| parse_edict_line(l))
+            .collect();
+        assert_eq!(build_index(&entries, &temp.dir).unwrap(), 1);
+        assert_eq!(index.lookup("ねこ").unwrap().headword, "猫");
+        let index = OfflineIndex::open(&temp.dir).unwrap();
+        assert_eq!(index.lookup("いぬ").unwrap().headword, "犬");
+        assert!(index.lookup("ねこ").is_none());
+    }
+}
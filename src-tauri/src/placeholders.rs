@@ -0,0 +1,286 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::error::TranslateError;
+use crate::settings::{EMAIL_REGEX, URL_REGEX};
+
+// Patterns that must survive translation byte for byte
+static INLINE_CODE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"`[^`\n]+`").unwrap());
+static MUSTACHE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{\{[^{}\n]*\}\}").unwrap());
+static TEMPLATE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\$\{[^{}\n]*\}").unwrap());
+// {name}, {0}, {count:02d}, {}
+static BRACE_VAR_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\{(?:[A-Za-z_][\w.]*|\d+)?(?::[^{}\n]*)?\}").unwrap());
+// printf-style: %s, %d, %1$s, %-5.2f, %lld, %@, %% (no space flag: "50% sure")
+static PRINTF_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"%(?:\d+\$)?[-+0#]*\d*(?:\.\d+)?(?:hh|h|ll|l|L|z|j|t)?[diouxXeEfgGcsp@%]").unwrap()
+});
+// HTML/XML tags, including `</text_to_translate>` typed into the text
+static HTML_TAG_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"</?[A-Za-z][A-Za-z0-9_:-]*(?:\s+[^<>]*?)?/?>"#).unwrap());
+static SENTINEL_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"⟦P(\d+)⟧").unwrap());
+
+fn sentinel(index: usize) -> String {
+    format!("⟦P{}⟧", index)
+}
+
+/// Text with placeholders replaced by sentinel tokens (`⟦P0⟧`, `⟦P1⟧`, ...)
+#[derive(Debug, Clone)]
+pub struct Protected {
+    pub text: String,
+    originals: Vec<String>,
+}
+
+/// Replace placeholders that the model must not translate or reorder.
+/// Earlier patterns win on overlap (a URL containing `%20` stays one placeholder).
+pub fn protect(text: &str) -> Protected {
+    let patterns: [&Regex; 8] = [
+        &INLINE_CODE_REGEX,
+        &HTML_TAG_REGEX,
+        &URL_REGEX,
+        &EMAIL_REGEX,
+        &MUSTACHE_REGEX,
+        &TEMPLATE_REGEX,
+        &BRACE_VAR_REGEX,
+        &PRINTF_REGEX,
+    ];
+
+    let spans = find_spans(text, &patterns);
+    let mut protected = String::with_capacity(text.len());
+    let mut originals = Vec::with_capacity(spans.len());
+    let mut cursor = 0;
+    for (start, end, _) in spans {
+        protected.push_str(&text[cursor..start]);
+        protected.push_str(&sentinel(originals.len()));
+        originals.push(text[start..end].to_string());
+        cursor = end;
+    }
+    protected.push_str(&text[cursor..]);
+
+    Protected {
+        text: protected,
+        originals,
+    }
+}
+
+/// Non-overlapping matches of `patterns` as (start, end, pattern index), sorted by start.
+/// Earlier patterns win; a match that runs into an earlier span is cut short there.
+pub fn find_spans(text: &str, patterns: &[&Regex]) -> Vec<(usize, usize, usize)> {
+    let mut spans: Vec<(usize, usize, usize)> = Vec::new();
+    for (index, pattern) in patterns.iter().enumerate() {
+        for m in pattern.find_iter(text) {
+            let start = m.start();
+            if spans.iter().any(|&(s, e, _)| s <= start && start < e) {
+                continue;
+            }
+            // URL_REGEX runs to the next whitespace; stop at an earlier span ("…/a</a>")
+            let end = spans
+                .iter()
+                .map(|&(s, _, _)| s)
+                .filter(|&s| start < s && s < m.end())
+                .min()
+                .unwrap_or(m.end());
+            spans.push((start, end, index));
+        }
+    }
+    spans.sort_unstable();
+    spans
+}
+
+/// Token substitution that can be applied to streamed text
+pub trait Substitute {
+    /// First and last character of every token
+    const OPEN: char;
+    const CLOSE: char;
+    /// Longest possible token, in chars
+    const MAX_TOKEN_CHARS: usize;
+
+    /// Replace known tokens with their originals (unknown tokens are left as is)
+    fn substitute(&self, text: &str) -> String;
+}
+
+impl Substitute for Protected {
+    const OPEN: char = '⟦';
+    const CLOSE: char = '⟧';
+    const MAX_TOKEN_CHARS: usize = 12;
+
+    fn substitute(&self, text: &str) -> String {
+        SENTINEL_REGEX
+            .replace_all(text, |caps: &regex::Captures| {
+                caps[1]
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| self.originals.get(i))
+                    .cloned()
+                    .unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    }
+}
+
+impl Protected {
+    pub fn is_empty(&self) -> bool {
+        self.originals.is_empty()
+    }
+
+    /// Check that every sentinel appears exactly once in the translation
+    pub fn validate(&self, translated: &str) -> Result<(), TranslateError> {
+        let mut counts = vec![0usize; self.originals.len()];
+        for caps in SENTINEL_REGEX.captures_iter(translated) {
+            if let Some(count) = caps[1]
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| counts.get_mut(i))
+            {
+                *count += 1;
+            }
+        }
+
+        let select = |keep: fn(usize) -> bool| -> Vec<String> {
+            counts
+                .iter()
+                .zip(&self.originals)
+                .filter(|(count, _)| keep(**count))
+                .map(|(_, original)| original.clone())
+                .collect()
+        };
+        let missing = select(|count| count == 0);
+        let duplicated = select(|count| count > 1);
+
+        if missing.is_empty() && duplicated.is_empty() {
+            Ok(())
+        } else {
+            Err(TranslateError::PlaceholderMismatch {
+                missing,
+                duplicated,
+            })
+        }
+    }
+
+    /// Validate and restore the original placeholders
+    pub fn restore(&self, translated: &str) -> Result<String, TranslateError> {
+        self.validate(translated)?;
+        Ok(self.substitute(translated))
+    }
+
+    pub fn stream_restorer(&self) -> StreamRestorer<'_, Self> {
+        StreamRestorer::new(self)
+    }
+}
+
+/// Restores tokens in streamed chunks, holding back a token split across chunks
+pub struct StreamRestorer<'a, T: Substitute> {
+    tokens: &'a T,
+    pending: String,
+}
+
+impl<'a, T: Substitute> StreamRestorer<'a, T> {
+    pub fn new(tokens: &'a T) -> Self {
+        Self {
+            tokens,
+            pending: String::new(),
+        }
+    }
+
+    /// Feed a chunk; returns the text that is safe to display
+    pub fn push(&mut self, chunk: &str) -> String {
+        self.pending.push_str(chunk);
+
+        // Hold back from an unterminated token start (unless it can no longer be a token)
+        let split = match self.pending.rfind(T::OPEN) {
+            Some(open)
+                if !self.pending[open..].contains(T::CLOSE)
+                    && self.pending[open..].chars().count() < T::MAX_TOKEN_CHARS =>
+            {
+                open
+            }
+            _ => self.pending.len(),
+        };
+        let ready: String = self.pending.drain(..split).collect();
+        self.tokens.substitute(&ready)
+    }
+
+    /// Flush whatever is still held back at the end of the stream
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        self.tokens.substitute(&rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_protect_developer_strings() {
+        let protected = protect("Hello {name}, you have %d messages. %1$s ${user} {{count}}");
+        assert_eq!(
+            protected.text,
+            "Hello ⟦P0⟧, you have ⟦P1⟧ messages. ⟦P2⟧ ⟦P3⟧ ⟦P4⟧"
+        );
+        assert_eq!(
+            protected.originals,
+            ["{name}", "%d", "%1$s", "${user}", "{{count}}"]
+        );
+    }
+
+    #[test]
+    fn test_protect_urls_code_and_html() {
+        let protected = protect(
+            "Run `npm i` and see <a href=\"https://example.com/a%20b\">docs</a> or mail dev@example.com",
+        );
+        assert_eq!(
+            protected.originals,
+            [
+                "`npm i`",
+                "<a href=\"https://example.com/a%20b\">",
+                "</a>",
+                "dev@example.com"
+            ]
+        );
+        assert!(!protected.text.contains("https://"));
+    }
+
+    #[test]
+    fn test_protect_leaves_prose_alone() {
+        let protected = protect("I'm 50% sure {this is prose} costs $5");
+        assert!(protected.is_empty());
+        assert_eq!(protected.text, "I'm 50% sure {this is prose} costs $5");
+    }
+
+    #[test]
+    fn test_restore_reordered_sentinels() {
+        let protected = protect("Hello {name}, you have %d messages");
+        let restored = protected
+            .restore("⟦P0⟧さん、⟦P1⟧件のメッセージがあります")
+            .unwrap();
+        assert_eq!(restored, "{name}さん、%d件のメッセージがあります");
+    }
+
+    #[test]
+    fn test_restore_reports_missing_and_duplicated() {
+        let protected = protect("Hello {name}, you have %d messages");
+        match protected.restore("⟦P0⟧さん、⟦P0⟧件") {
+            Err(TranslateError::PlaceholderMismatch {
+                missing,
+                duplicated,
+            }) => {
+                assert_eq!(missing, ["%d"]);
+                assert_eq!(duplicated, ["{name}"]);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_stream_restorer_handles_split_sentinels() {
+        let protected = protect("Hello {name}, you have %d messages");
+        let mut restorer = protected.stream_restorer();
+        let mut output = String::new();
+        for chunk in ["こんにちは⟦", "P0", "⟧さん、⟦P1⟧件", "です ⟦"] {
+            output.push_str(&restorer.push(chunk));
+        }
+        output.push_str(&restorer.finish());
+        assert_eq!(output, "こんにちは{name}さん、%d件です ⟦");
+    }
+}
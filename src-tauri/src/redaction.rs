@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::engine::EventSink;
+use crate::error::TranslateError;
+use crate::placeholders::{self, StreamRestorer, Substitute};
+use crate::settings::{EMAIL_REGEX, LONG_NUMBER_REGEX};
+
+// WHY: mask_sensitive_patterns only protects the cache preview; the full text still
+// reaches the API. In privacy mode PII is swapped for numbered placeholders before the
+// request and restored locally, so the originals never leave the machine.
+static PHONE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{1,4}\)[\s.-]?)?\d{2,4}[\s.-]\d{2,4}[\s.-]\d{3,4}\b")
+        .unwrap()
+});
+static IBAN_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,3})?\b").unwrap()
+});
+static PLACEHOLDER_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\[(?:EMAIL|PHONE|IBAN|NUMBER|CUSTOM)_\d+\]").unwrap());
+
+/// Kind of redacted value (also the placeholder prefix, e.g. `[EMAIL_1]`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum RedactionCategory {
+    Email,
+    Phone,
+    Iban,
+    Number,
+    Custom,
+}
+
+impl RedactionCategory {
+    fn prefix(self) -> &'static str {
+        match self {
+            Self::Email => "EMAIL",
+            Self::Phone => "PHONE",
+            Self::Iban => "IBAN",
+            Self::Number => "NUMBER",
+            Self::Custom => "CUSTOM",
+        }
+    }
+}
+
+/// One redacted value
+#[derive(Debug, Clone, Serialize)]
+pub struct RedactedItem {
+    pub placeholder: String,
+    pub category: RedactionCategory,
+    pub original: String,
+}
+
+/// What was redacted for a session (event payload for `translate-redaction`)
+#[derive(Debug, Clone, Serialize)]
+pub struct RedactionReport {
+    pub session_id: Option<String>,
+    pub items: Vec<RedactedItem>,
+}
+
+/// Replaces PII with numbered placeholders. The same value always gets the same
+/// placeholder, so several texts of one request (e.g. a conversation) stay consistent.
+pub struct Redactor {
+    custom: Vec<Regex>,
+    items: Vec<RedactedItem>,
+}
+
+impl Redactor {
+    pub fn new(custom_patterns: &[String]) -> Self {
+        let custom = custom_patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(regex) => Some(regex),
+                Err(e) => {
+                    warn!("Skipping invalid redaction pattern: {}", e);
+                    None
+                }
+            })
+            .collect();
+        Self {
+            custom,
+            items: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn placeholder_for(&mut self, category: RedactionCategory, original: &str) -> String {
+        if let Some(item) = self.items.iter().find(|i| i.original == original) {
+            return item.placeholder.clone();
+        }
+        let number = self.items.iter().filter(|i| i.category == category).count() + 1;
+        let placeholder = format!("[{}_{}]", category.prefix(), number);
+        self.items.push(RedactedItem {
+            placeholder: placeholder.clone(),
+            category,
+            original: original.to_string(),
+        });
+        placeholder
+    }
+
+    /// Replace PII in `text` with placeholders
+    pub fn redact(&mut self, text: &str) -> String {
+        // User patterns first: they are the most specific to the user's data
+        let mut patterns: Vec<(RedactionCategory, &Regex)> = self
+            .custom
+            .iter()
+            .map(|regex| (RedactionCategory::Custom, regex))
+            .collect();
+        patterns.extend([
+            (RedactionCategory::Email, &*EMAIL_REGEX),
+            (RedactionCategory::Iban, &*IBAN_REGEX),
+            (RedactionCategory::Phone, &*PHONE_REGEX),
+            (RedactionCategory::Number, &*LONG_NUMBER_REGEX),
+        ]);
+        let regexes: Vec<&Regex> = patterns.iter().map(|(_, regex)| *regex).collect();
+        let categories: Vec<RedactionCategory> = patterns.iter().map(|(c, _)| *c).collect();
+        let spans = placeholders::find_spans(text, &regexes);
+
+        let mut redacted = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for (start, end, index) in spans {
+            if start == end {
+                continue;
+            }
+            redacted.push_str(&text[cursor..start]);
+            redacted.push_str(&self.placeholder_for(categories[index], &text[start..end]));
+            cursor = end;
+        }
+        redacted.push_str(&text[cursor..]);
+        redacted
+    }
+
+    /// Restore originals in `output`. Every placeholder sent in `redacted_input`
+    /// must come back, otherwise the value would be silently lost.
+    pub fn restore(&self, redacted_input: &str, output: &str) -> Result<String, TranslateError> {
+        let mut missing: Vec<String> = PLACEHOLDER_REGEX
+            .find_iter(redacted_input)
+            .map(|m| m.as_str())
+            .filter(|placeholder| !output.contains(placeholder))
+            .map(String::from)
+            .collect();
+        missing.sort();
+        missing.dedup();
+        if !missing.is_empty() {
+            // Placeholder names only: the originals are what we are protecting
+            return Err(TranslateError::PlaceholderMismatch {
+                missing,
+                duplicated: Vec::new(),
+            });
+        }
+        Ok(self.substitute(output))
+    }
+
+    pub fn stream_restorer(&self) -> StreamRestorer<'_, Self> {
+        StreamRestorer::new(self)
+    }
+
+    pub fn report(&self, session_id: Option<&str>) -> RedactionReport {
+        RedactionReport {
+            session_id: session_id.map(String::from),
+            items: self.items.clone(),
+        }
+    }
+
+    /// Log counts and send the report (session None: popup translation)
+    pub fn emit_report(&self, sink: &dyn EventSink, session_id: Option<&str>) {
+        if self.is_empty() {
+            return;
+        }
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for item in &self.items {
+            *counts.entry(item.category.prefix()).or_default() += 1;
+        }
+        info!(
+            "Privacy mode redacted {} values: {:?}",
+            self.items.len(),
+            counts
+        );
+
+        sink.redaction(self.report(session_id));
+    }
+}
+
+/// Refuse a request that can't use placeholders (dictionary lookup, reading) when
+/// privacy mode would redact part of `text`. `patterns` is None when privacy mode is off.
+// WHY: A lookup result must match the text it was asked for, so PII can't be swapped
+// for a placeholder and restored the way translations do it.
+pub fn check_private(patterns: Option<&[String]>, text: &str) -> Result<(), TranslateError> {
+    let Some(patterns) = patterns else {
+        return Ok(());
+    };
+    let mut redactor = Redactor::new(patterns);
+    redactor.redact(text);
+    let mut categories: Vec<String> = redactor
+        .items
+        .iter()
+        .map(|item| item.category.prefix().to_lowercase())
+        .collect();
+    if categories.is_empty() {
+        return Ok(());
+    }
+    categories.sort();
+    categories.dedup();
+    Err(TranslateError::PrivateContentBlocked { categories })
+}
+
+impl Substitute for Redactor {
+    const OPEN: char = '[';
+    const CLOSE: char = ']';
+    const MAX_TOKEN_CHARS: usize = 16;
+
+    fn substitute(&self, text: &str) -> String {
+        PLACEHOLDER_REGEX
+            .replace_all(text, |caps: &regex::Captures| {
+                self.items
+                    .iter()
+                    .find(|item| item.placeholder == caps[0])
+                    .map(|item| item.original.clone())
+                    .unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_builtin_categories() {
+        let mut redactor = Redactor::new(&[]);
+        let redacted = redactor.redact(
+            "Mail jane@example.com or call +81 90-1234-5678. IBAN DE89 3704 0044 0532 0130 00, order 987654.",
+        );
+        assert_eq!(
+            redacted,
+            "Mail [EMAIL_1] or call [PHONE_1]. IBAN [IBAN_1], order [NUMBER_1]."
+        );
+    }
+
+    #[test]
+    fn test_redact_reuses_placeholders_and_custom_patterns() {
+        let mut redactor = Redactor::new(&[r"ACME-\d+".to_string(), "(".to_string()]);
+        let first = redactor.redact("Ticket ACME-42 from bob@example.com");
+        let second = redactor.redact("Reply to bob@example.com about ACME-7");
+        assert_eq!(first, "Ticket [CUSTOM_1] from [EMAIL_1]");
+        assert_eq!(second, "Reply to [EMAIL_1] about [CUSTOM_2]");
+
+        let report = redactor.report(Some("s1"));
+        assert_eq!(report.items.len(), 3);
+        assert_eq!(report.items[0].original, "ACME-42");
+    }
+
+    #[test]
+    fn test_check_private() {
+        let text = "Call bob@example.com or +81 90-1234-5678";
+        assert!(check_private(None, text).is_ok());
+        assert!(check_private(Some(&[]), "serendipity").is_ok());
+        assert!(matches!(
+            check_private(Some(&[]), text),
+            Err(TranslateError::PrivateContentBlocked { categories })
+                if categories == ["email", "phone"]
+        ));
+        let custom = [r"ACME-\d+".to_string()];
+        assert!(check_private(Some(&custom), "ACME-42").is_err());
+    }
+
+    #[test]
+    fn test_restore() {
+        let mut redactor = Redactor::new(&[]);
+        let redacted = redactor.redact("Contact jane@example.com");
+        assert_eq!(
+            redactor
+                .restore(&redacted, "[EMAIL_1] に連絡してください")
+                .unwrap(),
+            "jane@example.com に連絡してください"
+        );
+
+        match redactor.restore(&redacted, "連絡してください") {
+            Err(TranslateError::PlaceholderMismatch { missing, .. }) => {
+                assert_eq!(missing, ["[EMAIL_1]"]);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_stream_restorer() {
+        let mut redactor = Redactor::new(&[]);
+        redactor.redact("jane@example.com");
+        let mut restorer = redactor.stream_restorer();
+        let mut output = restorer.push("宛先: [EMA");
+        output.push_str(&restorer.push("IL_1] [note]"));
+        output.push_str(&restorer.finish());
+        assert_eq!(output, "宛先: jane@example.com [note]");
+    }
+}
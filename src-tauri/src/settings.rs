@@ -1,21 +1,36 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
-use tauri::AppHandle;
+use serde_json::{Map, Value};
+use tauri::{AppHandle, Manager, Runtime};
 use tauri_plugin_store::StoreExt;
 
+use crate::cache;
+use crate::history;
+
 // Regex patterns for masking sensitive data in cache previews
-static EMAIL_REGEX: Lazy<Regex> =
+// (URL/email are also protected from translation in placeholders.rs,
+// email/long numbers are redacted in privacy mode by redaction.rs)
+pub(crate) static EMAIL_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}").unwrap());
-static URL_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://[^\s]+").unwrap());
-static LONG_NUMBER_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d{4,}").unwrap());
+pub(crate) static URL_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://[^\s]+").unwrap());
+pub(crate) static LONG_NUMBER_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d{4,}").unwrap());
 
 const STORE_PATH: &str = "settings.json";
+/// Tauri identifier; the store lives in `<data dir>/<identifier>/`
+const APP_IDENTIFIER: &str = "com.ebiyy.traylingo";
 const MAX_ERROR_HISTORY: usize = 50;
-const MAX_TRANSLATION_CACHE: usize = 100; // Reduced from 500 for privacy
-const CACHE_TTL_SECS: i64 = 30 * 24 * 60 * 60; // 30 days
-const SOURCE_PREVIEW_LENGTH: usize = 30; // Reduced from 100 for privacy
+/// Usage recorded by the CLI and MCP server, one `UsageStats` delta per line
+const USAGE_LEDGER: &str = "usage_ledger.jsonl";
+/// A lock file older than this was left behind by a crashed process
+const STALE_LOCK: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
@@ -32,6 +47,84 @@ pub struct Settings {
     /// Enable translation cache (default: true)
     #[serde(default = "default_cache_enabled")]
     pub cache_enabled: bool,
+
+    /// Cached translations kept before the least recently used are evicted
+    #[serde(default = "default_cache_capacity")]
+    pub cache_capacity: usize,
+
+    /// Days a cached translation is served before it is fetched again
+    #[serde(default = "default_cache_ttl_days")]
+    pub cache_ttl_days: u32,
+
+    /// Entries kept per model (model id → count, 0: never cache); models not
+    /// listed only share `cache_capacity`
+    #[serde(default)]
+    pub cache_model_limits: BTreeMap<String, usize>,
+
+    /// Redact emails, phone numbers, etc. before sending text to the API (opt-in)
+    #[serde(default)]
+    pub privacy_mode: bool,
+
+    /// Extra regexes redacted in privacy mode
+    #[serde(default)]
+    pub redaction_patterns: Vec<String>,
+
+    /// What to do with translations that fail output validation
+    #[serde(default)]
+    pub output_validation: OutputValidation,
+
+    /// Serve translations to other local tools on 127.0.0.1 (opt-in)
+    #[serde(default)]
+    pub api_server_enabled: bool,
+
+    #[serde(default = "default_api_server_port")]
+    pub api_server_port: u16,
+
+    /// Bearer token for the local API (generated when the server is first enabled)
+    #[serde(default)]
+    pub api_server_token: String,
+
+    /// Use of similar earlier translations (cleared with the cache)
+    #[serde(default)]
+    pub translation_memory: MemoryMode,
+
+    /// Keep a searchable history of finished translations (opt-in, stored unencrypted)
+    #[serde(default)]
+    pub history_enabled: bool,
+
+    /// Unstarred history entries kept before the oldest are dropped
+    #[serde(default = "default_history_limit")]
+    pub history_limit: usize,
+
+    /// Days unstarred history entries are kept (0: until `history_limit` is reached)
+    #[serde(default = "default_history_retention_days")]
+    pub history_retention_days: u32,
+}
+
+/// Handling of translations with commentary, echoes or answered instructions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputValidation {
+    /// Don't check the output
+    Off,
+    /// Show a warning and skip the cache
+    #[default]
+    Flag,
+    /// Ask once more with a stricter reminder, flag if that fails too
+    Retry,
+}
+
+/// What to do with a translation memory match
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryMode {
+    /// Neither record nor look up segments
+    Off,
+    /// Show the match ("95% match") next to the new translation
+    #[default]
+    Suggest,
+    /// Also send the match to the model as a reference translation
+    Reference,
 }
 
 fn default_model() -> String {
@@ -46,12 +139,45 @@ fn default_cache_enabled() -> bool {
     true // Cache enabled by default
 }
 
+fn default_cache_capacity() -> usize {
+    cache::DEFAULT_CAPACITY
+}
+
+fn default_cache_ttl_days() -> u32 {
+    cache::DEFAULT_TTL_DAYS
+}
+
+fn default_history_limit() -> usize {
+    history::DEFAULT_LIMIT
+}
+
+fn default_history_retention_days() -> u32 {
+    history::DEFAULT_RETENTION_DAYS
+}
+
+fn default_api_server_port() -> u16 {
+    47811
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
             model: default_model(),
             send_telemetry: default_send_telemetry(),
             cache_enabled: default_cache_enabled(),
+            cache_capacity: default_cache_capacity(),
+            cache_ttl_days: default_cache_ttl_days(),
+            cache_model_limits: BTreeMap::new(),
+            privacy_mode: false,
+            redaction_patterns: Vec::new(),
+            output_validation: OutputValidation::default(),
+            api_server_enabled: false,
+            api_server_port: default_api_server_port(),
+            api_server_token: String::new(),
+            translation_memory: MemoryMode::default(),
+            history_enabled: false,
+            history_limit: default_history_limit(),
+            history_retention_days: default_history_retention_days(),
         }
     }
 }
@@ -70,6 +196,22 @@ pub const AVAILABLE_MODELS: &[(&str, &str)] = &[
     ("claude-3-5-haiku-20241022", "Claude 3.5 Haiku"),
 ];
 
+/// Full model id for an id or a short alias (`haiku` -> newest Haiku in AVAILABLE_MODELS)
+pub fn resolve_model(name: &str) -> Result<String, String> {
+    let prefix = format!("claude-{}", name);
+    AVAILABLE_MODELS
+        .iter()
+        .map(|(id, _)| *id)
+        .find(|id| *id == name || id.starts_with(&prefix))
+        .map(String::from)
+        // Newer models than the list knows about are passed through as-is
+        .or_else(|| name.starts_with("claude-").then(|| name.to_string()))
+        .ok_or_else(|| {
+            let known: Vec<&str> = AVAILABLE_MODELS.iter().map(|(id, _)| *id).collect();
+            format!("Unknown model: {} (available: {})", name, known.join(", "))
+        })
+}
+
 /// Model pricing (input_price_per_million, output_price_per_million)
 pub fn get_model_pricing(model: &str) -> (f64, f64) {
     match model {
@@ -81,28 +223,281 @@ pub fn get_model_pricing(model: &str) -> (f64, f64) {
     }
 }
 
-pub fn get_settings(app: &AppHandle) -> Settings {
-    let store = app.store(STORE_PATH).ok();
+// ==================== Store Access ====================
+
+/// Key/value access to settings.json: the Tauri store inside the app,
+/// `FileStore` for tools that run without it (CLI)
+pub trait SettingsStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<Value>;
+    fn set(&self, key: &str, value: Value);
+    fn save(&self) -> Result<(), String>;
+    /// Directory holding settings.json (the cache lives next to it)
+    fn data_dir(&self) -> Option<PathBuf>;
+    /// Whether the app may hold settings.json in memory and overwrite what this store
+    /// saves (usage then goes to the usage ledger instead)
+    fn is_detached(&self) -> bool {
+        false
+    }
+}
+
+impl<R: Runtime> SettingsStore for AppHandle<R> {
+    fn get(&self, key: &str) -> Option<Value> {
+        self.store(STORE_PATH).ok()?.get(key)
+    }
+
+    fn set(&self, key: &str, value: Value) {
+        if let Ok(store) = self.store(STORE_PATH) {
+            store.set(key, value);
+        }
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let store = self.store(STORE_PATH).map_err(|e| e.to_string())?;
+        store.save().map_err(|e| e.to_string())
+    }
 
+    fn data_dir(&self) -> Option<PathBuf> {
+        self.path().app_data_dir().ok()
+    }
+}
+
+/// settings.json read and written directly (same JSON object as tauri-plugin-store).
+// WHY: A running app keeps the store in memory and may overwrite entries written
+// here on its next save. `save` only writes the keys set through this store, merged
+// into the file as it is on disk, so the CLI and MCP server never revert changes made
+// in the app. Usage goes to the usage ledger, which the app reads and folds in; the
+// remaining entries (errors, translation memory) are history-like and harmless to
+// lose. The translation cache has its own file (cache.rs).
+pub struct FileStore {
+    path: PathBuf,
+    values: Mutex<Map<String, Value>>,
+    /// Keys set since the last save or reload
+    changed: Mutex<BTreeSet<String>>,
+}
+
+/// Cross-process lock: a file created exclusively, removed on drop
+struct LockFile(PathBuf);
+
+impl LockFile {
+    fn acquire(path: PathBuf) -> Result<Self, String> {
+        for _ in 0..250 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok(Self(path)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    let stale = std::fs::metadata(&path)
+                        .and_then(|m| m.modified())
+                        .ok()
+                        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                        .is_some_and(|age| age > STALE_LOCK);
+                    if stale {
+                        let _ = std::fs::remove_file(&path);
+                    } else {
+                        std::thread::sleep(Duration::from_millis(20));
+                    }
+                }
+                Err(e) => return Err(format!("Failed to lock {}: {}", path.display(), e)),
+            }
+        }
+        Err(format!("Timed out waiting for {}", path.display()))
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// JSON object in `path` (a missing file is an empty object)
+fn read_object(path: &Path) -> Result<Map<String, Value>, String> {
+    match std::fs::read(path) {
+        Ok(bytes) => {
+            serde_json::from_slice(&bytes).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Map::new()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+/// The app's data directory (what Tauri's `app_data_dir` resolves to), for the CLI
+/// and MCP server, which run without an AppHandle
+pub fn app_data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join(APP_IDENTIFIER))
+}
+
+impl FileStore {
+    /// The app's own settings.json
+    pub fn open_default() -> Result<Self, String> {
+        let data_dir =
+            app_data_dir().ok_or_else(|| "Could not locate the data directory".to_string())?;
+        Self::open(data_dir.join(STORE_PATH))
+    }
+
+    /// Load `path` (a missing file is an empty store)
+    pub fn open(path: PathBuf) -> Result<Self, String> {
+        let values = read_object(&path)?;
+        Ok(Self {
+            path,
+            values: Mutex::new(values),
+            changed: Mutex::new(BTreeSet::new()),
+        })
+    }
+
+    /// Re-read the file, dropping unsaved changes.
+    /// WHY: Long-running processes (MCP server) call this before each request, so
+    /// they read settings changed in the app in the meantime.
+    pub fn reload(&self) -> Result<(), String> {
+        let values = read_object(&self.path)?;
+        let mut changed = self.changed.lock().map_err(|e| e.to_string())?;
+        *self.values.lock().map_err(|e| e.to_string())? = values;
+        changed.clear();
+        Ok(())
+    }
+}
+
+impl SettingsStore for FileStore {
+    fn get(&self, key: &str) -> Option<Value> {
+        self.values.lock().ok()?.get(key).cloned()
+    }
+
+    fn set(&self, key: &str, value: Value) {
+        let (Ok(mut changed), Ok(mut values)) = (self.changed.lock(), self.values.lock()) else {
+            return;
+        };
+        values.insert(key.to_string(), value);
+        changed.insert(key.to_string());
+    }
+
+    /// Merge the changed keys into the file as it is now, under a lock file
+    fn save(&self) -> Result<(), String> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let _lock = LockFile::acquire(self.path.with_extension("json.lock"))?;
+        let mut changed = self.changed.lock().map_err(|e| e.to_string())?;
+        let mut values = self.values.lock().map_err(|e| e.to_string())?;
+
+        let mut merged = read_object(&self.path)?;
+        for key in changed.iter() {
+            match values.get(key) {
+                Some(value) => merged.insert(key.clone(), value.clone()),
+                None => merged.remove(key),
+            };
+        }
+        let bytes = serde_json::to_vec_pretty(&merged).map_err(|e| e.to_string())?;
+        // Write then rename, so a crash never leaves a truncated settings file
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, bytes).map_err(|e| e.to_string())?;
+        std::fs::rename(&tmp, &self.path).map_err(|e| e.to_string())?;
+
+        *values = merged;
+        changed.clear();
+        Ok(())
+    }
+
+    fn data_dir(&self) -> Option<PathBuf> {
+        self.path.parent().map(PathBuf::from)
+    }
+
+    fn is_detached(&self) -> bool {
+        true
+    }
+}
+
+/// A FileStore in a fresh temporary directory, removed on drop (tests only)
+#[cfg(test)]
+pub(crate) struct TempStore {
+    /// Holds settings.json; tests may put other files here too
+    pub dir: PathBuf,
+    store: FileStore,
+}
+
+#[cfg(test)]
+impl TempStore {
+    /// `name` keeps tests running in parallel apart
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("traylingo-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let store = FileStore::open(dir.join(STORE_PATH)).unwrap();
+        Self { dir, store }
+    }
+
+    /// settings.json, for opening more stores on the same file
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(STORE_PATH)
+    }
+}
+
+#[cfg(test)]
+impl SettingsStore for TempStore {
+    fn get(&self, key: &str) -> Option<Value> {
+        self.store.get(key)
+    }
+
+    fn set(&self, key: &str, value: Value) {
+        self.store.set(key, value)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        self.store.save()
+    }
+
+    fn data_dir(&self) -> Option<PathBuf> {
+        self.store.data_dir()
+    }
+
+    fn is_detached(&self) -> bool {
+        self.store.is_detached()
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempStore {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn get_settings(store: &dyn SettingsStore) -> Settings {
     store
-        .and_then(|s| s.get("settings"))
+        .get("settings")
         .and_then(|v| serde_json::from_value(v).ok())
         .unwrap_or_default()
 }
 
-pub fn save_settings(app: &AppHandle, settings: &Settings) -> Result<(), String> {
-    let store = app.store(STORE_PATH).map_err(|e| e.to_string())?;
+pub fn save_settings(store: &dyn SettingsStore, settings: &Settings) -> Result<(), String> {
+    for pattern in &settings.redaction_patterns {
+        Regex::new(pattern)
+            .map_err(|e| format!("Invalid redaction pattern \"{}\": {}", pattern, e))?;
+    }
+
     store.set(
         "settings",
         serde_json::to_value(settings).map_err(|e| e.to_string())?,
     );
-    store.save().map_err(|e| e.to_string())?;
-    Ok(())
+    store.save()
+}
+
+/// Random bearer token for the local API (256 bits, hex)
+pub fn generate_api_server_token() -> Result<String, String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// The local API token, generated and saved on first use
+pub fn ensure_api_server_token(store: &dyn SettingsStore) -> Result<String, String> {
+    let mut settings = get_settings(store);
+    if settings.api_server_token.is_empty() {
+        settings.api_server_token = generate_api_server_token()?;
+        save_settings(store, &settings)?;
+    }
+    Ok(settings.api_server_token)
 }
 
 /// Check if cache is enabled
-pub fn is_cache_enabled(app: &AppHandle) -> bool {
-    get_settings(app).cache_enabled
+pub fn is_cache_enabled(store: &dyn SettingsStore) -> bool {
+    get_settings(store).cache_enabled
 }
 
 // ==================== Error History ====================
@@ -123,9 +518,7 @@ pub struct ErrorHistoryEntry {
 }
 
 /// Save an error to history (keeps last MAX_ERROR_HISTORY entries)
-pub fn save_error(app: &AppHandle, entry: ErrorHistoryEntry) -> Result<(), String> {
-    let store = app.store(STORE_PATH).map_err(|e| e.to_string())?;
-
+pub fn save_error(store: &dyn SettingsStore, entry: ErrorHistoryEntry) -> Result<(), String> {
     let mut history: Vec<ErrorHistoryEntry> = store
         .get("error_history")
         .and_then(|v| serde_json::from_value(v).ok())
@@ -142,7 +535,7 @@ pub fn save_error(app: &AppHandle, entry: ErrorHistoryEntry) -> Result<(), Strin
         "error_history",
         serde_json::to_value(&history).map_err(|e| e.to_string())?,
     );
-    store.save().map_err(|e| e.to_string())?;
+    store.save()?;
     Ok(())
 }
 
@@ -166,236 +559,232 @@ pub fn clear_error_history(app: &AppHandle) -> Result<(), String> {
     Ok(())
 }
 
-// ==================== Window Position ====================
-
-/// Window position for persistence
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct WindowPosition {
-    pub x: i32,
-    pub y: i32,
-}
-
-/// Get saved window position for a window
-pub fn get_window_position(app: &AppHandle, window_label: &str) -> Option<WindowPosition> {
-    let key = format!("window_position_{}", window_label);
-    app.store(STORE_PATH)
-        .ok()
-        .and_then(|s| s.get(&key))
-        .and_then(|v| serde_json::from_value(v).ok())
-}
-
-/// Save window position
-pub fn save_window_position(
-    app: &AppHandle,
-    window_label: &str,
-    x: i32,
-    y: i32,
-) -> Result<(), String> {
-    let store = app.store(STORE_PATH).map_err(|e| e.to_string())?;
-    let key = format!("window_position_{}", window_label);
-    let position = WindowPosition { x, y };
-    store.set(
-        &key,
-        serde_json::to_value(&position).map_err(|e| e.to_string())?,
-    );
-    store.save().map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-// ==================== Translation Cache ====================
+// ==================== Usage Totals ====================
 
-/// Cached translation entry
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CachedTranslation {
-    /// SHA256 hash of source text (for lookup)
-    pub source_hash: String,
-    /// Original source text (truncated for storage, first 30 chars)
-    pub source_preview: String,
-    /// Translated text
-    pub translated_text: String,
-    /// Model used for translation
-    pub model: String,
-    /// Unix timestamp when cached
-    pub timestamp: i64,
+/// Token usage summed over all translations (app, popup, CLI, local API)
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct UsageStats {
+    pub translations: u64,
+    /// Served from the cache (no tokens used)
+    pub cached: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub estimated_cost: f64,
+    /// Translations that called the API, by model
+    #[serde(default)]
+    pub by_model: BTreeMap<String, ModelUsage>,
+    /// API calls besides translations (dictionary lookups, readings, verification,
+    /// refinement), included in the token and cost totals above
+    #[serde(default)]
+    pub other_requests: u64,
+    #[serde(default)]
+    pub other_cost: f64,
 }
 
-/// Cache statistics
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
-pub struct CacheStats {
-    /// Total entries in cache
-    pub entry_count: usize,
-    /// Cache hits (translations served from cache)
-    pub hits: u64,
-    /// Cache misses (new translations)
-    pub misses: u64,
+pub struct ModelUsage {
+    pub translations: u64,
+    pub estimated_cost: f64,
 }
 
-/// Generate SHA256 hash for cache key
-fn hash_text(text: &str) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(text.as_bytes());
-    format!("{:x}", hasher.finalize())
-}
+impl UsageStats {
+    /// Average cost of one API translation with `model` (the average over all
+    /// models if it has none yet)
+    pub fn average_cost(&self, model: &str) -> f64 {
+        match self.by_model.get(model) {
+            Some(usage) if usage.translations > 0 => {
+                usage.estimated_cost / usage.translations as f64
+            }
+            _ => {
+                let paid = self.translations.saturating_sub(self.cached);
+                if paid == 0 {
+                    0.0
+                } else {
+                    (self.estimated_cost - self.other_cost) / paid as f64
+                }
+            }
+        }
+    }
 
-/// Create a safe preview of text for cache storage.
-/// Truncates to SOURCE_PREVIEW_LENGTH and masks sensitive patterns.
-fn create_safe_preview(text: &str) -> String {
-    let preview: String = text.chars().take(SOURCE_PREVIEW_LENGTH).collect();
-    mask_sensitive_patterns(&preview)
+    /// Add another set of totals (or a single delta) to these
+    fn add(&mut self, other: &UsageStats) {
+        self.translations += other.translations;
+        self.cached += other.cached;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.estimated_cost += other.estimated_cost;
+        for (model, usage) in &other.by_model {
+            let total = self.by_model.entry(model.clone()).or_default();
+            total.translations += usage.translations;
+            total.estimated_cost += usage.estimated_cost;
+        }
+        self.other_requests += other.other_requests;
+        self.other_cost += other.other_cost;
+    }
 }
 
-/// Mask sensitive patterns in text (emails, URLs, long numbers)
-fn mask_sensitive_patterns(text: &str) -> String {
-    let text = EMAIL_REGEX.replace_all(text, "[EMAIL]");
-    let text = URL_REGEX.replace_all(&text, "[URL]");
-    let text = LONG_NUMBER_REGEX.replace_all(&text, "[***]");
-    text.to_string()
+fn stored_usage_stats(store: &dyn SettingsStore) -> UsageStats {
+    store
+        .get("usage_stats")
+        .and_then(|v| serde_json::from_value(v).ok())
+        .unwrap_or_default()
 }
 
-/// Get cached translation if exists (respects cache_enabled setting)
-pub fn get_cached_translation(app: &AppHandle, text: &str, model: &str) -> Option<String> {
-    // Check if cache is enabled
-    if !is_cache_enabled(app) {
-        return None;
+/// Sum of the usage ledger at `path` (unreadable lines are skipped)
+fn read_usage_ledger(path: &Path) -> UsageStats {
+    let mut total = UsageStats::default();
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return total;
+    };
+    for delta in content
+        .lines()
+        .filter_map(|line| serde_json::from_str::<UsageStats>(line).ok())
+    {
+        total.add(&delta);
     }
+    total
+}
 
-    let store = app.store(STORE_PATH).ok()?;
-    let hash = hash_text(text);
-
-    let cache: Vec<CachedTranslation> = store
-        .get("translation_cache")
-        .and_then(|v| serde_json::from_value(v).ok())
-        .unwrap_or_default();
-
-    // Get current timestamp for expiry check
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or(0);
-
-    // Find matching entry (same hash and model, not expired)
-    let result = cache
-        .iter()
-        .find(|entry| {
-            entry.source_hash == hash
-                && entry.model == model
-                && (now - entry.timestamp) < CACHE_TTL_SECS
-        })
-        .map(|entry| entry.translated_text.clone());
-
-    // Update stats
-    if let Ok(store) = app.store(STORE_PATH) {
-        let mut stats: CacheStats = store
-            .get("cache_stats")
-            .and_then(|v| serde_json::from_value(v).ok())
-            .unwrap_or_default();
-
-        if result.is_some() {
-            stats.hits += 1;
-        } else {
-            stats.misses += 1;
-        }
+/// Usage totals: settings.json plus CLI/MCP usage not yet folded in by the app
+pub fn get_usage_stats(store: &dyn SettingsStore) -> UsageStats {
+    let mut stats = stored_usage_stats(store);
+    if let Some(dir) = store.data_dir() {
+        stats.add(&read_usage_ledger(&dir.join(USAGE_LEDGER)));
+    }
+    stats
+}
 
-        if let Ok(value) = serde_json::to_value(&stats) {
-            store.set("cache_stats", value);
-            let _ = store.save();
-        }
+/// Add `delta` to the totals. Detached stores (CLI, MCP) append it to the ledger.
+// WHY: The app would overwrite usage_stats written by another process on its next
+// save; a line in a file the app only reads and folds in is never lost.
+fn add_usage(store: &dyn SettingsStore, delta: UsageStats) -> Result<(), String> {
+    if store.is_detached() {
+        let dir = store
+            .data_dir()
+            .ok_or_else(|| "Could not locate the data directory".to_string())?;
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        let _lock = LockFile::acquire(dir.join(format!("{}.lock", USAGE_LEDGER)))?;
+        let mut line = serde_json::to_vec(&delta).map_err(|e| e.to_string())?;
+        line.push(b'\n');
+        let mut ledger = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(USAGE_LEDGER))
+            .map_err(|e| e.to_string())?;
+        return ledger.write_all(&line).map_err(|e| e.to_string());
     }
 
-    result
+    let mut stats = stored_usage_stats(store);
+    stats.add(&delta);
+    store.set(
+        "usage_stats",
+        serde_json::to_value(&stats).map_err(|e| e.to_string())?,
+    );
+    store.save()
 }
 
-/// Save translation to cache (respects cache_enabled setting, LRU eviction when full)
-pub fn save_cached_translation(
-    app: &AppHandle,
-    text: &str,
-    translated_text: &str,
-    model: &str,
-) -> Result<(), String> {
-    // Check if cache is enabled
-    if !is_cache_enabled(app) {
+/// Move the usage ledger into usage_stats (the app, at startup)
+pub fn fold_usage_ledger(store: &dyn SettingsStore) -> Result<(), String> {
+    let Some(dir) = store.data_dir() else {
+        return Ok(());
+    };
+    let path = dir.join(USAGE_LEDGER);
+    if !path.exists() {
         return Ok(());
     }
+    let _lock = LockFile::acquire(dir.join(format!("{}.lock", USAGE_LEDGER)))?;
+    let delta = read_usage_ledger(&path);
 
-    let store = app.store(STORE_PATH).map_err(|e| e.to_string())?;
-    let hash = hash_text(text);
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map(|d| d.as_secs() as i64)
-        .unwrap_or(0);
-
-    let mut cache: Vec<CachedTranslation> = store
-        .get("translation_cache")
-        .and_then(|v| serde_json::from_value(v).ok())
-        .unwrap_or_default();
-
-    // Remove expired entries (30-day TTL)
-    cache.retain(|entry| (now - entry.timestamp) < CACHE_TTL_SECS);
+    let mut stats = stored_usage_stats(store);
+    stats.add(&delta);
+    store.set(
+        "usage_stats",
+        serde_json::to_value(&stats).map_err(|e| e.to_string())?,
+    );
+    store.save()?;
+    std::fs::remove_file(&path).map_err(|e| e.to_string())
+}
 
-    // Check if already exists (update timestamp if so)
-    if let Some(entry) = cache
-        .iter_mut()
-        .find(|e| e.source_hash == hash && e.model == model)
-    {
-        entry.timestamp = now;
-        entry.translated_text = translated_text.to_string();
-    } else {
-        // Add new entry with safe preview (truncated + masked for privacy)
-        let entry = CachedTranslation {
-            source_hash: hash,
-            source_preview: create_safe_preview(text),
-            translated_text: translated_text.to_string(),
-            model: model.to_string(),
-            timestamp: now,
-        };
-        cache.push(entry);
+/// Add one translation to the usage totals (`model` None: served from the cache)
+pub fn record_usage(
+    store: &dyn SettingsStore,
+    model: Option<&str>,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    estimated_cost: f64,
+    cached: bool,
+) -> Result<(), String> {
+    let mut delta = UsageStats {
+        translations: 1,
+        cached: u64::from(cached),
+        prompt_tokens: u64::from(prompt_tokens),
+        completion_tokens: u64::from(completion_tokens),
+        estimated_cost,
+        ..UsageStats::default()
+    };
+    if let Some(model) = model.filter(|_| !cached) {
+        delta.by_model.insert(
+            model.to_string(),
+            ModelUsage {
+                translations: 1,
+                estimated_cost,
+            },
+        );
     }
+    add_usage(store, delta)
+}
 
-    // LRU eviction: remove oldest entries if over limit
-    if cache.len() > MAX_TRANSLATION_CACHE {
-        cache.sort_by(|a, b| b.timestamp.cmp(&a.timestamp)); // newest first
-        cache.truncate(MAX_TRANSLATION_CACHE);
-    }
+/// Add an API call that isn't a translation to the token and cost totals
+pub fn record_request_usage(
+    store: &dyn SettingsStore,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    estimated_cost: f64,
+) -> Result<(), String> {
+    add_usage(
+        store,
+        UsageStats {
+            other_requests: 1,
+            other_cost: estimated_cost,
+            prompt_tokens: u64::from(prompt_tokens),
+            completion_tokens: u64::from(completion_tokens),
+            estimated_cost,
+            ..UsageStats::default()
+        },
+    )
+}
 
-    // Update entry count in stats
-    let mut stats: CacheStats = store
-        .get("cache_stats")
-        .and_then(|v| serde_json::from_value(v).ok())
-        .unwrap_or_default();
-    stats.entry_count = cache.len();
+// ==================== Window Position ====================
 
-    store.set(
-        "translation_cache",
-        serde_json::to_value(&cache).map_err(|e| e.to_string())?,
-    );
-    store.set(
-        "cache_stats",
-        serde_json::to_value(&stats).map_err(|e| e.to_string())?,
-    );
-    store.save().map_err(|e| e.to_string())?;
-    Ok(())
+/// Window position for persistence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowPosition {
+    pub x: i32,
+    pub y: i32,
 }
 
-/// Get cache statistics
-#[allow(dead_code)] // For future UI feature
-pub fn get_cache_stats(app: &AppHandle) -> CacheStats {
+/// Get saved window position for a window
+pub fn get_window_position(app: &AppHandle, window_label: &str) -> Option<WindowPosition> {
+    let key = format!("window_position_{}", window_label);
     app.store(STORE_PATH)
         .ok()
-        .and_then(|s| s.get("cache_stats"))
+        .and_then(|s| s.get(&key))
         .and_then(|v| serde_json::from_value(v).ok())
-        .unwrap_or_default()
 }
 
-/// Clear translation cache (called from UI)
-pub fn clear_translation_cache(app: &AppHandle) -> Result<(), String> {
+/// Save window position
+pub fn save_window_position(
+    app: &AppHandle,
+    window_label: &str,
+    x: i32,
+    y: i32,
+) -> Result<(), String> {
     let store = app.store(STORE_PATH).map_err(|e| e.to_string())?;
+    let key = format!("window_position_{}", window_label);
+    let position = WindowPosition { x, y };
     store.set(
-        "translation_cache",
-        serde_json::to_value::<Vec<CachedTranslation>>(vec![]).map_err(|e| e.to_string())?,
-    );
-    store.set(
-        "cache_stats",
-        serde_json::to_value(CacheStats::default()).map_err(|e| e.to_string())?,
+        &key,
+        serde_json::to_value(&position).map_err(|e| e.to_string())?,
     );
     store.save().map_err(|e| e.to_string())?;
     Ok(())
@@ -412,6 +801,20 @@ mod tests {
         assert_eq!(settings.model, "claude-haiku-4-5-20251001"); // Default model
         assert!(settings.send_telemetry); // Default: enabled (opt-out)
         assert!(settings.cache_enabled); // Default: enabled
+        assert_eq!(settings.cache_capacity, 5_000);
+        assert!(!settings.privacy_mode); // Default: disabled (opt-in)
+        assert!(!settings.api_server_enabled); // Default: disabled (opt-in)
+        assert!(settings.api_server_token.is_empty());
+        assert_eq!(settings.translation_memory, MemoryMode::Suggest);
+    }
+
+    #[test]
+    fn test_settings_without_privacy_fields() {
+        // Settings saved before privacy mode existed still load
+        let settings: Settings = serde_json::from_str(r#"{"model":"m"}"#).unwrap();
+        assert!(!settings.privacy_mode);
+        assert!(settings.redaction_patterns.is_empty());
+        assert_eq!(settings.output_validation, OutputValidation::Flag);
     }
 
     #[test]
@@ -425,40 +828,114 @@ mod tests {
         assert_eq!(output, 15.0);
     }
 
-    #[test]
-    fn test_mask_sensitive_patterns() {
-        // Email masking
-        assert_eq!(
-            mask_sensitive_patterns("Contact: user@example.com"),
-            "Contact: [EMAIL]"
-        );
-
-        // URL masking
-        assert_eq!(
-            mask_sensitive_patterns("See https://example.com/path"),
-            "See [URL]"
-        );
-
-        // Long number masking (4+ digits)
-        assert_eq!(mask_sensitive_patterns("Card: 1234567890"), "Card: [***]");
+    /// A store the way the app sees it (not detached)
+    struct AttachedStore<'a>(&'a FileStore);
 
-        // Short numbers are kept
-        assert_eq!(mask_sensitive_patterns("Code: 123"), "Code: 123");
+    impl SettingsStore for AttachedStore<'_> {
+        fn get(&self, key: &str) -> Option<Value> {
+            self.0.get(key)
+        }
+        fn set(&self, key: &str, value: Value) {
+            self.0.set(key, value)
+        }
+        fn save(&self) -> Result<(), String> {
+            self.0.save()
+        }
+        fn data_dir(&self) -> Option<PathBuf> {
+            self.0.data_dir()
+        }
+    }
 
-        // Combined
-        assert_eq!(
-            mask_sensitive_patterns("Email user@test.com or call 12345"),
-            "Email [EMAIL] or call [***]"
-        );
+    #[test]
+    fn test_file_store_merges_changed_keys() {
+        let temp = TempStore::new("merge");
+        let (dir, path) = (&temp.dir, temp.path());
+
+        // The CLI opens the store, then the app changes the settings
+        let cli = FileStore::open(path.clone()).unwrap();
+        let app = FileStore::open(path.clone()).unwrap();
+        let changed = Settings {
+            model: "m".to_string(),
+            ..Settings::default()
+        };
+        save_settings(&app, &changed).unwrap();
+
+        // Saving an unrelated key from the stale snapshot keeps the app's change
+        cli.set("error_history", serde_json::json!([]));
+        cli.save().unwrap();
+        let on_disk = FileStore::open(path).unwrap();
+        assert_eq!(get_settings(&on_disk).model, "m");
+        assert_eq!(on_disk.get("error_history"), Some(serde_json::json!([])));
+        // ...and the saving store now sees it too
+        assert_eq!(get_settings(&cli).model, "m");
+        assert!(!dir.join("settings.json.lock").exists());
     }
 
     #[test]
-    fn test_create_safe_preview() {
-        // Long text is truncated
-        let long_text = "a".repeat(100);
-        assert_eq!(create_safe_preview(&long_text).len(), SOURCE_PREVIEW_LENGTH);
+    fn test_file_store_round_trip() {
+        let temp = TempStore::new("store");
+        let (dir, path) = (&temp.dir, temp.path());
+
+        let store = FileStore::open(path.clone()).unwrap();
+        assert_eq!(get_settings(&store).model, default_model());
+        assert_eq!(store.data_dir().as_deref(), Some(dir.as_path()));
+        let changed = Settings {
+            model: "m".to_string(),
+            ..Settings::default()
+        };
+        save_settings(&store, &changed).unwrap();
+
+        // A fresh store reads what the first one saved
+        let store = FileStore::open(path).unwrap();
+        assert_eq!(get_settings(&store).model, "m");
+
+        record_usage(&store, Some("m"), 100, 20, 0.0002, false).unwrap();
+        record_usage(&store, Some("m"), 300, 60, 0.0004, false).unwrap();
+        record_usage(&store, None, 0, 0, 0.0, true).unwrap();
+        let usage = get_usage_stats(&store);
+        assert_eq!((usage.translations, usage.cached), (3, 1));
+        assert_eq!(usage.prompt_tokens, 400);
+        assert!((usage.average_cost("m") - 0.0003).abs() < 1e-12);
+        // No usage of its own yet: the overall average
+        assert!((usage.average_cost("other") - 0.0003).abs() < 1e-12);
+
+        // Other API calls add to the totals but not to the translation averages
+        record_request_usage(&store, 50, 10, 0.0001).unwrap();
+        let usage = get_usage_stats(&store);
+        assert_eq!((usage.translations, usage.other_requests), (3, 1));
+        assert_eq!(usage.prompt_tokens, 450);
+        assert!((usage.estimated_cost - 0.0007).abs() < 1e-12);
+        assert!((usage.average_cost("other") - 0.0003).abs() < 1e-12);
+
+        // CLI/MCP usage lives in the ledger until the app folds it in
+        assert!(dir.join(USAGE_LEDGER).exists());
+        assert_eq!(stored_usage_stats(&store).translations, 0);
+        let app_store = FileStore::open(dir.join("settings.json")).unwrap();
+        fold_usage_ledger(&AttachedStore(&app_store)).unwrap();
+        assert!(!dir.join(USAGE_LEDGER).exists());
+        let folded = stored_usage_stats(&app_store);
+        assert_eq!((folded.translations, folded.other_requests), (3, 1));
+        store.reload().unwrap();
+        assert_eq!(get_usage_stats(&store).prompt_tokens, 450);
+
+        let token = ensure_api_server_token(&store).unwrap();
+        assert_eq!(token.len(), 64);
+        assert_eq!(ensure_api_server_token(&store).unwrap(), token);
+        assert_ne!(generate_api_server_token().unwrap(), token);
+    }
 
-        // Short text with sensitive data is masked
-        assert!(create_safe_preview("user@example.com").contains("[EMAIL]"));
+    #[test]
+    fn test_resolve_model() {
+        assert_eq!(resolve_model("haiku").unwrap(), "claude-haiku-4-5-20251001");
+        assert_eq!(
+            resolve_model("3-5-haiku").unwrap(),
+            "claude-3-5-haiku-20241022"
+        );
+        assert_eq!(
+            resolve_model("claude-3-5-sonnet-20241022").unwrap(),
+            "claude-3-5-sonnet-20241022"
+        );
+        assert_eq!(resolve_model("claude-opus-9").unwrap(), "claude-opus-9");
+        assert!(resolve_model("gpt-4").is_err());
     }
 }
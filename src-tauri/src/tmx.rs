@@ -0,0 +1,396 @@
+//! TMX 1.4b import/export of the translation memory, for exchange with
+//! localization vendors and CAT tools.
+//!
+//! Only English–Japanese pairs are read; language tags map by their primary
+//! subtag (`en-US`, `EN-GB`, `ja_JP`, `jpn` all count). The cache can't be
+//! exported: its entries are keyed by hash and don't keep the source text.
+
+use std::io::{BufRead, Write};
+
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use crate::translation_memory::Segment;
+use crate::validation::Language;
+
+/// Tags written on export (locale-qualified, as most CAT tools expect)
+const EXPORT_TAGS: [(Language, &str); 2] =
+    [(Language::English, "en-US"), (Language::Japanese, "ja-JP")];
+
+/// Inline elements holding native codes rather than translatable text
+const CODE_ELEMENTS: [&[u8]; 5] = [b"bpt", b"ept", b"it", b"ph", b"ut"];
+
+fn export_tag(language: Language) -> &'static str {
+    EXPORT_TAGS
+        .iter()
+        .find(|(l, _)| *l == language)
+        .map(|(_, tag)| *tag)
+        .unwrap_or("en-US")
+}
+
+/// Our language for a TMX language tag (RFC 3066 / BCP 47, or ISO 639-2)
+pub fn language_for_tag(tag: &str) -> Option<Language> {
+    let primary = tag.split(['-', '_']).next().unwrap_or_default();
+    match primary.to_lowercase().as_str() {
+        "eng" => Some(Language::English),
+        "jpn" => Some(Language::Japanese),
+        other => Language::from_code(other),
+    }
+}
+
+// ==================== Export ====================
+
+/// Write segments as a TMX 1.4b document. Each unit's source language is
+/// detected from its text (the memory holds both directions).
+pub fn write_tmx<W: Write>(segments: &[Segment], out: &mut W) -> Result<usize, String> {
+    let mut written = 0;
+    let mut doc = String::new();
+    doc.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    doc.push_str("<tmx version=\"1.4\">\n");
+    doc.push_str(&format!(
+        "  <header creationtool=\"TrayLingo\" creationtoolversion=\"{}\" datatype=\"plaintext\" \
+         segtype=\"paragraph\" adminlang=\"en-US\" srclang=\"*all*\" o-tmf=\"TrayLingo\"/>\n",
+        env!("CARGO_PKG_VERSION")
+    ));
+    doc.push_str("  <body>\n");
+    for segment in segments {
+        let Some(source) = Language::detect(&segment.source)
+            .or_else(|| Language::detect(&segment.target).map(Language::opposite))
+        else {
+            continue;
+        };
+        doc.push_str(&format!(
+            "    <tu srclang=\"{}\" changedate=\"{}\">\n",
+            export_tag(source),
+            format_date(segment.timestamp)
+        ));
+        for (language, text) in [
+            (source, &segment.source),
+            (source.opposite(), &segment.target),
+        ] {
+            doc.push_str(&format!(
+                "      <tuv xml:lang=\"{}\"><seg>{}</seg></tuv>\n",
+                export_tag(language),
+                escape(text.as_str())
+            ));
+        }
+        doc.push_str("    </tu>\n");
+        written += 1;
+    }
+    doc.push_str("  </body>\n</tmx>\n");
+    out.write_all(doc.as_bytes())
+        .map_err(|e| format!("Failed to write TMX: {}", e))?;
+    Ok(written)
+}
+
+// ==================== Import ====================
+
+/// Translation unit being read
+#[derive(Default)]
+struct Unit {
+    srclang: Option<Language>,
+    timestamp: Option<i64>,
+    variants: Vec<(Language, String)>,
+}
+
+impl Unit {
+    /// Source is the unit's `srclang`, else the header's, else its first variant
+    fn into_segment(self, default_source: Option<Language>, now: i64) -> Option<Segment> {
+        let source = self
+            .srclang
+            .or(default_source)
+            .filter(|language| self.variants.iter().any(|(l, _)| l == language))
+            .or_else(|| self.variants.first().map(|(l, _)| *l))?;
+        let text_in = |language: Language| {
+            self.variants
+                .iter()
+                .find(|(l, text)| *l == language && !text.trim().is_empty())
+                .map(|(_, text)| text.clone())
+        };
+        Some(Segment {
+            source: text_in(source)?,
+            target: text_in(source.opposite())?,
+            timestamp: self.timestamp.unwrap_or(now),
+            imported: true,
+        })
+    }
+}
+
+fn attribute(element: &BytesStart, names: &[&[u8]]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| names.contains(&a.key.as_ref()))
+        .map(|a| String::from_utf8_lossy(&a.value).into_owned())
+}
+
+/// Read the English–Japanese units of a TMX document as segments. Units
+/// without both languages are left out; `changedate` (else `creationdate`)
+/// becomes the segment's timestamp.
+pub fn parse_tmx<R: BufRead>(source: R) -> Result<Vec<Segment>, String> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let mut reader = Reader::from_reader(source);
+    let mut buf = Vec::new();
+    let mut segments = Vec::new();
+
+    let mut header_srclang = None;
+    let mut unit: Option<Unit> = None;
+    let mut language: Option<Language> = None;
+    let mut seg: Option<String> = None;
+    // Depth inside inline code elements, whose text is skipped
+    let mut code_depth: u32 = 0;
+
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| format!("Invalid TMX at byte {}: {}", reader.buffer_position(), e))?;
+        match event {
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"header" => {
+                header_srclang =
+                    attribute(&e, &[b"srclang"]).and_then(|tag| language_for_tag(&tag));
+            }
+            Event::Start(e) => match e.name().as_ref() {
+                b"tu" => {
+                    let date = attribute(&e, &[b"changedate"])
+                        .or_else(|| attribute(&e, &[b"creationdate"]));
+                    unit = Some(Unit {
+                        srclang: attribute(&e, &[b"srclang"])
+                            .and_then(|tag| language_for_tag(&tag)),
+                        timestamp: date.and_then(|date| parse_date(&date)),
+                        variants: Vec::new(),
+                    });
+                }
+                // TMX 1.1 used `lang`
+                b"tuv" => {
+                    language = attribute(&e, &[b"xml:lang", b"lang"])
+                        .and_then(|tag| language_for_tag(&tag));
+                }
+                b"seg" => {
+                    seg = Some(String::new());
+                    code_depth = 0;
+                }
+                name if seg.is_some() && CODE_ELEMENTS.contains(&name) => code_depth += 1,
+                _ => {}
+            },
+            Event::Text(e) if code_depth == 0 => {
+                if let Some(seg) = seg.as_mut() {
+                    seg.push_str(&e.decode().map_err(|e| e.to_string())?);
+                }
+            }
+            Event::CData(e) if code_depth == 0 => {
+                if let Some(seg) = seg.as_mut() {
+                    seg.push_str(&String::from_utf8_lossy(&e));
+                }
+            }
+            Event::GeneralRef(e) if code_depth == 0 => {
+                if let Some(seg) = seg.as_mut() {
+                    if let Ok(Some(c)) = e.resolve_char_ref() {
+                        seg.push(c);
+                    } else {
+                        let name = e.decode().map_err(|e| e.to_string())?;
+                        seg.push_str(match name.as_ref() {
+                            "amp" => "&",
+                            "lt" => "<",
+                            "gt" => ">",
+                            "quot" => "\"",
+                            "apos" => "'",
+                            _ => "",
+                        });
+                    }
+                }
+            }
+            Event::End(e) => match e.name().as_ref() {
+                b"seg" => {
+                    if let (Some(unit), Some(language), Some(text)) =
+                        (unit.as_mut(), language, seg.take())
+                    {
+                        unit.variants.push((language, text));
+                    }
+                }
+                b"tuv" => language = None,
+                b"tu" => {
+                    if let Some(segment) = unit
+                        .take()
+                        .and_then(|u| u.into_segment(header_srclang, now))
+                    {
+                        segments.push(segment);
+                    }
+                }
+                name if seg.is_some() && CODE_ELEMENTS.contains(&name) => {
+                    code_depth = code_depth.saturating_sub(1)
+                }
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(segments)
+}
+
+// ==================== Dates ====================
+
+/// Unix seconds as a TMX date (`YYYYMMDDThhmmssZ`, UTC)
+fn format_date(timestamp: i64) -> String {
+    let (days, seconds) = (timestamp.div_euclid(86_400), timestamp.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+fn parse_date(date: &str) -> Option<i64> {
+    let date = date.trim().strip_suffix('Z')?;
+    if date.len() != 15 || !date.is_ascii() || date.as_bytes()[8] != b'T' {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| date[range].parse::<i64>().ok();
+    let (year, month, day) = (number(0..4)?, number(4..6)?, number(6..8)?);
+    let (hour, minute, second) = (number(9..11)?, number(11..13)?, number(13..15)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+// WHY: Howard Hinnant's civil-date algorithms; a date crate for two
+// conversions isn't worth the dependency.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(source: &str, target: &str, timestamp: i64) -> Segment {
+        Segment {
+            source: source.to_string(),
+            target: target.to_string(),
+            timestamp,
+            imported: false,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let segments = vec![
+            segment(
+                "Use <b> & \"quotes\" in the\ntemplate.",
+                "テンプレートでは <b> と & と \"引用符\" を使います。",
+                1_760_000_000,
+            ),
+            segment(
+                "変更を適用するにはアプリを再起動してください。",
+                "Please restart the app to apply changes.",
+                951_782_400, // 2000-02-29
+            ),
+        ];
+        let mut out = Vec::new();
+        assert_eq!(write_tmx(&segments, &mut out).unwrap(), 2);
+        let xml = String::from_utf8(out).unwrap();
+        assert!(xml.contains("<tu srclang=\"ja-JP\" changedate=\"20000229T000000Z\">"));
+        assert!(xml.contains("&lt;b&gt; &amp; &quot;quotes&quot;"));
+
+        let parsed = parse_tmx(xml.as_bytes()).unwrap();
+        assert_eq!(parsed.len(), 2);
+        for (original, parsed) in segments.iter().zip(&parsed) {
+            assert_eq!(parsed.source, original.source);
+            assert_eq!(parsed.target, original.target);
+            assert_eq!(parsed.timestamp, original.timestamp);
+        }
+    }
+
+    #[test]
+    fn test_language_tags() {
+        assert_eq!(language_for_tag("en-US"), Some(Language::English));
+        assert_eq!(language_for_tag("EN-GB"), Some(Language::English));
+        assert_eq!(language_for_tag("ja_JP"), Some(Language::Japanese));
+        assert_eq!(language_for_tag("jpn"), Some(Language::Japanese));
+        assert_eq!(language_for_tag("de-DE"), None);
+    }
+
+    #[test]
+    fn test_parse_vendor_tmx() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tmx version="1.4">
+  <header creationtool="Vendor" segtype="sentence" adminlang="EN-US" srclang="EN-GB" datatype="xml" o-tmf="x"/>
+  <body>
+    <tu creationdate="20240102T030405Z">
+      <tuv xml:lang="JA-JP"><seg>保存する &#x2192; <bpt i="1">&lt;b&gt;</bpt>今すぐ<ept i="1">&lt;/b&gt;</ept></seg></tuv>
+      <tuv xml:lang="EN-GB"><seg>Save <bpt i="1">&lt;b&gt;</bpt>now<ept i="1">&lt;/b&gt;</ept></seg></tuv>
+    </tu>
+    <tu>
+      <tuv xml:lang="en-US"><seg>Only English</seg></tuv>
+      <tuv xml:lang="de-DE"><seg>Nur Deutsch</seg></tuv>
+    </tu>
+    <tu srclang="ja-JP">
+      <tuv lang="en"><seg><![CDATA[Hello <there>]]></seg></tuv>
+      <tuv lang="ja"><seg>こんにちは</seg></tuv>
+    </tu>
+  </body>
+</tmx>"#;
+        let segments = parse_tmx(xml.as_bytes()).unwrap();
+        assert_eq!(segments.len(), 2);
+        // Header srclang decides the direction, whatever the order of variants
+        assert_eq!(segments[0].source, "Save now");
+        assert_eq!(segments[0].target, "保存する → 今すぐ");
+        assert_eq!(
+            segments[0].timestamp,
+            parse_date("20240102T030405Z").unwrap()
+        );
+        // The unit's own srclang wins
+        assert_eq!(segments[1].source, "こんにちは");
+        assert_eq!(segments[1].target, "Hello <there>");
+
+        assert!(parse_tmx("<tmx><body><tu>".as_bytes()).is_ok());
+        assert!(parse_tmx("<tmx><body></tu>".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_dates() {
+        assert_eq!(format_date(0), "19700101T000000Z");
+        assert_eq!(parse_date("19700101T000000Z"), Some(0));
+        assert_eq!(
+            parse_date("20261018T123456Z").map(format_date).as_deref(),
+            Some("20261018T123456Z")
+        );
+        assert_eq!(parse_date("2026-10-18"), None);
+        assert_eq!(parse_date("20261318T000000Z"), None);
+    }
+}
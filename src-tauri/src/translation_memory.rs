@@ -0,0 +1,540 @@
+//! Translation memory: source/target segments of earlier translations, found again
+//! by fuzzy match, so an edited paragraph can reuse (or be guided by) its old translation.
+//! Exact repeats are the cache's job; this is for near-misses.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cache;
+use crate::secrets;
+use crate::settings::{self, SettingsStore};
+
+/// Lowest similarity offered as a match (75%, the usual fuzzy-match floor)
+pub const MIN_SIMILARITY: f64 = 0.75;
+
+const MEMORY_KEY: &str = "translation_memory";
+/// Segments remembered from translations
+const MAX_SEGMENTS: usize = 500;
+/// Segments imported from TMX files, kept apart so a large import isn't cut to
+/// MAX_SEGMENTS (and doesn't push out what was translated here)
+const MAX_IMPORTED_SEGMENTS: usize = 10_000;
+/// Shorter segments match too much by accident
+const MIN_SEGMENT_CHARS: usize = 10;
+/// Keeps the edit-distance check cheap
+const MAX_SEGMENT_CHARS: usize = 2000;
+/// Best segments by bigram overlap that get the exact edit-distance check
+const CANDIDATES: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub source: String,
+    pub target: String,
+    /// Unix timestamp of the last translation that produced it
+    pub timestamp: i64,
+    /// Came from an import rather than a translation here
+    #[serde(default)]
+    pub imported: bool,
+}
+
+/// What an imported segment does when its source is already remembered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergePolicy {
+    /// Leave the remembered translation
+    KeepExisting,
+    /// Take the imported one
+    Overwrite,
+    /// Whichever changed last
+    #[default]
+    KeepNewer,
+}
+
+/// Outcome of an import
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct ImportSummary {
+    pub added: usize,
+    pub updated: usize,
+    /// Duplicates the policy kept, and segments the memory doesn't take
+    pub skipped: usize,
+    /// Oldest imported segments dropped to stay within MAX_IMPORTED_SEGMENTS
+    pub dropped: usize,
+}
+
+/// Remembered segment similar to the text being translated
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MemoryMatch {
+    pub source: String,
+    pub translation: String,
+    /// Percent (75–100)
+    pub similarity: u8,
+}
+
+/// Source/target pairs worth remembering: paragraph by paragraph when both sides
+/// have the same number of paragraphs, otherwise the whole text
+pub fn align(source: &str, target: &str) -> Vec<(String, String)> {
+    let paragraphs = |text: &str| -> Vec<String> {
+        text.split("\n\n")
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(String::from)
+            .collect()
+    };
+    let sources = paragraphs(source);
+    let targets = paragraphs(target);
+    let pairs = if sources.len() == targets.len() && sources.len() > 1 {
+        sources.into_iter().zip(targets).collect()
+    } else {
+        vec![(source.trim().to_string(), target.trim().to_string())]
+    };
+    pairs
+        .into_iter()
+        .filter(|(source, target)| {
+            let length = source.chars().count();
+            (MIN_SEGMENT_CHARS..=MAX_SEGMENT_CHARS).contains(&length) && !target.is_empty()
+        })
+        .collect()
+}
+
+/// Closest segment at or above MIN_SIMILARITY
+pub fn best_match(segments: &[Segment], text: &str) -> Option<MemoryMatch> {
+    let text = normalize(text);
+    if text.len() < MIN_SEGMENT_CHARS || text.len() > MAX_SEGMENT_CHARS {
+        return None;
+    }
+    let text_bigrams = bigrams(&text);
+
+    // Edit distance is at least the length difference, so the ratio caps similarity
+    let mut candidates: Vec<(f64, Vec<char>, &Segment)> = segments
+        .iter()
+        .map(|segment| (normalize(&segment.source), segment))
+        .filter(|(source, _)| {
+            let (short, long) = (source.len().min(text.len()), source.len().max(text.len()));
+            short as f64 >= long as f64 * MIN_SIMILARITY
+        })
+        .map(|(source, segment)| (dice(&text_bigrams, &bigrams(&source)), source, segment))
+        .collect();
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    candidates
+        .into_iter()
+        .take(CANDIDATES)
+        .map(|(_, source, segment)| (similarity(&text, &source), segment))
+        .filter(|(similarity, _)| *similarity >= MIN_SIMILARITY)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(similarity, segment)| MemoryMatch {
+            source: segment.source.clone(),
+            translation: segment.target.clone(),
+            similarity: (similarity * 100.0).floor() as u8,
+        })
+}
+
+/// Case- and whitespace-insensitive characters
+fn normalize(text: &str) -> Vec<char> {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .chars()
+        .collect()
+}
+
+fn bigrams(chars: &[char]) -> HashSet<(char, char)> {
+    chars.windows(2).map(|w| (w[0], w[1])).collect()
+}
+
+/// Dice coefficient of two bigram sets (cheap pre-ranking)
+fn dice(a: &HashSet<(char, char)>, b: &HashSet<(char, char)>) -> f64 {
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    2.0 * a.intersection(b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+/// 1 - edit distance / longer length
+fn similarity(a: &[char], b: &[char]) -> f64 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(a, b) as f64 / longest as f64
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+// ==================== Store ====================
+
+// WHY: Segments are whole sentences of what was translated, so settings.json only
+// holds them sealed with the cache key. Errs while that key can't be read, so a
+// locked Keychain never saves over the memory.
+fn load(store: &dyn SettingsStore) -> Result<Vec<Segment>, String> {
+    match store.get(MEMORY_KEY) {
+        Some(Value::String(sealed)) => Ok(cache::unseal(store, MEMORY_KEY, &sealed)?
+            .unwrap_or_else(|| {
+                log::warn!("Translation memory can't be decrypted; starting over");
+                Vec::new()
+            })),
+        // Written in plain text by older versions; sealed from now on
+        Some(Value::Array(legacy)) if !legacy.is_empty() => {
+            let segments: Vec<Segment> =
+                serde_json::from_value(Value::Array(legacy)).unwrap_or_default();
+            save(store, &segments)?;
+            Ok(segments)
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Every remembered segment, oldest first
+pub fn segments(store: &dyn SettingsStore) -> Result<Vec<Segment>, String> {
+    let mut segments = load(store)?;
+    segments.sort_by_key(|segment| segment.timestamp);
+    Ok(segments)
+}
+
+/// Keep the newest segments of each kind within its limit; returns how many
+/// imported segments were dropped
+fn trim(segments: &mut Vec<Segment>) -> usize {
+    segments.sort_by_key(|segment| std::cmp::Reverse(segment.timestamp));
+    let (mut recorded, mut imported) = (0, 0);
+    segments.retain(|segment| {
+        let (count, limit) = if segment.imported {
+            (&mut imported, MAX_IMPORTED_SEGMENTS)
+        } else {
+            (&mut recorded, MAX_SEGMENTS)
+        };
+        *count += 1;
+        *count <= limit
+    });
+    imported.saturating_sub(MAX_IMPORTED_SEGMENTS)
+}
+
+fn save(store: &dyn SettingsStore, segments: &[Segment]) -> Result<(), String> {
+    store.set(
+        MEMORY_KEY,
+        Value::String(cache::seal(store, MEMORY_KEY, &segments)?),
+    );
+    store.save()
+}
+
+/// Closest remembered segment (None when the cache is disabled)
+pub fn lookup(store: &dyn SettingsStore, text: &str) -> Option<MemoryMatch> {
+    if !settings::is_cache_enabled(store) {
+        return None;
+    }
+    best_match(&load(store).ok()?, text)
+}
+
+/// Remember the segments of a finished translation. Same rules as the cache:
+/// nothing while it is disabled, never credential-like text.
+pub fn record(store: &dyn SettingsStore, source: &str, target: &str) -> Result<(), String> {
+    if !settings::is_cache_enabled(store) || secrets::contains_secrets(source) {
+        return Ok(());
+    }
+    let pairs = align(source, target);
+    if pairs.is_empty() {
+        return Ok(());
+    }
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let mut segments = load(store)?;
+    for (source, target) in pairs {
+        segments.retain(|segment| segment.source != source);
+        segments.push(Segment {
+            source,
+            target,
+            timestamp: now,
+            imported: false,
+        });
+    }
+    trim(&mut segments);
+    save(store, &segments)
+}
+
+/// Add segments from elsewhere (a TMX file), resolving duplicate sources with
+/// `policy`. Credential-like and out-of-range segments are skipped, as in `record`.
+pub fn import(
+    store: &dyn SettingsStore,
+    imported: Vec<Segment>,
+    policy: MergePolicy,
+) -> Result<ImportSummary, String> {
+    let mut summary = ImportSummary::default();
+    let mut segments = load(store)?;
+    for mut segment in imported {
+        segment.imported = true;
+        let length = segment.source.chars().count();
+        if !(MIN_SEGMENT_CHARS..=MAX_SEGMENT_CHARS).contains(&length)
+            || segment.target.trim().is_empty()
+            || secrets::contains_secrets(&segment.source)
+        {
+            summary.skipped += 1;
+            continue;
+        }
+        match segments.iter_mut().find(|s| s.source == segment.source) {
+            None => {
+                segments.push(segment);
+                summary.added += 1;
+            }
+            Some(existing) => {
+                let replace = match policy {
+                    MergePolicy::KeepExisting => false,
+                    MergePolicy::Overwrite => true,
+                    MergePolicy::KeepNewer => segment.timestamp > existing.timestamp,
+                };
+                if replace {
+                    *existing = segment;
+                    summary.updated += 1;
+                } else {
+                    summary.skipped += 1;
+                }
+            }
+        }
+    }
+    summary.dropped = trim(&mut segments);
+    save(store, &segments)?;
+    Ok(summary)
+}
+
+/// Forget all segments (cleared together with the cache)
+pub fn clear(store: &dyn SettingsStore) -> Result<(), String> {
+    store.set(MEMORY_KEY, Value::Array(Vec::new()));
+    store.save()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::TempStore;
+
+    fn segment(source: &str, target: &str) -> Segment {
+        Segment {
+            source: source.to_string(),
+            target: target.to_string(),
+            timestamp: 0,
+            imported: false,
+        }
+    }
+
+    #[test]
+    fn test_best_match() {
+        let segments = vec![
+            segment(
+                "Please restart the app to apply changes.",
+                "変更を適用するにはアプリを再起動してください。",
+            ),
+            segment("Settings were saved.", "設定を保存しました。"),
+        ];
+
+        // One typo fixed
+        let found = best_match(&segments, "Please restart the app to aply changes.").unwrap();
+        assert_eq!(
+            found.translation,
+            "変更を適用するにはアプリを再起動してください。"
+        );
+        assert_eq!(found.similarity, 97);
+
+        // Case and spacing don't count
+        let found = best_match(&segments, "please  restart the app to apply changes.").unwrap();
+        assert_eq!(found.similarity, 100);
+
+        assert!(best_match(&segments, "Please close the window first.").is_none());
+        assert!(best_match(&segments, "Settings").is_none());
+    }
+
+    #[test]
+    fn test_best_match_japanese() {
+        let segments = vec![segment(
+            "変更を適用するにはアプリを再起動してください。",
+            "Please restart the app to apply changes.",
+        )];
+        let found = best_match(&segments, "変更を適用するにはアプリを再起動して下さい。").unwrap();
+        assert!(found.similarity >= 85);
+    }
+
+    #[test]
+    fn test_align() {
+        let pairs = align(
+            "First paragraph here.\n\nSecond paragraph here.",
+            "最初の段落です。\n\n二番目の段落です。",
+        );
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[1].1, "二番目の段落です。");
+
+        // Paragraph counts differ: one segment
+        let pairs = align("One paragraph only here.", "一つ目。\n\n二つ目。");
+        assert_eq!(pairs.len(), 1);
+
+        assert!(align("Hi", "やあ").is_empty());
+    }
+
+    #[test]
+    fn test_levenshtein() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(levenshtein(&chars("kitten"), &chars("sitting")), 3);
+        assert_eq!(levenshtein(&chars(""), &chars("abc")), 3);
+        assert_eq!(levenshtein(&chars("same"), &chars("same")), 0);
+    }
+
+    #[test]
+    fn test_record_and_lookup() {
+        let store = TempStore::new("memory");
+
+        record(
+            &store,
+            "Settings were saved to disk.",
+            "設定をディスクに保存しました。",
+        )
+        .unwrap();
+        record(
+            &store,
+            "Settings were saved to disk.",
+            "設定をディスクへ保存しました。",
+        )
+        .unwrap();
+        record(
+            &store,
+            "token: sk-ant-REDACTED",
+            "トークン",
+        )
+        .unwrap();
+        assert_eq!(load(&store).unwrap().len(), 1);
+
+        let found = lookup(&store, "Settings were saved to disc.").unwrap();
+        assert_eq!(found.translation, "設定をディスクへ保存しました。");
+
+        clear(&store).unwrap();
+        assert!(lookup(&store, "Settings were saved to disc.").is_none());
+    }
+
+    #[test]
+    fn test_memory_is_sealed() {
+        let store = TempStore::new("memory-sealed");
+        let path = store.path();
+
+        record(
+            &store,
+            "Settings were saved to disk.",
+            "設定をディスクに保存しました。",
+        )
+        .unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(!written.contains("Settings were saved"));
+        assert!(!written.contains("設定"));
+
+        // Plain text from older versions is sealed on first read
+        store.set(
+            MEMORY_KEY,
+            serde_json::to_value(vec![segment("Old plain segment.", "古い平文。")]).unwrap(),
+        );
+        store.save().unwrap();
+        assert_eq!(segments(&store).unwrap()[0].source, "Old plain segment.");
+        assert!(!std::fs::read_to_string(&path)
+            .unwrap()
+            .contains("Old plain segment"));
+    }
+
+    #[test]
+    fn test_import_merge_policies() {
+        let store = TempStore::new("memory-import");
+        let at = |source: &str, target: &str, timestamp: i64| Segment {
+            timestamp,
+            ..segment(source, target)
+        };
+        import(
+            &store,
+            vec![at("Settings were saved to disk.", "保存しました。", 100)],
+            MergePolicy::Overwrite,
+        )
+        .unwrap();
+
+        let older = vec![at("Settings were saved to disk.", "古い訳。", 50)];
+        let summary = import(&store, older.clone(), MergePolicy::KeepNewer).unwrap();
+        assert_eq!((summary.added, summary.updated, summary.skipped), (0, 0, 1));
+        let summary = import(&store, older.clone(), MergePolicy::KeepExisting).unwrap();
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(segments(&store).unwrap()[0].target, "保存しました。");
+
+        let summary = import(&store, older, MergePolicy::Overwrite).unwrap();
+        assert_eq!(summary.updated, 1);
+        assert_eq!(segments(&store).unwrap()[0].target, "古い訳。");
+
+        let newer = vec![
+            at(
+                "Settings were saved to disk.",
+                "ディスクに保存しました。",
+                200,
+            ),
+            at("Short", "短い", 200),
+            at(
+                "Please restart the app to apply changes.",
+                "再起動してください。",
+                200,
+            ),
+        ];
+        let summary = import(&store, newer, MergePolicy::KeepNewer).unwrap();
+        assert_eq!(
+            summary,
+            ImportSummary {
+                added: 1,
+                updated: 1,
+                skipped: 1,
+                dropped: 0
+            }
+        );
+        assert_eq!(segments(&store).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_import_beyond_recorded_limit() {
+        let store = TempStore::new("memory-large");
+        record(
+            &store,
+            "Settings were saved to disk.",
+            "設定をディスクに保存しました。",
+        )
+        .unwrap();
+
+        let units = |count: usize, timestamp: i64| -> Vec<Segment> {
+            (0..count)
+                .map(|i| Segment {
+                    timestamp,
+                    ..segment(&format!("Imported sentence number {}", i), "訳")
+                })
+                .collect()
+        };
+        let summary = import(&store, units(MAX_SEGMENTS * 2, 100), MergePolicy::KeepNewer).unwrap();
+        assert_eq!((summary.added, summary.dropped), (MAX_SEGMENTS * 2, 0));
+        // Imported units don't push out what was translated here
+        assert_eq!(load(&store).unwrap().len(), MAX_SEGMENTS * 2 + 1);
+        assert!(lookup(&store, "Settings were saved to disc.").is_some());
+
+        // Past the import limit the oldest go, and the summary says so
+        let more: Vec<Segment> = units(MAX_IMPORTED_SEGMENTS, 200)
+            .into_iter()
+            .map(|unit| Segment {
+                source: format!("{} (revised)", unit.source),
+                ..unit
+            })
+            .collect();
+        let summary = import(&store, more, MergePolicy::KeepNewer).unwrap();
+        assert_eq!(summary.added, MAX_IMPORTED_SEGMENTS);
+        assert_eq!(summary.dropped, MAX_SEGMENTS * 2);
+        assert_eq!(load(&store).unwrap().len(), MAX_IMPORTED_SEGMENTS + 1);
+    }
+}
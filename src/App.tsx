@@ -9,12 +9,22 @@ import {
 } from "@tauri-apps/plugin-notification";
 import { relaunch } from "@tauri-apps/plugin-process";
 import { check } from "@tauri-apps/plugin-updater";
-import { Check as CheckIcon, Copy, Settings as SettingsIcon } from "lucide-solid";
+import {
+  Check as CheckIcon,
+  Copy,
+  EyeOff,
+  History as HistoryIcon,
+  Settings as SettingsIcon,
+} from "lucide-solid";
 import { createMemo, createSignal, onCleanup, onMount, Show } from "solid-js";
 import { ErrorDisplay } from "./components/ErrorDisplay";
+import { History } from "./components/History";
 import { Settings } from "./components/Settings";
 import type { TranslateError } from "./types/error";
 import { parseError } from "./types/error";
+import type { MemoryMatchPayload } from "./types/memory";
+import type { FlaggedPayload, OutputIssue } from "./types/validation";
+import { getFlagMessage } from "./types/validation";
 import { formatText } from "./utils/formatText";
 import { Logger } from "./utils/logger";
 
@@ -59,7 +69,11 @@ function App() {
   const [sessionCost, setSessionCost] = createSignal(0);
   const [currentSessionId, setCurrentSessionId] = createSignal("");
   const [error, setError] = createSignal<TranslateError | null>(null);
-  const [view, setView] = createSignal<"main" | "settings">("main");
+  const [flagged, setFlagged] = createSignal<OutputIssue[]>([]);
+  const [memoryMatch, setMemoryMatch] = createSignal<MemoryMatchPayload | null>(null);
+  const [view, setView] = createSignal<"main" | "settings" | "history">("main");
+  // History recording paused (toggled here or from the tray menu)
+  const [incognito, setIncognito] = createSignal(false);
   const [currentModel, setCurrentModel] = createSignal("");
 
   // Debounce timer for auto-translate
@@ -79,18 +93,20 @@ function App() {
   };
 
   // Start translation
-  const startTranslation = async (text: string) => {
+  const startTranslation = async (text: string, allowSensitive = false) => {
     const sessionId = generateSessionId();
     setCurrentSessionId(sessionId);
     setTranslated("");
     setIsTranslating(true);
     setUsage(null);
     setError(null);
+    setFlagged([]);
+    setMemoryMatch(null);
 
     Logger.info("ipc", "translate start", { textLength: text.length }, sessionId);
 
     try {
-      await invoke("translate", { text, sessionId });
+      await invoke("translate", { text, sessionId, allowSensitive });
     } catch (err) {
       Logger.error("ipc", "translate failed", { error: String(err) }, sessionId);
       setError(parseError(err));
@@ -106,6 +122,14 @@ function App() {
     }
   };
 
+  // User confirmed sending text that looks like it contains credentials
+  const handleSendAnyway = () => {
+    const text = original();
+    if (text) {
+      startTranslation(text, true);
+    }
+  };
+
   // Trigger translation with debounce control
   const triggerTranslation = (text: string, immediate: boolean) => {
     // Clear any pending debounce
@@ -144,6 +168,11 @@ function App() {
 
     Logger.info("lifecycle", "App mounted");
     await loadSettings();
+    setIncognito(await invoke<boolean>("get_incognito"));
+
+    globalUnlistenFns.push(
+      await listen<boolean>("incognito-changed", (event) => setIncognito(event.payload)),
+    );
 
     // Listen for shortcut trigger
     globalUnlistenFns.push(
@@ -157,6 +186,15 @@ function App() {
       }),
     );
 
+    // traylingo://translate?mode=window links (confirmed in Rust before this fires)
+    globalUnlistenFns.push(
+      await listen<string>("deep-link-text", (event) => {
+        Logger.info("ui", "deep link translation");
+        setOriginal(event.payload);
+        triggerTranslation(event.payload, true);
+      }),
+    );
+
     // Listen for translation chunks (filter by session ID)
     globalUnlistenFns.push(
       await listen<ChunkPayload>("translate-chunk", (event) => {
@@ -166,6 +204,39 @@ function App() {
       }),
     );
 
+    // Retried translation replaces what was streamed (filter by session ID)
+    globalUnlistenFns.push(
+      await listen<ChunkPayload>("translate-replace", (event) => {
+        if (event.payload.session_id === currentSessionId()) {
+          setTranslated(event.payload.text);
+        }
+      }),
+    );
+
+    // Output validation warnings (filter by session ID)
+    globalUnlistenFns.push(
+      await listen<FlaggedPayload>("translate-flagged", (event) => {
+        if (event.payload.session_id === currentSessionId()) {
+          Logger.warn(
+            "ipc",
+            "translation flagged",
+            { issues: event.payload.issues },
+            event.payload.session_id,
+          );
+          setFlagged(event.payload.issues);
+        }
+      }),
+    );
+
+    // Similar earlier translation from the translation memory (filter by session ID)
+    globalUnlistenFns.push(
+      await listen<MemoryMatchPayload>("translate-memory-match", (event) => {
+        if (event.payload.session_id === currentSessionId()) {
+          setMemoryMatch(event.payload);
+        }
+      }),
+    );
+
     // Listen for translation completion (filter by session ID)
     globalUnlistenFns.push(
       await listen<DonePayload>("translate-done", (event) => {
@@ -252,12 +323,30 @@ function App() {
     <Show
       when={view() === "main"}
       fallback={
-        <Settings
-          onClose={() => {
-            setView("main");
-            loadSettings(); // Reload in case model changed
-          }}
-        />
+        <Show
+          when={view() === "history"}
+          fallback={
+            <Settings
+              onClose={() => {
+                setView("main");
+                loadSettings(); // Reload in case model changed
+              }}
+            />
+          }
+        >
+          <History
+            onClose={() => setView("main")}
+            onOpen={(source, translation) => {
+              setOriginal(source);
+              setTranslated(translation);
+              setUsage(null);
+              setError(null);
+              setFlagged([]);
+              setMemoryMatch(null);
+              setView("main");
+            }}
+          />
+        </Show>
       }
     >
       <div class="flex flex-col h-screen bg-gradient-subtle text-[var(--text-primary)]">
@@ -310,6 +399,7 @@ function App() {
                   <ErrorDisplay
                     error={error() as TranslateError}
                     onRetry={handleRetry}
+                    onConfirm={handleSendAnyway}
                     onOpenSettings={() => setView("settings")}
                     context={{ model: currentModel() }}
                   />
@@ -326,6 +416,30 @@ function App() {
                           Translation will appear here...
                         </span>
                       )}
+                      <Show when={flagged().length > 0}>
+                        <p class="mt-3 text-xs text-[var(--accent-secondary)]">
+                          {getFlagMessage(flagged())}
+                        </p>
+                      </Show>
+                      <Show when={memoryMatch()}>
+                        {(match) => (
+                          <div class="mt-3 p-2 rounded-md border border-[var(--border-primary)] text-xs text-[var(--text-secondary)]">
+                            <div class="flex items-center justify-between mb-1">
+                              <span class="text-[var(--accent-secondary)]">
+                                {match().similarity}% match
+                              </span>
+                              <button
+                                type="button"
+                                onClick={() => writeText(match().translation)}
+                                class="text-[var(--text-muted)] hover:text-[var(--text-primary)] transition-theme"
+                              >
+                                Copy
+                              </button>
+                            </div>
+                            <p class="whitespace-pre-wrap">{match().translation}</p>
+                          </div>
+                        )}
+                      </Show>
                     </div>
                   }
                 >
@@ -353,6 +467,25 @@ function App() {
             >
               <SettingsIcon size={16} />
             </button>
+            <button
+              type="button"
+              onClick={() => setView("history")}
+              class="text-[var(--text-muted)] hover:text-[var(--accent-secondary)] transition-theme"
+              title="History"
+            >
+              <HistoryIcon size={16} />
+            </button>
+            <Show when={incognito()}>
+              <button
+                type="button"
+                onClick={() => invoke("set_incognito", { enabled: false })}
+                class="flex items-center gap-1 text-[var(--accent-secondary)] transition-theme"
+                title="Not recording history. Click to turn off Incognito."
+              >
+                <EyeOff size={14} />
+                Incognito
+              </button>
+            </Show>
             <Show when={usage()}>
               <Show
                 when={usage()?.cached}
@@ -0,0 +1,102 @@
+import { invoke } from "@tauri-apps/api/core";
+import { Trash2 } from "lucide-solid";
+import { createResource, createSignal, For, Show } from "solid-js";
+import type { CachePage, CacheStats } from "../types/cache";
+import { Logger } from "../utils/logger";
+
+const PAGE_SIZE = 20;
+
+export function CacheBrowser() {
+  const [query, setQuery] = createSignal("");
+  const [offset, setOffset] = createSignal(0);
+  const [stats, { refetch: refetchStats }] = createResource<CacheStats>(() =>
+    invoke("get_cache_stats"),
+  );
+  const [page, { refetch: refetchPage }] = createResource(
+    () => ({ query: query(), offset: offset() }),
+    (params) => invoke<CachePage>("list_cache_entries", { ...params, limit: PAGE_SIZE }),
+  );
+
+  const handleSearch = (value: string) => {
+    setQuery(value);
+    setOffset(0);
+  };
+
+  const handleDelete = async (id: string) => {
+    try {
+      await invoke("delete_cache_entry", { id });
+      await Promise.all([refetchPage(), refetchStats()]);
+    } catch (err) {
+      Logger.error("ipc", "Failed to delete cache entry", { error: String(err) });
+    }
+  };
+
+  const pageEnd = () => Math.min(offset() + PAGE_SIZE, page()?.total ?? 0);
+
+  return (
+    <div class="ml-7 mt-3">
+      <Show when={stats()}>
+        {(s) => (
+          <p class="text-xs text-[var(--text-muted)] mb-2">
+            {s().entry_count} entries · {Math.round(s().hit_rate * 100)}% hit rate · about $
+            {s().estimated_savings.toFixed(2)} saved
+          </p>
+        )}
+      </Show>
+      <input
+        type="search"
+        placeholder="Search cached translations"
+        value={query()}
+        onInput={(e) => handleSearch(e.currentTarget.value)}
+        class="w-full px-2 py-1 mb-2 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] text-xs focus:outline-none focus:border-[var(--accent-primary)] transition-theme"
+      />
+      <ul class="max-h-64 overflow-y-auto">
+        <For each={page()?.entries}>
+          {(entry) => (
+            <li class="flex items-start gap-2 py-1.5 border-b border-[var(--border-primary)]">
+              <div class="flex-1 min-w-0 text-xs">
+                <p class="text-[var(--text-muted)] truncate">{entry.preview}</p>
+                <p class="text-[var(--text-primary)] truncate">{entry.value}</p>
+                <p class="text-[var(--text-muted)]">
+                  {entry.model} · {new Date(entry.timestamp * 1000).toLocaleDateString()}
+                  {entry.namespace === "dictionary" ? " · dictionary" : ""}
+                </p>
+              </div>
+              <button
+                type="button"
+                onClick={() => handleDelete(entry.id)}
+                title="Delete this entry"
+                class="p-1 text-[var(--text-muted)] hover:text-[var(--error)] transition-theme"
+              >
+                <Trash2 size={14} />
+              </button>
+            </li>
+          )}
+        </For>
+      </ul>
+      <Show when={(page()?.total ?? 0) > PAGE_SIZE}>
+        <div class="flex items-center justify-between mt-2 text-xs text-[var(--text-secondary)]">
+          <button
+            type="button"
+            disabled={offset() === 0}
+            onClick={() => setOffset(Math.max(0, offset() - PAGE_SIZE))}
+            class="disabled:opacity-50"
+          >
+            Previous
+          </button>
+          <span>
+            {offset() + 1}–{pageEnd()} of {page()?.total}
+          </span>
+          <button
+            type="button"
+            disabled={pageEnd() >= (page()?.total ?? 0)}
+            onClick={() => setOffset(offset() + PAGE_SIZE)}
+            class="disabled:opacity-50"
+          >
+            Next
+          </button>
+        </div>
+      </Show>
+    </div>
+  );
+}
@@ -7,6 +7,7 @@ import {
   getRetryDelay,
   getUserMessage,
   isRetryable,
+  needsConfirmation,
   needsSettings,
 } from "../types/error";
 
@@ -14,6 +15,8 @@ interface ErrorDisplayProps {
   error: TranslateError;
   onRetry: () => void;
   onOpenSettings: () => void;
+  /** Resend with explicit confirmation (credential-like text) */
+  onConfirm?: () => void;
   context?: ErrorReportContext;
 }
 
@@ -75,6 +78,16 @@ export function ErrorDisplay(props: ErrorDisplayProps) {
           </button>
         </Show>
 
+        <Show when={needsConfirmation(props.error) && props.onConfirm}>
+          <button
+            type="button"
+            onClick={() => props.onConfirm?.()}
+            class="px-4 py-2 bg-[var(--bg-elevated)] hover:bg-[var(--border-secondary)] text-white rounded-md transition-theme text-sm"
+          >
+            Send Anyway
+          </button>
+        </Show>
+
         <Show when={isRetryable(props.error)}>
           <button
             type="button"
@@ -0,0 +1,204 @@
+import { invoke } from "@tauri-apps/api/core";
+import { writeText } from "@tauri-apps/plugin-clipboard-manager";
+import { Copy, Star, Trash2, X } from "lucide-solid";
+import { createResource, createSignal, For, Show } from "solid-js";
+import type { ExportFormat, HistoryPage } from "../types/history";
+import { Logger } from "../utils/logger";
+
+const PAGE_SIZE = 20;
+
+interface HistoryProps {
+  onClose: () => void;
+  // Show an entry again in the main window
+  onOpen: (source: string, translation: string) => void;
+}
+
+export function History(props: HistoryProps) {
+  const [query, setQuery] = createSignal("");
+  const [starredOnly, setStarredOnly] = createSignal(false);
+  const [offset, setOffset] = createSignal(0);
+  const [exportFormat, setExportFormat] = createSignal<ExportFormat>("csv");
+  const [message, setMessage] = createSignal<string | null>(null);
+  const [page, { refetch }] = createResource(
+    () => ({ query: query(), starredOnly: starredOnly(), offset: offset() }),
+    (params) => invoke<HistoryPage>("search_history", { ...params, limit: PAGE_SIZE }),
+  );
+
+  const handleSearch = (value: string) => {
+    setQuery(value);
+    setOffset(0);
+  };
+
+  const handleStar = async (id: string, starred: boolean) => {
+    try {
+      await invoke("set_history_starred", { id, starred });
+      await refetch();
+    } catch (err) {
+      Logger.error("ipc", "Failed to star history entry", { error: String(err) });
+    }
+  };
+
+  const handleDelete = async (id: string) => {
+    try {
+      await invoke("delete_history_entry", { id });
+      await refetch();
+    } catch (err) {
+      Logger.error("ipc", "Failed to delete history entry", { error: String(err) });
+    }
+  };
+
+  // Asks for the file in a save dialog (null: cancelled)
+  const handleExport = async () => {
+    try {
+      const count = await invoke<number | null>("export_history", { format: exportFormat() });
+      if (count !== null) {
+        setMessage(`Exported ${count} entries`);
+        setTimeout(() => setMessage(null), 3000);
+      }
+    } catch (err) {
+      Logger.error("ipc", "Failed to export history", { error: String(err) });
+      setMessage(String(err));
+    }
+  };
+
+  const pageEnd = () => Math.min(offset() + PAGE_SIZE, page()?.total ?? 0);
+
+  return (
+    <div class="flex flex-col h-screen bg-gradient-subtle text-[var(--text-primary)]">
+      <div class="flex items-center justify-between p-3 border-b border-[var(--border-primary)]">
+        <h2 class="text-sm font-medium text-[var(--accent-secondary)]">History</h2>
+        <button
+          type="button"
+          onClick={props.onClose}
+          class="text-[var(--text-muted)] hover:text-[var(--text-primary)] transition-theme"
+          title="Close"
+        >
+          <X size={20} />
+        </button>
+      </div>
+
+      <div class="flex items-center gap-2 p-3 border-b border-[var(--border-primary)]">
+        <input
+          type="search"
+          placeholder="Search source and translation"
+          value={query()}
+          onInput={(e) => handleSearch(e.currentTarget.value)}
+          class="flex-1 px-2 py-1 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] text-xs focus:outline-none focus:border-[var(--accent-primary)] transition-theme"
+        />
+        <label class="flex items-center gap-1 text-xs text-[var(--text-secondary)] cursor-pointer">
+          <input
+            type="checkbox"
+            checked={starredOnly()}
+            onChange={(e) => {
+              setStarredOnly(e.currentTarget.checked);
+              setOffset(0);
+            }}
+          />
+          Starred
+        </label>
+        <select
+          value={exportFormat()}
+          onChange={(e) => setExportFormat(e.currentTarget.value as ExportFormat)}
+          class="px-2 py-1 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] text-xs"
+        >
+          <option value="csv">CSV</option>
+          <option value="json">JSON</option>
+          <option value="markdown">Markdown</option>
+        </select>
+        <button
+          type="button"
+          onClick={handleExport}
+          class="px-3 py-1 text-xs bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded hover:bg-[var(--bg-tertiary)] transition-theme"
+        >
+          Export
+        </button>
+      </div>
+      <Show when={message()}>
+        <p class="px-3 pt-2 text-xs text-[var(--text-muted)]">{message()}</p>
+      </Show>
+
+      <ul class="flex-1 overflow-y-auto px-3">
+        <For
+          each={page()?.entries}
+          fallback={
+            <p class="py-6 text-center text-xs text-[var(--text-muted)]">
+              No translations yet. Turn on history in Settings to keep them.
+            </p>
+          }
+        >
+          {(entry) => (
+            <li class="flex items-start gap-2 py-2 border-b border-[var(--border-primary)]">
+              <button
+                type="button"
+                onClick={() => props.onOpen(entry.source, entry.translation)}
+                class="flex-1 min-w-0 text-left text-xs"
+                title="Show in the main window"
+              >
+                <p class="text-[var(--text-muted)] truncate">{entry.source}</p>
+                <p class="text-[var(--text-primary)] truncate">{entry.translation}</p>
+                <p class="text-[var(--text-muted)]">
+                  {new Date(entry.timestamp * 1000).toLocaleString()} · {entry.model} ·{" "}
+                  {entry.window === "popup" ? "popup" : "window"}
+                  {entry.mode !== "translation" ? ` · ${entry.mode}` : ""}
+                  {entry.cost !== null ? ` · $${entry.cost.toFixed(6)}` : ""}
+                </p>
+              </button>
+              <button
+                type="button"
+                onClick={() => handleStar(entry.id, !entry.starred)}
+                title={entry.starred ? "Unstar" : "Star (kept when the history is pruned)"}
+                class="p-1 text-[var(--text-muted)] hover:text-[var(--accent-secondary)] transition-theme"
+              >
+                <Star
+                  size={14}
+                  fill={entry.starred ? "currentColor" : "none"}
+                  class={entry.starred ? "text-[var(--accent-secondary)]" : ""}
+                />
+              </button>
+              <button
+                type="button"
+                onClick={() => writeText(entry.translation)}
+                title="Copy translation"
+                class="p-1 text-[var(--text-muted)] hover:text-[var(--text-primary)] transition-theme"
+              >
+                <Copy size={14} />
+              </button>
+              <button
+                type="button"
+                onClick={() => handleDelete(entry.id)}
+                title="Delete this entry"
+                class="p-1 text-[var(--text-muted)] hover:text-[var(--error)] transition-theme"
+              >
+                <Trash2 size={14} />
+              </button>
+            </li>
+          )}
+        </For>
+      </ul>
+
+      <Show when={(page()?.total ?? 0) > PAGE_SIZE}>
+        <div class="flex items-center justify-between px-4 py-2 border-t border-[var(--border-primary)] text-xs text-[var(--text-secondary)]">
+          <button
+            type="button"
+            disabled={offset() === 0}
+            onClick={() => setOffset(Math.max(0, offset() - PAGE_SIZE))}
+            class="disabled:opacity-50"
+          >
+            Previous
+          </button>
+          <span>
+            {offset() + 1}–{pageEnd()} of {page()?.total}
+          </span>
+          <button
+            type="button"
+            disabled={pageEnd() >= (page()?.total ?? 0)}
+            onClick={() => setOffset(offset() + PAGE_SIZE)}
+            class="disabled:opacity-50"
+          >
+            Next
+          </button>
+        </div>
+      </Show>
+    </div>
+  );
+}
@@ -3,9 +3,12 @@ import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import { getCurrentWindow, PhysicalSize } from "@tauri-apps/api/window";
 import { writeText } from "@tauri-apps/plugin-clipboard-manager";
 import { Check, Copy, X } from "lucide-solid";
-import { createEffect, createSignal, onCleanup, onMount, Show } from "solid-js";
+import { createEffect, createSignal, For, onCleanup, onMount, Show } from "solid-js";
+import type { DictionaryEntry } from "../types/dictionary";
 import type { TranslateError } from "../types/error";
-import { getUserMessage, parseError } from "../types/error";
+import { getUserMessage, needsConfirmation, parseError } from "../types/error";
+import type { FlaggedPayload, OutputIssue } from "../types/validation";
+import { getFlagMessage } from "../types/validation";
 import { formatText } from "../utils/formatText";
 import { Logger } from "../utils/logger";
 
@@ -19,9 +22,16 @@ export function PopupView() {
   const [isLoading, setIsLoading] = createSignal(true);
   const [error, setError] = createSignal<TranslateError | null>(null);
   const [copied, setCopied] = createSignal(false);
+  const [flagged, setFlagged] = createSignal<OutputIssue[]>([]);
+  // Structured result of a word lookup (the text is its senses joined, for copying)
+  const [entry, setEntry] = createSignal<DictionaryEntry | null>(null);
   let contentRef: HTMLDivElement | undefined;
   let autoCloseTimer: ReturnType<typeof setTimeout> | undefined;
   let unlistenPopupShown: UnlistenFn | undefined;
+  let unlistenFlagged: UnlistenFn | undefined;
+  let unlistenDictionary: UnlistenFn | undefined;
+  // Text of the last run, for "Send Anyway"
+  let lastText: string | null = null;
 
   const closePopup = async () => {
     await invoke("close_popup");
@@ -52,9 +62,12 @@ export function PopupView() {
     }
   };
 
-  const runTranslation = async (clipboardText: string | null) => {
+  const runTranslation = async (clipboardText: string | null, allowSensitive = false) => {
+    lastText = clipboardText;
     setText("");
     setError(null);
+    setFlagged([]);
+    setEntry(null);
     setIsLoading(true);
 
     const correlationId = crypto.randomUUID();
@@ -69,6 +82,7 @@ export function PopupView() {
       if (clipboardText?.trim()) {
         const result = await invoke<string>("quick_translate", {
           text: clipboardText,
+          allowSensitive,
         });
         Logger.info("ipc", "quick_translate done", { resultLength: result.length }, correlationId);
         setText(result);
@@ -84,6 +98,12 @@ export function PopupView() {
     }
   };
 
+  // User confirmed sending text that looks like it contains credentials
+  const handleConfirm = () => {
+    Logger.info("ui", "sensitive content confirmed");
+    runTranslation(lastText, true);
+  };
+
   // Resize window based on content
   const resizeToContent = async () => {
     if (!contentRef) return;
@@ -100,11 +120,15 @@ export function PopupView() {
     // Track reactive dependencies
     const currentText = text();
     const currentError = error();
+    const currentEntry = entry();
     const loading = isLoading();
     // Resize after DOM update
     setTimeout(resizeToContent, 10);
     // Start auto-close timer when translation completes
-    if (!loading && (currentText || currentError)) {
+    // (not while "Send Anyway" waits for the user)
+    if (currentError && needsConfirmation(currentError)) {
+      clearTimeout(autoCloseTimer);
+    } else if (!loading && (currentText || currentError || currentEntry)) {
       resetAutoCloseTimer();
     }
   });
@@ -122,6 +146,19 @@ export function PopupView() {
       Logger.info("ui", "popup shown (⌃⌥J)", { hasClipboard: !!event.payload });
       runTranslation(event.payload);
     });
+
+    // Output validation warnings for the popup translation (no session ID;
+    // main window translations carry theirs)
+    unlistenFlagged = await listen<FlaggedPayload>("translate-flagged", (event) => {
+      if (event.payload.session_id === null) {
+        setFlagged(event.payload.issues);
+      }
+    });
+
+    // Emitted by quick_translate before it returns, for single words and short phrases
+    unlistenDictionary = await listen<DictionaryEntry>("dictionary-result", (event) => {
+      setEntry(event.payload);
+    });
   });
 
   onCleanup(() => {
@@ -132,6 +169,12 @@ export function PopupView() {
     if (unlistenPopupShown) {
       unlistenPopupShown();
     }
+    if (unlistenFlagged) {
+      unlistenFlagged();
+    }
+    if (unlistenDictionary) {
+      unlistenDictionary();
+    }
   });
 
   return (
@@ -178,12 +221,73 @@ export function PopupView() {
           <Show
             when={!error()}
             fallback={
-              <p class="text-[var(--error)]">{getUserMessage(error() as TranslateError)}</p>
+              <div>
+                <p class="text-[var(--error)]">{getUserMessage(error() as TranslateError)}</p>
+                <Show when={needsConfirmation(error() as TranslateError)}>
+                  <button
+                    type="button"
+                    onClick={handleConfirm}
+                    class="mt-2 px-3 py-1 bg-[var(--bg-elevated)] hover:bg-[var(--border-secondary)] text-white rounded-md transition-theme text-xs"
+                  >
+                    Send Anyway
+                  </button>
+                </Show>
+              </div>
             }
           >
-            <p class="leading-relaxed whitespace-pre-wrap wrap-break-word animate-fade-in">
-              {formatText(text())}
-            </p>
+            <Show
+              when={entry()}
+              fallback={
+                <p class="leading-relaxed whitespace-pre-wrap wrap-break-word animate-fade-in">
+                  {formatText(text())}
+                </p>
+              }
+            >
+              {(e) => (
+                <div class="animate-fade-in">
+                  <p>
+                    <span class="font-medium">{e().headword}</span>
+                    <Show when={e().reading}>
+                      <span class="ml-2 text-[var(--text-secondary)]">{e().reading}</span>
+                    </Show>
+                    <Show when={e().part_of_speech}>
+                      <span class="ml-2 text-xs text-[var(--text-muted)]">
+                        {e().part_of_speech}
+                      </span>
+                    </Show>
+                  </p>
+                  <Show when={e().inflection}>
+                    <p class="text-xs text-[var(--text-muted)]">{e().inflection}</p>
+                  </Show>
+                  <ol class="mt-1 list-decimal list-inside">
+                    <For each={e().senses}>
+                      {(sense) => (
+                        <li>
+                          {sense.gloss}
+                          <Show when={sense.note}>
+                            <span class="ml-1 text-xs text-[var(--text-muted)]">
+                              ({sense.note})
+                            </span>
+                          </Show>
+                        </li>
+                      )}
+                    </For>
+                  </ol>
+                  <For each={e().examples}>
+                    {(example) => (
+                      <p class="mt-1 text-xs text-[var(--text-secondary)]">
+                        {example.source} — {example.translation}
+                      </p>
+                    )}
+                  </For>
+                </div>
+              )}
+            </Show>
+            <Show when={flagged().length > 0}>
+              <p class="mt-2 text-xs text-[var(--accent-secondary)]">
+                {getFlagMessage(flagged())}
+              </p>
+            </Show>
           </Show>
         </Show>
       </div>
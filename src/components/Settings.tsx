@@ -1,7 +1,19 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import { X } from "lucide-solid";
-import { createEffect, createResource, createSignal, For, Show } from "solid-js";
+import {
+  createEffect,
+  createResource,
+  createSignal,
+  For,
+  onCleanup,
+  onMount,
+  Show,
+} from "solid-js";
 import { setTelemetryEnabled } from "../index";
+import { CacheBrowser } from "./CacheBrowser";
+import { getUserMessage, parseError } from "../types/error";
+import type { MemoryMode } from "../types/memory";
 import { Logger } from "../utils/logger";
 
 interface SettingsData {
@@ -9,6 +21,41 @@ interface SettingsData {
   model: string;
   send_telemetry?: boolean;
   cache_enabled?: boolean;
+  cache_capacity?: number;
+  cache_ttl_days?: number;
+  // Model id → entries kept (0: never cache)
+  cache_model_limits?: Record<string, number>;
+  privacy_mode?: boolean;
+  redaction_patterns?: string[];
+  output_validation?: OutputValidation;
+  translation_memory?: MemoryMode;
+  history_enabled?: boolean;
+  history_limit?: number;
+  // 0: no age limit
+  history_retention_days?: number;
+  api_server_enabled?: boolean;
+  api_server_port?: number;
+  // Generated by the backend; changed only via regenerate_api_server_token
+  api_server_token?: string;
+}
+
+// Matches Rust OutputValidation enum (src-tauri/src/settings.rs)
+type OutputValidation = "off" | "flag" | "retry";
+
+// Matches Rust BatchJob (src-tauri/src/batch.rs), without the source texts
+interface BatchJob {
+  id: string;
+  created_at: number;
+  status: "in_progress" | "canceling" | "ended";
+  counts: { processing: number; succeeded: number; errored: number };
+  total: number;
+  result: { cached: number; flagged: number; failed: number; estimated_cost: number } | null;
+}
+
+interface BatchSubmission {
+  job: BatchJob | null;
+  already_cached: number;
+  skipped: { index: number; reason: string }[];
 }
 
 interface SettingsProps {
@@ -27,12 +74,43 @@ export function Settings(props: SettingsProps) {
   const [model, setModel] = createSignal("claude-haiku-4-5-20251001");
   const [sendTelemetry, setSendTelemetry] = createSignal(true);
   const [cacheEnabled, setCacheEnabled] = createSignal(true);
+  const [cacheCapacity, setCacheCapacity] = createSignal(5000);
+  const [cacheCapacityError, setCacheCapacityError] = createSignal<string | null>(null);
+  const [cacheTtlDays, setCacheTtlDays] = createSignal(30);
+  const [cacheModelLimits, setCacheModelLimits] = createSignal<Record<string, number>>({});
+  const [privacyMode, setPrivacyMode] = createSignal(false);
+  const [redactionPatterns, setRedactionPatterns] = createSignal("");
+  const [outputValidation, setOutputValidation] = createSignal<OutputValidation>("flag");
+  const [memoryMode, setMemoryMode] = createSignal<MemoryMode>("suggest");
+  const [historyEnabled, setHistoryEnabled] = createSignal(false);
+  const [historyLimit, setHistoryLimit] = createSignal(1000);
+  const [historyRetentionDays, setHistoryRetentionDays] = createSignal(90);
+  const [historyError, setHistoryError] = createSignal<string | null>(null);
+  const [historyCleared, setHistoryCleared] = createSignal(false);
+  const [apiServerEnabled, setApiServerEnabled] = createSignal(false);
+  const [apiServerPort, setApiServerPort] = createSignal(47811);
+  const [apiServerError, setApiServerError] = createSignal<string | null>(null);
+  const [tokenCopied, setTokenCopied] = createSignal(false);
   const [showKey, setShowKey] = createSignal(false);
   const [clearingCache, setClearingCache] = createSignal(false);
   const [cacheCleared, setCacheCleared] = createSignal(false);
+  const [cacheKeyReset, setCacheKeyReset] = createSignal(false);
   const [saved, setSaved] = createSignal(false);
   const [savingApiKey, setSavingApiKey] = createSignal(false);
   const [apiKeySaved, setApiKeySaved] = createSignal(false);
+  const [batchJobs, { refetch: refetchBatchJobs }] = createResource<BatchJob[]>(() =>
+    invoke("get_batch_jobs"),
+  );
+  const [batchText, setBatchText] = createSignal("");
+  const [submittingBatch, setSubmittingBatch] = createSignal(false);
+  const [batchMessage, setBatchMessage] = createSignal<string | null>(null);
+
+  // The backend polls batch jobs and emits each change
+  let unlistenBatch: UnlistenFn | undefined;
+  onMount(async () => {
+    unlistenBatch = await listen("batch-updated", () => refetchBatchJobs());
+  });
+  onCleanup(() => unlistenBatch?.());
 
   // Initialize form when settings load
   createEffect(() => {
@@ -41,6 +119,18 @@ export function Settings(props: SettingsProps) {
       setModel(s.model);
       setSendTelemetry(s.send_telemetry ?? true);
       setCacheEnabled(s.cache_enabled ?? true);
+      setCacheCapacity(s.cache_capacity ?? 5000);
+      setCacheTtlDays(s.cache_ttl_days ?? 30);
+      setCacheModelLimits(s.cache_model_limits ?? {});
+      setPrivacyMode(s.privacy_mode ?? false);
+      setRedactionPatterns((s.redaction_patterns ?? []).join("\n"));
+      setOutputValidation(s.output_validation ?? "flag");
+      setMemoryMode(s.translation_memory ?? "suggest");
+      setHistoryEnabled(s.history_enabled ?? false);
+      setHistoryLimit(s.history_limit ?? 1000);
+      setHistoryRetentionDays(s.history_retention_days ?? 90);
+      setApiServerEnabled(s.api_server_enabled ?? false);
+      setApiServerPort(s.api_server_port ?? 47811);
     }
   });
 
@@ -80,11 +170,25 @@ export function Settings(props: SettingsProps) {
       if (!currentSettings) return;
 
       const mergedSettings = {
+        ...currentSettings,
         model: newSettings.model ?? model(),
         send_telemetry: newSettings.send_telemetry ?? sendTelemetry(),
         cache_enabled: newSettings.cache_enabled ?? cacheEnabled(),
+        cache_capacity: newSettings.cache_capacity ?? cacheCapacity(),
+        cache_ttl_days: newSettings.cache_ttl_days ?? cacheTtlDays(),
+        cache_model_limits: newSettings.cache_model_limits ?? cacheModelLimits(),
+        privacy_mode: newSettings.privacy_mode ?? privacyMode(),
+        redaction_patterns: newSettings.redaction_patterns ?? parsePatterns(redactionPatterns()),
+        output_validation: newSettings.output_validation ?? outputValidation(),
+        translation_memory: newSettings.translation_memory ?? memoryMode(),
+        history_enabled: newSettings.history_enabled ?? historyEnabled(),
+        history_limit: newSettings.history_limit ?? historyLimit(),
+        history_retention_days: newSettings.history_retention_days ?? historyRetentionDays(),
+        api_server_enabled: newSettings.api_server_enabled ?? apiServerEnabled(),
+        api_server_port: newSettings.api_server_port ?? apiServerPort(),
       };
 
+      setApiServerError(null);
       await invoke("save_settings", { newSettings: mergedSettings });
 
       // Update frontend telemetry flag if changed
@@ -97,6 +201,11 @@ export function Settings(props: SettingsProps) {
       setTimeout(() => setSaved(false), 2000);
     } catch (err) {
       Logger.error("ipc", "Failed to save settings", { error: String(err) });
+      // Settings are saved even when the local API can't start (e.g. port in use)
+      if (newSettings.api_server_enabled !== undefined || newSettings.api_server_port) {
+        setApiServerError(String(err));
+        await refetch();
+      }
     }
   };
 
@@ -110,11 +219,133 @@ export function Settings(props: SettingsProps) {
     handleAutoSave({ cache_enabled: enabled });
   };
 
+  // Matches MIN_CAPACITY / MAX_CAPACITY in src-tauri/src/cache.rs
+  const handleCacheCapacitySave = () => {
+    const capacity = cacheCapacity();
+    if (Number.isInteger(capacity) && capacity >= 100 && capacity <= 100000) {
+      setCacheCapacityError(null);
+      handleAutoSave({ cache_capacity: capacity });
+    } else {
+      setCacheCapacityError("Cache size must be between 100 and 100,000 entries");
+    }
+  };
+
+  // Matches MAX_TTL_DAYS in src-tauri/src/cache.rs
+  const handleCacheTtlSave = () => {
+    const days = cacheTtlDays();
+    if (Number.isInteger(days) && days >= 1 && days <= 365) {
+      setCacheCapacityError(null);
+      handleAutoSave({ cache_ttl_days: days });
+    } else {
+      setCacheCapacityError("Entries must expire after 1 to 365 days");
+    }
+  };
+
+  // Empty: no limit of its own
+  const handleModelLimitSave = (modelId: string, value: string) => {
+    const limits = { ...cacheModelLimits() };
+    const limit = Number(value);
+    if (value.trim() === "") {
+      delete limits[modelId];
+    } else if (Number.isInteger(limit) && limit >= 0) {
+      limits[modelId] = limit;
+    } else {
+      setCacheCapacityError("Model limits must be whole numbers (0: don't cache)");
+      return;
+    }
+    setCacheCapacityError(null);
+    setCacheModelLimits(limits);
+    handleAutoSave({ cache_model_limits: limits });
+  };
+
   const handleTelemetryChange = (enabled: boolean) => {
     setSendTelemetry(enabled);
     handleAutoSave({ send_telemetry: enabled });
   };
 
+  const handleOutputValidationChange = (mode: OutputValidation) => {
+    setOutputValidation(mode);
+    handleAutoSave({ output_validation: mode });
+  };
+
+  const handleMemoryModeChange = (mode: MemoryMode) => {
+    setMemoryMode(mode);
+    handleAutoSave({ translation_memory: mode });
+  };
+
+  const handlePrivacyModeChange = (enabled: boolean) => {
+    setPrivacyMode(enabled);
+    handleAutoSave({ privacy_mode: enabled });
+  };
+
+  const handleHistoryChange = (enabled: boolean) => {
+    setHistoryEnabled(enabled);
+    handleAutoSave({ history_enabled: enabled });
+  };
+
+  // Matches MIN_LIMIT / MAX_LIMIT in src-tauri/src/history.rs
+  const handleHistoryLimitsSave = () => {
+    const limit = historyLimit();
+    const days = historyRetentionDays();
+    if (!Number.isInteger(limit) || limit < 10 || limit > 10000) {
+      setHistoryError("History must keep between 10 and 10,000 entries");
+    } else if (!Number.isInteger(days) || days < 0) {
+      setHistoryError("Days must be a whole number (0: no age limit)");
+    } else {
+      setHistoryError(null);
+      handleAutoSave({ history_limit: limit, history_retention_days: days });
+    }
+  };
+
+  const handleClearHistory = async () => {
+    try {
+      await invoke("clear_history");
+      setHistoryCleared(true);
+      setTimeout(() => setHistoryCleared(false), 2000);
+    } catch (err) {
+      Logger.error("ipc", "Failed to clear history", { error: String(err) });
+      setHistoryError(String(err));
+    }
+  };
+
+  const handleApiServerChange = (enabled: boolean) => {
+    setApiServerEnabled(enabled);
+    handleAutoSave({ api_server_enabled: enabled });
+  };
+
+  const handleApiServerPortSave = () => {
+    const port = apiServerPort();
+    if (Number.isInteger(port) && port >= 1024 && port <= 65535) {
+      handleAutoSave({ api_server_port: port });
+    } else {
+      setApiServerError("Port must be between 1024 and 65535");
+    }
+  };
+
+  const handleCopyToken = async () => {
+    const token = settings()?.api_server_token;
+    if (!token) return;
+    await navigator.clipboard.writeText(token);
+    setTokenCopied(true);
+    setTimeout(() => setTokenCopied(false), 2000);
+  };
+
+  const handleRegenerateToken = async () => {
+    try {
+      await invoke("regenerate_api_server_token");
+      await refetch();
+    } catch (err) {
+      Logger.error("ipc", "Failed to regenerate API token", { error: String(err) });
+    }
+  };
+
+  // One regex per line; blank lines are ignored
+  const parsePatterns = (value: string) =>
+    value
+      .split("\n")
+      .map((line) => line.trim())
+      .filter((line) => line.length > 0);
+
   const handleClearCache = async () => {
     setClearingCache(true);
     try {
@@ -129,6 +360,75 @@ export function Settings(props: SettingsProps) {
     }
   };
 
+  // New encryption key; everything sealed with the old one is wiped
+  const handleResetCacheKey = async () => {
+    setClearingCache(true);
+    try {
+      await invoke("reset_translation_cache_key");
+      setCacheKeyReset(true);
+      setTimeout(() => setCacheKeyReset(false), 2000);
+      Logger.info("ui", "Translation cache key reset");
+    } catch (err) {
+      Logger.error("ipc", "Failed to reset cache key", { error: String(err) });
+    } finally {
+      setClearingCache(false);
+    }
+  };
+
+  const handleSubmitBatch = async () => {
+    const texts = batchText()
+      .split("\n")
+      .filter((line) => line.trim().length > 0);
+    if (texts.length === 0) return;
+    setSubmittingBatch(true);
+    setBatchMessage(null);
+    try {
+      const submission = await invoke<BatchSubmission>("submit_batch", { texts });
+      const parts = [
+        submission.job ? `${submission.job.total} submitted` : "Nothing to submit",
+        submission.already_cached > 0 ? `${submission.already_cached} already cached` : null,
+        ...submission.skipped.map((s) => `line ${s.index + 1} skipped: ${s.reason}`),
+      ];
+      setBatchMessage(parts.filter(Boolean).join("; "));
+      if (submission.job) setBatchText("");
+      await refetchBatchJobs();
+    } catch (err) {
+      Logger.error("ipc", "Failed to submit batch", { error: String(err) });
+      setBatchMessage(getUserMessage(parseError(err)));
+    } finally {
+      setSubmittingBatch(false);
+    }
+  };
+
+  const handleCancelBatch = async (id: string) => {
+    try {
+      await invoke("cancel_batch", { id });
+      await refetchBatchJobs();
+    } catch (err) {
+      Logger.error("ipc", "Failed to cancel batch", { error: String(err) });
+    }
+  };
+
+  const handleClearFinishedBatches = async () => {
+    try {
+      await invoke("clear_finished_batches");
+      await refetchBatchJobs();
+    } catch (err) {
+      Logger.error("ipc", "Failed to clear batch jobs", { error: String(err) });
+    }
+  };
+
+  const batchJobStatus = (job: BatchJob) => {
+    if (job.result) {
+      const { cached, flagged, failed, estimated_cost } = job.result;
+      const cost = `$${estimated_cost.toFixed(4)}`;
+      return `${cached} cached, ${flagged} flagged, ${failed} failed (${cost})`;
+    }
+    if (job.status === "canceling") return "Canceling…";
+    const done = job.counts.succeeded + job.counts.errored;
+    return `${done}/${job.total} processed`;
+  };
+
   // Check if API key has unsaved changes
   const apiKeyChanged = () => {
     const currentStoredKey = storedApiKey() ?? "";
@@ -229,6 +529,56 @@ export function Settings(props: SettingsProps) {
             </select>
           </div>
 
+          {/* Output Validation */}
+          <div class="mb-6">
+            <label
+              for="output-validation-select"
+              class="block text-sm font-medium text-[var(--text-secondary)] mb-2"
+            >
+              Suspicious translations
+            </label>
+            <select
+              id="output-validation-select"
+              value={outputValidation()}
+              onChange={(e) =>
+                handleOutputValidationChange(e.currentTarget.value as OutputValidation)
+              }
+              class="w-full px-3 py-2 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] focus:outline-none focus:border-[var(--accent-primary)] transition-theme text-sm"
+            >
+              <option value="flag">Show a warning</option>
+              <option value="retry">Retry once automatically</option>
+              <option value="off">Don't check</option>
+            </select>
+            <p class="mt-2 text-xs text-[var(--text-muted)]">
+              Catches added notes, untranslated text and answered instructions. Flagged
+              translations are never cached.
+            </p>
+          </div>
+
+          {/* Translation memory */}
+          <div class="mb-6">
+            <label
+              for="translation-memory-select"
+              class="block text-sm font-medium text-[var(--text-secondary)] mb-2"
+            >
+              Similar earlier translations
+            </label>
+            <select
+              id="translation-memory-select"
+              value={memoryMode()}
+              onChange={(e) => handleMemoryModeChange(e.currentTarget.value as MemoryMode)}
+              class="w-full px-3 py-2 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] focus:outline-none focus:border-[var(--accent-primary)] transition-theme text-sm"
+            >
+              <option value="suggest">Show as a suggestion</option>
+              <option value="reference">Also send to Claude for consistency</option>
+              <option value="off">Don't remember</option>
+            </select>
+            <p class="mt-2 text-xs text-[var(--text-muted)]">
+              Finds translations of text at least 75% similar (e.g. a paragraph with one typo
+              fixed). Encrypted with the cache key; cleared with the translation cache.
+            </p>
+          </div>
+
           {/* Cache Settings */}
           <div class="mb-6">
             <h3 class="text-sm font-medium text-[var(--text-secondary)] mb-3">Translation Cache</h3>
@@ -245,9 +595,60 @@ export function Settings(props: SettingsProps) {
             </label>
             <p class="text-xs text-[var(--text-muted)] ml-7 mb-3">
               Caches translations to avoid repeated API calls. Disable for privacy.
-              <br />
-              Cache entries expire after 30 days.
             </p>
+            <div class="ml-7 mb-3 flex items-center gap-2">
+              <label for="cache-capacity" class="text-xs text-[var(--text-secondary)]">
+                Keep up to
+              </label>
+              <input
+                id="cache-capacity"
+                type="number"
+                value={cacheCapacity()}
+                onInput={(e) => setCacheCapacity(e.currentTarget.valueAsNumber)}
+                onBlur={handleCacheCapacitySave}
+                class="w-24 px-2 py-1 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] text-xs focus:outline-none focus:border-[var(--accent-primary)] transition-theme"
+              />
+              <span class="text-xs text-[var(--text-secondary)]">translations for</span>
+              <input
+                id="cache-ttl"
+                type="number"
+                value={cacheTtlDays()}
+                onInput={(e) => setCacheTtlDays(e.currentTarget.valueAsNumber)}
+                onBlur={handleCacheTtlSave}
+                class="w-16 px-2 py-1 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] text-xs focus:outline-none focus:border-[var(--accent-primary)] transition-theme"
+              />
+              <label for="cache-ttl" class="text-xs text-[var(--text-secondary)]">
+                days
+              </label>
+            </div>
+            <details class="ml-7 mb-3">
+              <summary class="text-xs text-[var(--text-secondary)] cursor-pointer">
+                Limits per model
+              </summary>
+              <For each={models()}>
+                {([id, name]) => (
+                  <div class="flex items-center gap-2 mt-2">
+                    <label
+                      for={`cache-limit-${id}`}
+                      class="flex-1 text-xs text-[var(--text-muted)]"
+                    >
+                      {name}
+                    </label>
+                    <input
+                      id={`cache-limit-${id}`}
+                      type="number"
+                      placeholder="No limit"
+                      value={cacheModelLimits()[id] ?? ""}
+                      onChange={(e) => handleModelLimitSave(id, e.currentTarget.value)}
+                      class="w-24 px-2 py-1 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] text-xs focus:outline-none focus:border-[var(--accent-primary)] transition-theme"
+                    />
+                  </div>
+                )}
+              </For>
+            </details>
+            <Show when={cacheCapacityError()}>
+              <p class="ml-7 mb-3 text-xs text-[var(--error)]">{cacheCapacityError()}</p>
+            </Show>
             <button
               type="button"
               onClick={handleClearCache}
@@ -256,6 +657,19 @@ export function Settings(props: SettingsProps) {
             >
               {clearingCache() ? "Clearing..." : cacheCleared() ? "Cleared!" : "Clear all cache"}
             </button>
+            <button
+              type="button"
+              onClick={handleResetCacheKey}
+              disabled={clearingCache()}
+              title="Encrypt with a new key and wipe the cache, translation memory and queued batch texts"
+              class="ml-2 px-3 py-1.5 text-xs bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded hover:bg-[var(--bg-tertiary)] transition-theme disabled:opacity-50"
+            >
+              {cacheKeyReset() ? "New key set!" : "New key & wipe"}
+            </button>
+            {/* Remounted (and reloaded) after a clear */}
+            <Show when={!clearingCache()}>
+              <CacheBrowser />
+            </Show>
           </div>
 
           {/* Error Reporting */}
@@ -286,6 +700,201 @@ export function Settings(props: SettingsProps) {
                 Privacy Policy
               </button>
             </p>
+            <label class="flex items-center gap-3 cursor-pointer mt-4">
+              <input
+                type="checkbox"
+                checked={privacyMode()}
+                onChange={(e) => handlePrivacyModeChange(e.currentTarget.checked)}
+                class="w-4 h-4 rounded border-[var(--border-primary)] bg-[var(--bg-secondary)] text-[var(--accent-primary)] focus:ring-[var(--accent-primary)] focus:ring-offset-0"
+              />
+              <span class="text-sm text-[var(--text-secondary)]">
+                Redact personal data before sending (privacy mode)
+              </span>
+            </label>
+            <p class="mt-2 text-xs text-[var(--text-muted)] ml-7">
+              Emails, phone numbers, IBANs and long numbers are replaced with placeholders like
+              [EMAIL_1] and restored locally. Redacted translations are not cached.
+            </p>
+            <Show when={privacyMode()}>
+              <textarea
+                value={redactionPatterns()}
+                onInput={(e) => setRedactionPatterns(e.currentTarget.value)}
+                onBlur={() => handleAutoSave({})}
+                placeholder="Custom patterns (one regex per line)"
+                rows={3}
+                class="mt-2 ml-7 w-[calc(100%-1.75rem)] px-3 py-2 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] text-xs font-mono focus:outline-none focus:ring-2 focus:ring-[var(--accent-primary)]"
+              />
+            </Show>
+          </div>
+
+          {/* History */}
+          <div class="mb-6">
+            <h3 class="text-sm font-medium text-[var(--text-secondary)] mb-3">History</h3>
+            <label class="flex items-center gap-3 cursor-pointer">
+              <input
+                type="checkbox"
+                checked={historyEnabled()}
+                onChange={(e) => handleHistoryChange(e.currentTarget.checked)}
+                class="w-4 h-4 rounded border-[var(--border-primary)] bg-[var(--bg-secondary)] text-[var(--accent-primary)] focus:ring-[var(--accent-primary)] focus:ring-offset-0"
+              />
+              <span class="text-sm text-[var(--text-secondary)]">
+                Keep a history of my translations
+              </span>
+            </label>
+            <p class="mt-2 text-xs text-[var(--text-muted)] ml-7">
+              Source and translation are stored unencrypted on this Mac. Nothing is recorded while
+              Incognito is checked in the menu bar, for text that looks like a credential, or for
+              text privacy mode redacts.
+            </p>
+            <div class="flex items-center gap-2 ml-7 mt-3">
+              <label for="history-limit" class="text-xs text-[var(--text-secondary)]">
+                Keep
+              </label>
+              <input
+                id="history-limit"
+                type="number"
+                value={historyLimit()}
+                onInput={(e) => setHistoryLimit(e.currentTarget.valueAsNumber)}
+                onBlur={handleHistoryLimitsSave}
+                class="w-24 px-2 py-1 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] text-xs focus:outline-none focus:border-[var(--accent-primary)] transition-theme"
+              />
+              <span class="text-xs text-[var(--text-secondary)]">entries for</span>
+              <input
+                id="history-days"
+                type="number"
+                value={historyRetentionDays()}
+                onInput={(e) => setHistoryRetentionDays(e.currentTarget.valueAsNumber)}
+                onBlur={handleHistoryLimitsSave}
+                class="w-16 px-2 py-1 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] text-xs focus:outline-none focus:border-[var(--accent-primary)] transition-theme"
+              />
+              <label for="history-days" class="text-xs text-[var(--text-secondary)]">
+                days (starred entries are kept)
+              </label>
+            </div>
+            <Show when={historyError()}>
+              <p class="mt-2 ml-7 text-xs text-[var(--error)]">{historyError()}</p>
+            </Show>
+            <button
+              type="button"
+              onClick={handleClearHistory}
+              class="ml-7 mt-3 px-3 py-1.5 text-xs bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded hover:bg-[var(--bg-tertiary)] transition-theme"
+            >
+              {historyCleared() ? "Cleared!" : "Clear history"}
+            </button>
+          </div>
+
+          {/* Local API */}
+          <div class="mb-6">
+            <h3 class="text-sm font-medium text-[var(--text-secondary)] mb-3">Local API</h3>
+            <label class="flex items-center gap-3 cursor-pointer">
+              <input
+                type="checkbox"
+                checked={apiServerEnabled()}
+                onChange={(e) => handleApiServerChange(e.currentTarget.checked)}
+                class="w-4 h-4 rounded border-[var(--border-primary)] bg-[var(--bg-secondary)] text-[var(--accent-primary)] focus:ring-[var(--accent-primary)] focus:ring-offset-0"
+              />
+              <span class="text-sm text-[var(--text-secondary)]">
+                Let local tools translate via http://127.0.0.1
+              </span>
+            </label>
+            <p class="mt-2 text-xs text-[var(--text-muted)] ml-7">
+              Requests need the token below and use your API key. Only this Mac can connect.
+            </p>
+            <Show when={apiServerEnabled()}>
+              <div class="mt-3 ml-7 flex items-center gap-2">
+                <label for="api-server-port" class="text-xs text-[var(--text-secondary)]">
+                  Port
+                </label>
+                <input
+                  id="api-server-port"
+                  type="number"
+                  value={apiServerPort()}
+                  onInput={(e) => setApiServerPort(e.currentTarget.valueAsNumber)}
+                  onBlur={handleApiServerPortSave}
+                  class="w-24 px-2 py-1 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] text-xs focus:outline-none focus:border-[var(--accent-primary)] transition-theme"
+                />
+              </div>
+              <div class="mt-2 ml-7 flex items-center gap-2">
+                <code class="flex-1 truncate px-2 py-1 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-xs font-mono text-[var(--text-secondary)]">
+                  {settings()?.api_server_token}
+                </code>
+                <button
+                  type="button"
+                  onClick={handleCopyToken}
+                  class="px-2 py-1 text-xs bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded hover:bg-[var(--bg-tertiary)] transition-theme"
+                >
+                  {tokenCopied() ? "Copied!" : "Copy"}
+                </button>
+                <button
+                  type="button"
+                  onClick={handleRegenerateToken}
+                  class="px-2 py-1 text-xs bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded hover:bg-[var(--bg-tertiary)] transition-theme"
+                >
+                  Regenerate
+                </button>
+              </div>
+            </Show>
+            <Show when={apiServerError()}>
+              <p class="mt-2 ml-7 text-xs text-[var(--error)]">{apiServerError()}</p>
+            </Show>
+          </div>
+
+          {/* Batch translation */}
+          <div class="mb-6">
+            <h3 class="text-sm font-medium text-[var(--text-secondary)] mb-3">
+              Batch translation
+            </h3>
+            <textarea
+              value={batchText()}
+              onInput={(e) => setBatchText(e.currentTarget.value)}
+              rows={4}
+              placeholder="One text per line"
+              class="w-full px-3 py-2 bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded-md text-[var(--text-primary)] text-xs focus:outline-none focus:ring-2 focus:ring-[var(--accent-primary)]"
+            />
+            <p class="mt-2 text-xs text-[var(--text-muted)]">
+              Half price via the Message Batches API. Results arrive within 24 hours and go to the
+              translation cache. Queued texts are kept encrypted with the cache key until then.
+            </p>
+            <div class="mt-2 flex items-center gap-2">
+              <button
+                type="button"
+                onClick={handleSubmitBatch}
+                disabled={submittingBatch() || batchText().trim().length === 0}
+                class="px-3 py-1.5 text-xs bg-[var(--bg-secondary)] border border-[var(--border-primary)] rounded hover:bg-[var(--bg-tertiary)] disabled:opacity-50 transition-theme"
+              >
+                {submittingBatch() ? "Submitting..." : "Submit batch"}
+              </button>
+              <Show when={batchJobs()?.some((job) => job.result)}>
+                <button
+                  type="button"
+                  onClick={handleClearFinishedBatches}
+                  class="px-3 py-1.5 text-xs text-[var(--text-muted)] hover:text-[var(--text-primary)] transition-theme"
+                >
+                  Clear finished
+                </button>
+              </Show>
+            </div>
+            <Show when={batchMessage()}>
+              <p class="mt-2 text-xs text-[var(--text-secondary)]">{batchMessage()}</p>
+            </Show>
+            <For each={batchJobs()}>
+              {(job) => (
+                <div class="mt-2 flex items-center justify-between gap-2 text-xs text-[var(--text-secondary)]">
+                  <span>
+                    {new Date(job.created_at * 1000).toLocaleString()} · {batchJobStatus(job)}
+                  </span>
+                  <Show when={!job.result && job.status === "in_progress"}>
+                    <button
+                      type="button"
+                      onClick={() => handleCancelBatch(job.id)}
+                      class="text-[var(--text-muted)] hover:text-[var(--error)] transition-theme"
+                    >
+                      Cancel
+                    </button>
+                  </Show>
+                </div>
+              )}
+            </For>
           </div>
 
           {/* Security Note */}
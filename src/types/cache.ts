@@ -0,0 +1,27 @@
+// Matches Rust CacheStats (src-tauri/src/cache.rs)
+export interface CacheStats {
+  entry_count: number;
+  hits: number;
+  misses: number;
+  // 0–1
+  hit_rate: number;
+  // USD
+  estimated_savings: number;
+}
+
+// Matches Rust CacheEntry (src-tauri/src/cache.rs)
+export interface CacheEntry {
+  id: string;
+  namespace: "translation" | "dictionary";
+  model: string;
+  // Start of the source text, truncated and masked
+  preview: string;
+  value: string;
+  timestamp: number;
+}
+
+// Matches Rust CachePage (src-tauri/src/cache.rs)
+export interface CachePage {
+  entries: CacheEntry[];
+  total: number;
+}
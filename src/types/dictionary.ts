@@ -0,0 +1,11 @@
+// Matches Rust DictionaryEntry (src-tauri/src/dictionary.rs)
+export interface DictionaryEntry {
+  headword: string;
+  // Kana reading (Japanese) or pronunciation (English)
+  reading: string | null;
+  part_of_speech: string | null;
+  senses: { gloss: string; note: string | null }[];
+  examples: { source: string; translation: string }[];
+  // Deinflection chain for conjugated selections (e.g. "negative < past")
+  inflection?: string;
+}
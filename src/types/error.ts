@@ -9,7 +9,10 @@ export type TranslateError =
   | { type: "ApiError"; data: { status: number; message: string } }
   | { type: "ParseError"; data: { message: string } }
   | { type: "Unknown"; data: { message: string } }
-  | { type: "IncompleteResponse" };
+  | { type: "IncompleteResponse" }
+  | { type: "PlaceholderMismatch"; data: { missing: string[]; duplicated: string[] } }
+  | { type: "SensitiveContentBlocked"; data: { categories: string[] } }
+  | { type: "PrivateContentBlocked"; data: { categories: string[] } };
 
 /**
  * Parse error from backend - can be JSON or plain string
@@ -57,6 +60,12 @@ export function getUserMessage(error: TranslateError): string {
       return error.data.message || "An unknown error occurred.";
     case "IncompleteResponse":
       return "Translation was interrupted. The response may be incomplete. Please try again.";
+    case "PlaceholderMismatch":
+      return `Translation changed placeholders (${error.data.missing.length} missing, ${error.data.duplicated.length} duplicated). Please try again.`;
+    case "SensitiveContentBlocked":
+      return `This text looks like it contains credentials (${error.data.categories.join(", ")}). It was not sent for translation.`;
+    case "PrivateContentBlocked":
+      return `Privacy mode is on and this text contains personal data (${error.data.categories.join(", ")}). It was not sent.`;
   }
 }
 
@@ -70,9 +79,17 @@ export function isRetryable(error: TranslateError): boolean {
     "Timeout",
     "NetworkError",
     "IncompleteResponse",
+    "PlaceholderMismatch",
   ].includes(error.type);
 }
 
+/**
+ * Check if the user can explicitly confirm sending the text anyway
+ */
+export function needsConfirmation(error: TranslateError): boolean {
+  return error.type === "SensitiveContentBlocked";
+}
+
 /**
  * Check if error requires settings (API key issue)
  */
@@ -122,6 +139,16 @@ function sanitizeErrorData(error: TranslateError): Record<string, unknown> | nul
       return { retry_after_secs: data.retry_after_secs };
     case "Timeout":
       return { timeout_secs: data.timeout_secs };
+    case "PlaceholderMismatch":
+      // Counts only: placeholders can be URLs or emails
+      return {
+        missing: (data.missing as string[]).length,
+        duplicated: (data.duplicated as string[]).length,
+      };
+    case "SensitiveContentBlocked":
+    case "PrivateContentBlocked":
+      // Category names only, never the matched values
+      return { categories: data.categories };
     case "NetworkError":
     case "ParseError":
     case "AuthenticationFailed":
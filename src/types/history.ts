@@ -0,0 +1,23 @@
+// Matches Rust HistoryEntry (src-tauri/src/history.rs)
+export interface HistoryEntry {
+  id: string;
+  timestamp: number;
+  source: string;
+  translation: string;
+  // Model id, "offline" for offline dictionary hits
+  model: string;
+  mode: "translation" | "markdown" | "dictionary";
+  // USD (0 for cache hits, null when unknown)
+  cost: number | null;
+  window: "main" | "popup";
+  starred: boolean;
+}
+
+// Matches Rust HistoryPage (src-tauri/src/history.rs)
+export interface HistoryPage {
+  entries: HistoryEntry[];
+  total: number;
+}
+
+// Matches Rust ExportFormat (src-tauri/src/history.rs)
+export type ExportFormat = "csv" | "json" | "markdown";
@@ -0,0 +1,11 @@
+// Matches Rust MemoryMatchPayload (src-tauri/src/engine.rs)
+export interface MemoryMatchPayload {
+  session_id: string | null;
+  source: string;
+  translation: string;
+  // Percent, 75–100
+  similarity: number;
+}
+
+// Matches Rust MemoryMode enum (src-tauri/src/settings.rs)
+export type MemoryMode = "off" | "suggest" | "reference";
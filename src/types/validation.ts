@@ -0,0 +1,28 @@
+// Matches Rust OutputIssue enum (src-tauri/src/validation.rs)
+export type OutputIssue =
+  | "echo"
+  | "wrong_language"
+  | "leaked_tag"
+  | "meta_commentary"
+  | "instruction_answer";
+
+export interface FlaggedPayload {
+  session_id: string | null;
+  issues: OutputIssue[];
+}
+
+const ISSUE_LABELS: Record<OutputIssue, string> = {
+  echo: "the text was not translated",
+  wrong_language: "the output is in the wrong language",
+  leaked_tag: "internal tags leaked into the output",
+  meta_commentary: "notes or commentary were added",
+  instruction_answer: "instructions in the text were answered instead of translated",
+};
+
+/**
+ * Warning shown under a translation that failed output validation
+ */
+export function getFlagMessage(issues: OutputIssue[]): string {
+  const reasons = issues.map((issue) => ISSUE_LABELS[issue]).join("; ");
+  return `Check this translation: ${reasons}.`;
+}